@@ -1,16 +1,17 @@
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::fmt::{Display, Formatter, Write};
-use std::io::Error as IOErr;
+use std::io::{Read, Error as IOErr};
 
 use crate::{read_to_string_buf};
-use crate::nmf::NmfInfo;
+use crate::nmf::{NmfInfo, NmfBufFull};
 use crate::ini::{self,
                  BuildingIni,
                  RenderIni,
                  MaterialMtl,
                  RenderToken as RT,
                  MaterialToken as MT,
+                 GameRoots,
                  common::IdStringParam,
                  };
 
@@ -46,13 +47,16 @@ pub enum BuildingError {
 
 
 impl ModBuildingDef {
-    fn from_render_ini(
+    fn from_render_ini<R, M>(
         building_ini: &Path, 
         render: &Path,
         render_root: &Path, 
         render_ini: RenderIni, 
-        render_path_resolver: fn(&Path, &IdStringParam) -> PathBuf,
-        mtl_path_resolver:    fn(&Path, &IdStringParam) -> PathBuf) -> Result<Self, BuildingError> 
+        roots: &GameRoots,
+        render_path_resolver: R,
+        mtl_path_resolver:    M) -> Result<Self, BuildingError> 
+    where R: Fn(&Path, &IdStringParam) -> PathBuf,
+          M: Fn(&Path, &IdStringParam) -> PathBuf
     {
         macro_rules! get_render_value {
             ($p:pat, $s:ident) => {{
@@ -76,9 +80,9 @@ impl ModBuildingDef {
         let material_e = get_render_value!(RT::MaterialEmissive(s), s);
 
         let mut textures = Vec::with_capacity(10);
-        push_textures(&material, &mut textures, mtl_path_resolver)?;
+        push_textures(&material, &mut textures, roots, &mtl_path_resolver)?;
         if let Some(ref material_e) = material_e {
-            push_textures(material_e, &mut textures, mtl_path_resolver)?;
+            push_textures(material_e, &mut textures, roots, &mtl_path_resolver)?;
         }
 
         Ok(ModBuildingDef {
@@ -96,9 +100,11 @@ impl ModBuildingDef {
     }
 
 
-    // Does not re-parse renderconfig!
-    pub fn parse_and_validate(&self, nmf_override: Option<&NmfInfo>) -> Result<(), BuildingError> {
+    // Model and material paths are taken from self; renderconfig is only re-read for LOD distances.
+    // On success returns the list of warnings (problems that do not break the building)
+    pub fn parse_and_validate(&self, nmf_override: Option<&NmfInfo>) -> Result<Vec<String>, BuildingError> {
         let mut errors = Vec::<String>::with_capacity(0);
+        let mut warnings = Vec::<String>::with_capacity(0);
 
         macro_rules! check_path {
             ($name:expr, $path:expr) => { 
@@ -127,11 +133,62 @@ impl ModBuildingDef {
         check_popt!("MATERIALEMISSIVE", &self.material_e);
         for tx in self.textures.iter() {
             check_path!("texture", tx);
+            // the game loads only DDS; a png or tga with the right name still exists but will not render
+            if tx.exists() && !has_dds_magic(tx) {
+                errors.push(format!("texture ({}) is not a DDS file", tx.display()));
+            }
+        }
+
+        // the emissive model is drawn with the emissive material: one without the other is likely a renderconfig mistake
+        match (&self.model_e, &self.material_e) {
+            (Some(_), None) => warnings.push(format!("{}: MODELEMISSIVE is set, but MATERIALEMISSIVE is missing", self.render.display())),
+            (None, Some(_)) => warnings.push(format!("{}: MATERIALEMISSIVE is set, but MODELEMISSIVE is missing", self.render.display())),
+            _ => ()
         }
 
+        if let (Some(model_e), Some(material_e)) = (&self.model_e, &self.material_e) {
+            push_emissive_pair_warnings(model_e, material_e, &mut warnings);
+        }
+
+        match fs::read_to_string(&self.render) {
+            Ok(render_buf) => match ini::parse_renderconfig_ini(&render_buf) {
+                Ok(render_ini) => validate_lod_distances(&render_ini, &mut errors),
+                Err(e) => errors.push(format!("Cannot parse file {}: {}", self.render.display(), concat_parse_errors(e)))
+            },
+            Err(e) => errors.push(format!("Cannot read file {}: {:#?}", self.render.display(), e))
+        };
+
+        let mut bld_buf = String::with_capacity(0);
+        let building_ini = match read_to_string_buf(&self.building_ini, &mut bld_buf) {
+            Ok(()) => match ini::parse_building_ini(&bld_buf) {
+                Ok(building_ini) => {
+                    push_connection_warnings(&building_ini, &mut warnings);
+                    push_name_warnings(&building_ini, &mut warnings);
+                    push_storage_warnings(&building_ini, &mut warnings);
+                    push_point_pair_warnings(&building_ini, &mut warnings);
+                    Some(building_ini)
+                },
+                Err(e) => {
+                    errors.push(format!("Cannot parse file {}: {:#?}", self.building_ini.display(), e));
+                    None
+                }
+            },
+            Err(e) => {
+                errors.push(format!("Cannot read file {}: {:#?}", self.building_ini.display(), e));
+                None
+            }
+        };
+
         match NmfInfo::from_path(&self.model) {
             Ok(model) => {
                 let model = nmf_override.unwrap_or(&model);
+                for o in model.empty_objects() {
+                    warnings.push(format!("{}: object '{}' has no geometry", self.model.display(), o.name.as_str()));
+                }
+                if model.remainder() > 0 {
+                    warnings.push(format!("{}: {} leftover bytes after the last object", self.model.display(), model.remainder()));
+                }
+
                 let mut str_buf = String::with_capacity(0);
                 macro_rules! push_errors {
                     ($ini_path:expr, $parser:expr, $model_data:expr, $pusher:ident, $pfx:expr) => {
@@ -139,7 +196,7 @@ impl ModBuildingDef {
                         match read_res {
                             Ok(()) => match $parser(&str_buf) {
                                 Ok(ini) => {
-                                    $pusher(&ini, $model_data, &mut errors, $pfx)
+                                    $pusher(&ini, $model_data, &mut errors, &mut warnings, $pfx)
                                 },
                                 Err(e) => errors.push(format!("Cannot parse file {}: {:#?}", $ini_path.display(), e))
                             },
@@ -148,12 +205,14 @@ impl ModBuildingDef {
                     };
                 }
 
-                push_errors!(&self.building_ini, ini::parse_building_ini, &model,        push_buildingini_errors, "building.ini");
+                if let Some(building_ini) = &building_ini {
+                    push_buildingini_errors(building_ini, model, &mut errors, "building.ini");
+                }
 
                 let sm_usage = model.get_used_sumbaterials().collect::<Vec<_>>();
-                push_errors!(&self.material,     ini::parse_mtl,          sm_usage.iter(), push_mtl_errors,         "primary material");
+                push_errors!(&self.material, ini::parse_mtl, sm_usage.iter(), push_mtl_errors, "primary material");
                 if let Some(material_e) = &self.material_e {
-                    push_errors!(&material_e,    ini::parse_mtl,          sm_usage.iter(), push_mtl_errors,         "emissive material");
+                    push_errors!(&material_e, ini::parse_mtl, sm_usage.iter(), push_mtl_errors, "emissive material");
                 }
             },
             Err(e) => { 
@@ -163,20 +222,24 @@ impl ModBuildingDef {
 
 
         if errors.is_empty() {
-            Ok(())
+            Ok(warnings)
         } else {
             Err(BuildingError::Validation(errors))
         }
     }
 
 
-    pub fn from_render_path(building_ini: &Path, renderconfig: &Path, path_resolver: fn(&Path, &IdStringParam) -> PathBuf, validate: bool) -> Result<Self, BuildingError> {
-        let render_root = renderconfig.parent().expect(&format!("Cannot get render root from {}", renderconfig.display()));
+    // path_resolver: resolves model and material tokens of renderconfig and texture tokens of mtl files
+    // against the directory of the file; roots are used for stock texture tokens
+    pub fn from_render_path<F>(building_ini: &Path, renderconfig: &Path, roots: &GameRoots, path_resolver: F, validate: bool) -> Result<Self, BuildingError>
+    where F: Fn(&Path, &IdStringParam) -> PathBuf
+    {
+        let render_root = renderconfig.parent().unwrap_or_else(|| panic!("Cannot get render root from {}", renderconfig.display()));
 
         let render_buf = fs::read_to_string(renderconfig).map_err(|e| BuildingError::FileIO(renderconfig.to_path_buf(), e.to_string()))?;
         let render_ini = ini::parse_renderconfig_ini(&render_buf).map_err(|e| BuildingError::Parse(renderconfig.to_path_buf(), concat_parse_errors(e)))?;
 
-        let mut result = Self::from_render_ini(building_ini, renderconfig, render_root, render_ini, path_resolver, path_resolver)?;
+        let mut result = Self::from_render_ini(building_ini, renderconfig, render_root, render_ini, roots, &path_resolver, &path_resolver)?;
 
         result.image_gui = {
             let img_path = render_root.join("imagegui.png");
@@ -188,13 +251,132 @@ impl ModBuildingDef {
         };
 
         if validate {
-            result.parse_and_validate(None)?;
+            for w in result.parse_and_validate(None)? {
+                eprintln!("WARNING: {}", w);
+            }
         }
 
         Ok(result)
     }
 
 
+    // Printable building_ini_node_usage of building.ini against the main model
+    pub fn node_refs_report(&self) -> Result<Vec<String>, BuildingError> {
+        let mut buf = String::with_capacity(0);
+        read_to_string_buf(&self.building_ini, &mut buf).map_err(|e| BuildingError::FileIO(self.building_ini.clone(), e.to_string()))?;
+        let building_ini = ini::parse_building_ini(&buf).map_err(|e| BuildingError::Parse(self.building_ini.clone(), concat_parse_errors(e)))?;
+        let model = NmfInfo::from_path(&self.model).map_err(|e| BuildingError::Parse(self.model.clone(), e.to_string()))?;
+        let obj_names: Vec<_> = model.object_names().collect();
+
+        let (refs, unreferenced) = building_ini_node_usage(building_ini.get_model_refs(), &obj_names[..]);
+        let mut lines = refs.iter().map(|(r, matched)| {
+            let (kind, name) = match r {
+                ini::BuildingNodeRef::Exact(node)  => ("node", node),
+                ini::BuildingNodeRef::Keyword(key) => ("keyword", key),
+            };
+            format!("{} '{}' -> {}", kind, name, if matched.is_empty() { String::from("(no objects)") } else { matched.join(", ") })
+        }).collect::<Vec<_>>();
+
+        if unreferenced.is_empty() {
+            lines.push(String::from("all objects are referenced"));
+        } else {
+            lines.push(format!("not referenced by building.ini: {}", unreferenced.join(", ")));
+        }
+
+        Ok(lines)
+    }
+
+    // Every file of the building: configs, models, materials, textures, imagegui
+    pub fn all_asset_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        use std::iter::once;
+
+        once(&self.render)
+            .chain(once(&self.building_ini))
+            .chain(self.image_gui.iter())
+            .chain(once(&self.model))
+            .chain(self.model_lod.iter())
+            .chain(self.model_lod2.iter())
+            .chain(self.model_e.iter())
+            .chain(once(&self.material))
+            .chain(self.material_e.iter())
+            .chain(self.textures.iter())
+    }
+
+
+    // Applies mechanical fixes to the files inside the building directory (stock and workshop files are never touched):
+    //   - NMF: out-of-sync object / face bboxes, bytes past the declared file length or after the last object
+    //   - ini and mtl files: mixed line endings (normalized to CRLF)
+    // Every changed file is first copied to <name>.bak. Returns descriptions of the changes made.
+    pub fn fix(&self) -> Result<Vec<String>, BuildingError> {
+        let root = self.render.parent().unwrap();
+        let mut changes = Vec::<String>::with_capacity(0);
+
+        let backup = |path: &Path| -> Result<(), BuildingError> {
+            let mut bak = path.as_os_str().to_os_string();
+            bak.push(".bak");
+            fs::copy(path, bak).map(|_| ()).map_err(|e| BuildingError::FileIO(path.to_path_buf(), e.to_string()))
+        };
+
+        let models = std::iter::once(&self.model).chain(self.model_lod.iter()).chain(self.model_lod2.iter()).chain(self.model_e.iter());
+        let mut seen = Vec::<&PathBuf>::with_capacity(4);
+        for path in models.filter(|p| p.starts_with(root)) {
+            if seen.contains(&path) {
+                continue;
+            }
+            seen.push(path);
+
+            // Extra bytes appended past the length declared in the header are dropped
+            let bytes = fs::read(path).map_err(|e| BuildingError::FileIO(path.to_path_buf(), e.to_string()))?;
+            let declared = bytes.get(16 .. 20).map(|s| u32::from_le_bytes([s[0], s[1], s[2], s[3]]) as usize).unwrap_or(bytes.len());
+            let data = if declared < bytes.len() { &bytes[.. declared] } else { &bytes[..] };
+
+            let mut nmf = NmfBufFull::from_bytes(data).map_err(|e| BuildingError::Parse(path.to_path_buf(), e.to_string()))?;
+            let mut fixed = Vec::<String>::with_capacity(0);
+            if data.len() < bytes.len() {
+                fixed.push(format!("{}: removed {} bytes past the file length declared in the header", path.display(), bytes.len() - data.len()));
+            }
+
+            for o in nmf.objects.iter_mut() {
+                if o.recalc_bboxes() {
+                    fixed.push(format!("{}: recalculated bounding boxes of object '{}'", path.display(), o.name()));
+                }
+            }
+
+            if nmf.remainder() > 0 {
+                fixed.push(format!("{}: removed {} leftover bytes after the last object", path.display(), nmf.remainder()));
+            }
+
+            if !fixed.is_empty() {
+                backup(path)?;
+                nmf.write_to_file(path).map_err(|e| BuildingError::FileIO(path.to_path_buf(), e.to_string()))?;
+                changes.append(&mut fixed);
+            }
+        }
+
+        let texts = [&self.render, &self.building_ini, &self.material];
+        for path in texts.iter().copied().chain(self.material_e.iter()).filter(|p| p.starts_with(root)) {
+            let buf = fs::read(path).map_err(|e| BuildingError::FileIO(path.to_path_buf(), e.to_string()))?;
+            let crlf = buf.windows(2).filter(|w| w == b"\r\n").count();
+            let lf = buf.iter().filter(|b| **b == b'\n').count() - crlf;
+            if crlf > 0 && lf > 0 {
+                let mut fixed = Vec::<u8>::with_capacity(buf.len() + lf);
+                for (i, b) in buf.iter().enumerate() {
+                    if *b == b'\n' && (i == 0 || buf[i - 1] != b'\r') {
+                        fixed.push(b'\r');
+                    }
+                    fixed.push(*b);
+                }
+
+                backup(path)?;
+                fs::write(path, fixed).map_err(|e| BuildingError::FileIO(path.to_path_buf(), e.to_string()))?;
+                changes.push(format!("{}: normalized {} LF line endings to CRLF", path.display(), lf));
+            }
+        }
+
+        Ok(changes)
+    }
+
+
     pub fn shallow_copy_to(&self, target_dir: &Path) -> Result<Self, IOErr> {
         let source_root = self.render.parent().unwrap();
         
@@ -254,7 +436,11 @@ where REFS: Iterator<Item = ini::BuildingNodeRef<'a>>,
     for r in ini_refs {
         match r {
             ini::BuildingNodeRef::Exact(node) => if object_names.iter().all(|obj| obj.as_ref() != node) {
-                errors.push(format!("building.ini contains invalid reference to node '{}'. No object in the NMF has such name", node));
+                let mut msg = format!("building.ini contains invalid reference to node '{}'. No object in the NMF has such name", node);
+                if let Some(similar) = closest_name(node, object_names) {
+                    write!(msg, "; did you mean '{}'?", similar).unwrap();
+                }
+                errors.push(msg);
             },
             ini::BuildingNodeRef::Keyword(key) => if object_names.iter().all(|obj| !obj.as_ref().starts_with(key)) {
                 errors.push(format!("building.ini contains invalid node-keyword '{}'. No object in the NMF starts with that key", key));
@@ -270,6 +456,162 @@ where REFS: Iterator<Item = ini::BuildingNodeRef<'a>>,
 }
 
 
+// What building.ini refers to in the NMF: each reference with the objects it matches (the exact node,
+// or every object starting with the keyword), and the objects no reference matches.
+// Unreferenced objects are not an error: they are still drawn, but are not used for construction costs.
+pub fn building_ini_node_usage<'a, 'n, REFS, N>(ini_refs: REFS, object_names: &'n [N]) -> (Vec<(ini::BuildingNodeRef<'a>, Vec<&'n str>)>, Vec<&'n str>)
+where REFS: Iterator<Item = ini::BuildingNodeRef<'a>>,
+      N: AsRef<str>,
+{
+    let refs = ini_refs.map(|r| {
+        let matched = object_names.iter().map(|obj| obj.as_ref()).filter(|obj| match &r {
+            ini::BuildingNodeRef::Exact(node)  => obj == node,
+            ini::BuildingNodeRef::Keyword(key) => obj.starts_with(key),
+        }).collect::<Vec<_>>();
+        (r, matched)
+    }).collect::<Vec<_>>();
+
+    let unreferenced = object_names.iter().map(|obj| obj.as_ref())
+        .filter(|obj| refs.iter().all(|(_, matched)| !matched.contains(obj)))
+        .collect();
+
+    (refs, unreferenced)
+}
+
+
+// $NAME (localization id) and $NAME_STR (literal): the building should have exactly one of them
+fn push_name_warnings(building_ini: &BuildingIni, warnings: &mut Vec<String>) {
+    let has_id  = building_ini.tokens().any(|t| matches!(t, ini::BuildingToken::Name(_)));
+    let has_str = building_ini.tokens().any(|t| matches!(t, ini::BuildingToken::NameStr(_)));
+
+    match (has_id, has_str) {
+        (false, false) => warnings.push(String::from("building.ini: building is unnamed (neither $NAME nor $NAME_STR is set)")),
+        (true, true)   => warnings.push(String::from("building.ini: both $NAME and $NAME_STR are set (ambiguous name)")),
+        _ => ()
+    }
+}
+
+
+// Negative capacities, and resources that cannot be carried by the storage's cargo type.
+// Only combinations that are certainly wrong are reported: the full resource-to-cargo table is not known.
+fn push_storage_warnings(building_ini: &BuildingIni, warnings: &mut Vec<String>) {
+    use ini::BuildingToken as BT;
+    use ini::building::{StorageCargoType as SC, ResourceType as RT};
+
+    for t in building_ini.tokens() {
+        let (cargo, capacity, resource) = match t {
+            BT::Storage((c, x)) | BT::StorageFuel((c, x)) | BT::StorageExport((c, x)) | BT::StorageImport((c, x)) |
+            BT::StorageImportCarplant((c, x)) | BT::StorageDemandBasic((c, x)) | BT::StorageDemandMediumAdvanced((c, x)) |
+            BT::StorageDemandAdvanced((c, x)) | BT::StorageDemandHotel((c, x)) => (c, *x, None),
+            BT::StorageSpecial((c, x, r)) | BT::StorageExportSpecial((c, x, r)) | BT::StorageImportSpecial((c, x, r)) => (c, *x, Some(r)),
+            _ => continue
+        };
+
+        if !(capacity >= 0.0 && capacity.is_finite()) {
+            warnings.push(format!("building.ini: storage capacity must be a non-negative number ({})", t));
+        }
+
+        if let Some(resource) = resource {
+            let mismatch = match (cargo, resource) {
+                (_, RT::Electricity | RT::Heat) => true,
+                (SC::Passanger, r) => !matches!(r, RT::Workers),
+                (_, RT::Workers) => true,
+                (SC::Nuclear1 | SC::Nuclear2, r) => !matches!(r, RT::NuclearFuel | RT::NuclearWaste),
+                (_, RT::NuclearFuel | RT::NuclearWaste) => true,
+                _ => false
+            };
+
+            if mismatch {
+                warnings.push(format!("building.ini: resource cannot be stored with this cargo type ({})", t));
+            }
+        }
+    }
+}
+
+
+// Steepest plausible slope (rise over horizontal run) between the two points of a connection
+const RAIL_MAX_GRADE: f32 = 0.1;
+const ROAD_MAX_GRADE: f32 = 0.3;
+
+// Soft rule: a rail or road connection much steeper than the game allows is most likely a typo in a Y coordinate
+fn push_connection_warnings(building_ini: &BuildingIni, warnings: &mut Vec<String>) {
+    use ini::building::Connection2PType as C2P;
+
+    for t in building_ini.tokens() {
+        if let ini::BuildingToken::Connection2Points((c_type, p1, p2)) = t {
+            let (kind, max_grade) = match c_type {
+                C2P::Rail | C2P::RailAllowpass | C2P::RailBorder => ("rail", RAIL_MAX_GRADE),
+                C2P::Road | C2P::RoadAllowpass | C2P::RoadBorder | C2P::RoadIn | C2P::RoadOut => ("road", ROAD_MAX_GRADE),
+                _ => continue
+            };
+
+            let rise = (p2.y - p1.y).abs();
+            let run = (p2.x - p1.x).hypot(p2.z - p1.z);
+            if rise > max_grade * run {
+                warnings.push(format!("building.ini: {} connection {} - {} rises {} over a horizontal distance of {}, check the Y coordinates",
+                                      kind, p1, p2, rise, run));
+            }
+        }
+    }
+}
+
+
+// Two-point tokens that have collapsed: a station or parking whose ends are the same point,
+// or a caption / worker area whose corners differ in fewer than two coordinates (a line or a point, no area).
+// Point order is not checked: the game accepts the corners either way.
+fn push_point_pair_warnings(building_ini: &BuildingIni, warnings: &mut Vec<String>) {
+    use ini::BuildingToken as BT;
+
+    for t in building_ini.tokens() {
+        let (keyword, is_area, p1, p2) = match t {
+            BT::VehicleStation((a, b))         => ("VEHICLE_STATION",          false, a, b),
+            BT::VehicleParking((a, b))         => ("VEHICLE_PARKING",          false, a, b),
+            BT::VehicleParkingPersonal((a, b)) => ("VEHICLE_PARKING_PERSONAL", false, a, b),
+            BT::HeliportStation((a, b))        => ("HELIPORT_STATION",         false, a, b),
+            BT::ShipStation((a, b))            => ("SHIP_STATION",             false, a, b),
+            BT::TextCaption((a, b))            => ("TEXT_CAPTION",             true,  a, b),
+            BT::WorkerRenderingArea((a, b))    => ("WORKER_RENDERING_AREA",    true,  a, b),
+            _ => continue
+        };
+
+        let differing = [p1.x != p2.x, p1.y != p2.y, p1.z != p2.z].iter().filter(|d| **d).count();
+        if differing == 0 {
+            warnings.push(format!("building.ini: {} has both points at {}", keyword, p1));
+        } else if is_area && differing < 2 {
+            warnings.push(format!("building.ini: {} {} - {} has zero area", keyword, p1, p2));
+        }
+    }
+}
+
+
+// Most similar object name, if it is close enough to be a typo (at most a third of the name is different)
+fn closest_name<'n, N: AsRef<str>>(node: &str, object_names: &'n [N]) -> Option<&'n str> {
+    object_names.iter()
+        .map(|obj| (obj.as_ref(), edit_distance(node, obj.as_ref())))
+        .filter(|(obj, d)| *d <= node.chars().count().max(obj.chars().count()) / 3)
+        .min_by_key(|(_, d)| *d)
+        .map(|(obj, _)| obj)
+}
+
+
+// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0 ..= b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let subst = diag + if ca == *cb { 0 } else { 1 };
+            diag = row[j + 1];
+            row[j + 1] = subst.min(row[j] + 1).min(diag + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+
 fn push_buildingini_errors(building_ini: &BuildingIni, model: &NmfInfo, errors: &mut Vec<String>, _pfx: &str) {
     let obj_names: Vec<_> = model.object_names().collect();
     if let Err(mut e) = validate_building_ini_refs(building_ini.get_model_refs(), &obj_names[..]) {
@@ -300,23 +642,114 @@ where REF:  AsRef<str>,
     }
 }
 
-pub fn push_mtl_errors<P: Display, SM, SMS>(mtl: &MaterialMtl, used_submaterials: SMS, errors: &mut Vec<String>, pfx: P)
+pub fn push_mtl_errors<P: Display, SM, SMS>(mtl: &MaterialMtl, used_submaterials: SMS, errors: &mut Vec<String>, warnings: &mut Vec<String>, pfx: P)
 where SM:  AsRef<str>,
       SMS: Iterator<Item = SM>
 {
-    // For now there is only 1 hard rule:
+    // Hard rules:
     // "all submaterials that are used by objects in NMF must have a token in mtl file"
+    // "texture slot indices must be unique within a (sub)material"
     // other checks could be added later
 
 
-    let mtl_tokens = mtl.tokens().filter_map(|t| match t {
-        MT::Submaterial(mtl_sm) => Some(mtl_sm),
-        _ => None
-    }).collect::<Vec<_>>();
+    let mut mtl_errors = match validate_mtl_refs(&mtl.submaterial_names()[..], used_submaterials) {
+        Ok(()) => Vec::with_capacity(0),
+        Err(e) => e
+    };
+
+    let mut slot_warnings = Vec::with_capacity(0);
+    validate_mtl_texture_slots(mtl, &mut mtl_errors, &mut slot_warnings);
+    warnings.extend(slot_warnings.into_iter().map(|w| format!("{}: {}", pfx, w)));
 
-    if let Err(mut e) = validate_mtl_refs(&mtl_tokens[..], used_submaterials) {
+    if !mtl_errors.is_empty() {
         errors.push(format!("Errors in {}", pfx));
-        errors.append(&mut e);
+        errors.append(&mut mtl_errors);
+    }
+}
+
+
+// The emissive model is drawn with the emissive material, so every submaterial it uses needs a token there.
+// Missing files are reported by the path checks, a broken mtl by the material validation.
+fn push_emissive_pair_warnings(model_e: &Path, material_e: &Path, warnings: &mut Vec<String>) {
+    let model = match NmfInfo::from_path(model_e) {
+        Ok(model) => model,
+        Err(e) => {
+            if model_e.exists() {
+                warnings.push(format!("{}: cannot load emissive model: {}", model_e.display(), e));
+            }
+            return;
+        }
+    };
+
+    let mtl_buf = match fs::read_to_string(material_e) {
+        Ok(buf) => buf,
+        Err(_) => return
+    };
+
+    let mtl = match ini::parse_mtl(&mtl_buf) {
+        Ok(mtl) => mtl,
+        Err(_) => return
+    };
+
+    if let Err(mismatches) = validate_mtl_refs(&mtl.submaterial_names()[..], model.get_used_sumbaterials()) {
+        warnings.extend(mismatches.into_iter().map(|e| format!("{} (emissive) vs {}: {}", model_e.display(), material_e.display(), e)));
+    }
+}
+
+
+// Highest texture slot index expected in $TEXTURE* tokens. The range the game accepts is not documented,
+// so a higher slot is only a warning.
+const MTL_TEXTURE_SLOT_MAX: u8 = 7;
+
+fn validate_mtl_texture_slots(mtl: &MaterialMtl, errors: &mut Vec<String>, warnings: &mut Vec<String>) {
+    // slots are tracked per submaterial; textures before the first $SUBMATERIAL belong to the whole material
+    let mut submaterial: Option<&str> = None;
+    let mut used_slots = [false; u8::MAX as usize + 1];
+
+    for t in mtl.tokens() {
+        let slot = match t {
+            MT::Submaterial(sm) => {
+                submaterial = Some(sm.as_str());
+                used_slots = [false; u8::MAX as usize + 1];
+                continue;
+            },
+            MT::Texture((i, _))         => *i,
+            MT::TextureNoMip((i, _))    => *i,
+            MT::TextureMtl((i, _))      => *i,
+            MT::TextureNoMipMtl((i, _)) => *i,
+            _ => continue
+        };
+
+        let sm_name = submaterial.unwrap_or("<material>");
+        if slot > MTL_TEXTURE_SLOT_MAX {
+            warnings.push(format!("{}: texture slot {} is outside the expected range (0..={})", sm_name, slot, MTL_TEXTURE_SLOT_MAX));
+        }
+
+        if used_slots[slot as usize] {
+            errors.push(format!("{}: texture slot {} is defined more than once", sm_name, slot));
+        } else {
+            used_slots[slot as usize] = true;
+        }
+    }
+}
+
+
+// Each LOD must switch in farther than the previous one, otherwise the game never shows it
+fn validate_lod_distances(render_ini: &RenderIni, errors: &mut Vec<String>) {
+    let mut lod = None;
+    let mut lod2 = None;
+    for t in render_ini.tokens() {
+        match t {
+            RT::ModelLod((_, d))  if lod.is_none()  => lod = Some(*d),
+            RT::ModelLod2((_, d)) if lod2.is_none() => lod2 = Some(*d),
+            _ => ()
+        }
+    }
+
+    if let (Some(lod), Some(lod2)) = (lod, lod2) {
+        if lod2 <= lod {
+            errors.push(format!("MODEL_LOD2 distance ({}) must be greater than MODEL_LOD distance ({})", lod2, lod));
+        }
     }
 }
 
@@ -331,13 +764,19 @@ fn concat_parse_errors(errors: Vec<(&str, String)>) -> String {
 }
 
 
-fn push_textures<F>(mtl_path: &Path, textures: &mut Vec<PathBuf>, mtl_path_resolver: F) -> Result<(), BuildingError>
+fn has_dds_magic(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    fs::File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && &magic == b"DDS "
+}
+
+
+fn push_textures<F>(mtl_path: &Path, textures: &mut Vec<PathBuf>, roots: &GameRoots, mtl_path_resolver: F) -> Result<(), BuildingError>
 where F: Fn(&Path, &IdStringParam) -> PathBuf 
 {
-    let mtl_root = mtl_path.parent().expect(&format!("Cannot get mtl root from {}", mtl_path.display()));
+    let mtl_root = mtl_path.parent().unwrap_or_else(|| panic!("Cannot get mtl root from {}", mtl_path.display()));
     let mtl_buf = fs::read_to_string(mtl_path).map_err(|e| BuildingError::FileIO(mtl_path.to_path_buf(), e.to_string()))?;
     let mtl = ini::parse_mtl(&mtl_buf).map_err(|e| BuildingError::Parse(mtl_path.to_path_buf(), concat_parse_errors(e)))?;
-    for tx_path in mtl.get_texture_paths(|p| mtl_path_resolver(mtl_root, p)) {
+    for tx_path in mtl.get_texture_paths(roots, |p| mtl_path_resolver(mtl_root, p)) {
         if textures.iter().all(|x| *x != tx_path) {
             textures.push(tx_path);
         }
@@ -393,3 +832,160 @@ impl Display for BuildingError {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mtl_errors(src: &str) -> (Vec<String>, Vec<String>) {
+        let mtl = ini::parse_mtl(src).unwrap();
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        push_mtl_errors(&mtl, ["mat_a", "mat_b"].iter(), &mut errors, &mut warnings, "model.mtl");
+        (errors, warnings)
+    }
+
+    #[test]
+    fn mtl_texture_slots_are_checked_per_submaterial() {
+        let (errors, warnings) = mtl_errors("$SUBMATERIAL mat_a\r\n$TEXTURE_MTL 0 a.dds\r\n$SUBMATERIAL mat_b\r\n$TEXTURE_MTL 0 b.dds\r\n");
+        assert!(errors.is_empty() && warnings.is_empty());
+
+        let (errors, warnings) = mtl_errors("$SUBMATERIAL mat_a\r\n$TEXTURE_MTL 0 a.dds\r\n$TEXTURE 0 c.dds\r\n\
+                                             $SUBMATERIAL mat_b\r\n$TEXTURE_MTL 9 b.dds\r\n$TEXTURE 9 c.dds\r\n");
+        assert_eq!(errors, vec!["Errors in model.mtl",
+                                "mat_a: texture slot 0 is defined more than once",
+                                "mat_b: texture slot 9 is defined more than once"]);
+        assert_eq!(warnings, vec!["model.mtl: mat_b: texture slot 9 is outside the expected range (0..=7)",
+                                  "model.mtl: mat_b: texture slot 9 is outside the expected range (0..=7)"]);
+    }
+
+    fn lod_errors(src: &str) -> Vec<String> {
+        let render = ini::parse_renderconfig_ini(src).unwrap();
+        let mut errors = Vec::new();
+        validate_lod_distances(&render, &mut errors);
+        errors
+    }
+
+    #[test]
+    fn inverted_lod_distances_are_reported() {
+        assert!(lod_errors("MODEL m.nmf\r\nMODEL_LOD lod.nmf 100\r\nMODEL_LOD2 lod2.nmf 300\r\n").is_empty());
+        assert!(lod_errors("MODEL m.nmf\r\nMODEL_LOD2 lod2.nmf 300\r\n").is_empty());
+
+        assert_eq!(lod_errors("MODEL m.nmf\r\nMODEL_LOD lod.nmf 300\r\nMODEL_LOD2 lod2.nmf 100\r\n"),
+                   vec!["MODEL_LOD2 distance (100) must be greater than MODEL_LOD distance (300)"]);
+        assert_eq!(lod_errors("MODEL m.nmf\r\nMODEL_LOD lod.nmf 100\r\nMODEL_LOD2 lod2.nmf 100\r\n").len(), 1);
+    }
+
+    // Warnings from one of the building.ini checks (push_*_warnings)
+    fn building_warnings<F>(push: F, src: &str) -> Vec<String>
+    where F: Fn(&BuildingIni, &mut Vec<String>)
+    {
+        let building_ini = ini::parse_building_ini(src).unwrap();
+        let mut warnings = Vec::new();
+        push(&building_ini, &mut warnings);
+        warnings
+    }
+
+    #[test]
+    fn steep_rail_connection_is_reported() {
+        // 1 up over 20 is fine for rail, 2 up over 10 is not; the same 2 over 10 is fine for a road
+        assert!(building_warnings(push_connection_warnings, "$CONNECTION_RAIL 0 0 0 0 1 20\r\n$CONNECTION_ROAD 0 0 0 0 2 10\r\nend\r\n").is_empty());
+
+        let warnings = building_warnings(push_connection_warnings, "$CONNECTION_RAIL 0 0 0 0 2 10\r\n$CONNECTION_PEDESTRIAN 0 0 0 0 5 1\r\nend\r\n");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("building.ini: rail connection"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn node_typo_gets_a_suggestion() {
+        let objects = ["chimney", "door_1", "main"];
+        let ref_errors = |src: &str| {
+            let building_ini = ini::parse_building_ini(src).unwrap();
+            validate_building_ini_refs(building_ini.get_model_refs(), &objects[..]).unwrap_err()
+        };
+
+        assert_eq!(ref_errors("$COST_WORK_BUILDING_NODE chimeny\r\nend\r\n"),
+                   vec!["building.ini contains invalid reference to node 'chimeny'. No object in the NMF has such name; did you mean 'chimney'?"]);
+        assert!(!ref_errors("$COST_WORK_BUILDING_NODE roof\r\nend\r\n")[0].contains("did you mean"));
+    }
+
+    #[test]
+    fn unnamed_building_is_reported() {
+        assert!(building_warnings(push_name_warnings, "$NAME_STR \"b1\"\r\n$WORKERS_NEEDED 10\r\nend\r\n").is_empty());
+        assert!(building_warnings(push_name_warnings, "$NAME 1234\r\n$WORKERS_NEEDED 10\r\nend\r\n").is_empty());
+
+        assert_eq!(building_warnings(push_name_warnings, "$WORKERS_NEEDED 10\r\nend\r\n"),
+                   vec!["building.ini: building is unnamed (neither $NAME nor $NAME_STR is set)"]);
+        assert_eq!(building_warnings(push_name_warnings, "$NAME 1234\r\n$NAME_STR \"b1\"\r\nend\r\n"),
+                   vec!["building.ini: both $NAME and $NAME_STR are set (ambiguous name)"]);
+    }
+
+    #[test]
+    fn negative_storage_capacity_is_reported() {
+        assert!(building_warnings(push_storage_warnings, "$STORAGE RESOURCE_TRANSPORT_COVERED 100\r\n\
+                                                          $STORAGE_SPECIAL RESOURCE_TRANSPORT_COVERED 20 food\r\n\
+                                                          $STORAGE_SPECIAL RESOURCE_TRANSPORT_NUCLEAR1 5 nuclearfuel\r\nend\r\n").is_empty());
+
+        let warnings = building_warnings(push_storage_warnings, "$STORAGE RESOURCE_TRANSPORT_COVERED -20\r\nend\r\n");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("building.ini: storage capacity must be a non-negative number"), "{}", warnings[0]);
+
+        let warnings = building_warnings(push_storage_warnings, "$STORAGE_SPECIAL RESOURCE_TRANSPORT_PASSANGER 10 food\r\n\
+                                                                 $STORAGE_SPECIAL RESOURCE_TRANSPORT_COVERED 10 eletric\r\nend\r\n");
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings.iter().all(|w| w.starts_with("building.ini: resource cannot be stored with this cargo type")), "{:?}", warnings);
+    }
+
+    #[test]
+    fn zero_size_vehicle_station_is_reported() {
+        assert!(building_warnings(push_point_pair_warnings, "$VEHICLE_STATION 1 0 1 2 0 2\r\n$TEXT_CAPTION 0 0 0 1 1 0\r\nend\r\n").is_empty());
+
+        let warnings = building_warnings(push_point_pair_warnings, "$VEHICLE_STATION 1 0 1 1 0 1\r\n$WORKER_RENDERING_AREA 0 0 0 5 0 0\r\nend\r\n");
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert!(warnings[0].starts_with("building.ini: VEHICLE_STATION has both points at"), "{}", warnings[0]);
+        assert!(warnings[1].starts_with("building.ini: WORKER_RENDERING_AREA") && warnings[1].ends_with("has zero area"), "{}", warnings[1]);
+    }
+
+    #[test]
+    fn unreferenced_objects_are_listed() {
+        let objects = ["main", "door_1", "door_2", "chimney"];
+        let usage = |src: &str| {
+            let building_ini = ini::parse_building_ini(src).unwrap();
+            let (refs, unreferenced) = building_ini_node_usage(building_ini.get_model_refs(), &objects[..]);
+            (refs.into_iter().map(|(_, matched)| matched).collect::<Vec<_>>(), unreferenced)
+        };
+
+        let (matched, unreferenced) = usage("$COST_WORK_BUILDING_NODE main\r\nend\r\n");
+        assert_eq!(matched, vec![vec!["main"]]);
+        assert_eq!(unreferenced, ["door_1", "door_2", "chimney"]);
+
+        let (matched, unreferenced) = usage("$COST_WORK_BUILDING_NODE main\r\n$COST_WORK_BUILDING_KEYWORD $door_\r\nend\r\n");
+        assert_eq!(matched, vec![vec!["main"], vec!["door_1", "door_2"]]);
+        assert_eq!(unreferenced, ["chimney"]);
+    }
+
+    // sample_nmf uses mat_a and mat_b, the emissive mtl only defines mat_a
+    #[test]
+    fn emissive_submaterial_mismatch_is_reported() {
+        use crate::test_util::{ScratchDir, sample_nmf, nmf_bytes};
+
+        let dir = ScratchDir::new("emissive_pair");
+        let model_e = dir.write("model_e.nmf", nmf_bytes(&sample_nmf()));
+        let material_e = dir.write("model_e.mtl", "$SUBMATERIAL mat_a\r\n$TEXTURE_MTL 0 a.dds\r\n");
+
+        let emissive_warnings = || {
+            let mut warnings = Vec::new();
+            push_emissive_pair_warnings(&model_e, &material_e, &mut warnings);
+            warnings
+        };
+
+        let warnings = emissive_warnings();
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert!(warnings[0].contains("(emissive)") && warnings[0].contains("mat_b"), "{}", warnings[0]);
+
+        dir.write("model_e.mtl", "$SUBMATERIAL mat_a\r\n$TEXTURE_MTL 0 a.dds\r\n$SUBMATERIAL mat_b\r\n$TEXTURE_MTL 0 b.dds\r\n");
+        let warnings = emissive_warnings();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+}
@@ -2,11 +2,13 @@ use std::path::PathBuf;
 use std::str::FromStr;
 
 use lazy_static::lazy_static;
+use const_format::concatcp;
 use normpath::BasePathBuf;
 
+use wrsr_mt::{ini, modpack};
 
-pub const RENDERCONFIG_INI: &str = "renderconfig.ini";
-pub const BUILDING_INI:     &str = "building.ini";
+pub use wrsr_mt::{RENDERCONFIG_INI, BUILDING_INI};
+pub use wrsr_mt::ini::transform::CostScaling;
 
 
 pub enum AppCommand {
@@ -19,43 +21,93 @@ pub enum AppCommand {
 //-----------------------------
 
 pub enum NmfCommand {
-    Show(PathBuf),
-    ToObj(FromToCommand),
+    // bool: also print the opaque header bytes of each object
+    Show(PathBuf, bool),
+    Compare(PathBuf, PathBuf),
+    Classify(Vec<PathBuf>),
+    Histogram(PathBuf),
+    VerifyLength(PathBuf),
+    // f32: UV bound
+    Check(PathBuf, f32),
+    // bool: group faces by submaterial
+    ToObj(FromToCommand, bool, wrsr_mt::nmf::export::ObjIndexBase),
+    ExtractObject(ExtractObjectCommand),
+    DumpObject(ExtractObjectCommand),
+    RenameObject(RenameObjectCommand),
+    Export(FromToCommand, ExportFormat),
+    TransformBuilding(TransformBuildingCommand),
+    RecalcNormals(FromToCommand, wrsr_mt::nmf::NormalsMode),
     Scale(ScaleCommand),
     Mirror(FromToCommand),
+    // scale / mirror --validate-only: apply the transform and its inverse, report the drift
+    CheckReversible(PathBuf, NmfTransform),
     Optimize(FromToCommand),
+    Repair(FromToCommand),
+    Uv(FromToCommand, (f32, f32), (f32, f32)),
+    NormalizeSubmaterials(FromToCommand),
+    // bool: only submaterials used by objects
+    GenMtl(FromToCommand, bool),
 }
 
 //-------------------------------
 
 pub enum ModCommand {
-    Validate(PathBuf),
-    Scale(ScaleCommand),
-    Mirror(FromToCommand),
+    // bools: strict, fix, print node references
+    Validate(PathBuf, bool, bool, bool),
+    Scale(ScaleCommand, ModParts, CostScaling, Option<usize>, bool),
+    Mirror(FromToCommand, Option<usize>, bool),
+    Pack(FromToCommand),
+}
+
+pub struct ModParts {
+    pub models: bool,
+    pub ini: bool,
 }
 
 //-------------------------------
 
 pub enum IniCommand {
-    ParseBuilding(PathBuf),
+    // bool: summary; Option: category filter
+    ParseBuilding(PathBuf, bool, Option<String>),
     ParseRender(PathBuf),
-    ParseMtl(PathBuf),
-    ScaleBuilding(ScaleCommand),
-    ScaleRender(ScaleCommand),
-    MirrorBuilding(FromToCommand),
-    MirrorRender(FromToCommand),
+    // bool: print resolved texture paths
+    ParseMtl(PathBuf, bool),
+    // Option<usize>: float precision of the written .ini, if set
+    // Vec: one or more input/output pairs processed with the same settings
+    // bool (building): lenient parsing, unknown tokens are passed through
+    // last bool: annotated mode, the transformed tokens are printed with the rewritten ones marked
+    ScaleBuilding(Vec<ScaleCommand>, CostScaling, Option<usize>, bool, bool),
+    ScaleRender(Vec<ScaleCommand>, Option<usize>, bool),
+    MirrorBuilding(Vec<FromToCommand>, Option<usize>, bool, bool),
+    MirrorRender(Vec<FromToCommand>, Option<usize>, bool),
+    MergeBuilding(MergeCommand),
+    ListTypes,
+    GetModels(PathBuf),
+    Coverage(PathBuf),
 }
 
 //-------------------------------
 
 pub enum ModpackCommand {
     Install(ModpackInstallCommand),
-    Validate(PathBuf),
+    // Option: path of the JSON report; bool: print only errors and totals
+    Validate(PathBuf, Option<PathBuf>, bool),
+    Stats(PathBuf),
+    NewBuilding(PathBuf),
 }
 
 pub struct ModpackInstallCommand {
     pub source: PathBuf,
     pub destination: PathBuf,
+    pub visibility: u8,
+    pub exclude: Vec<String>,
+    pub only: Vec<String>,
+    pub max_buildings: Option<usize>,
+    pub link_assets: bool,
+    pub timings: bool,
+    pub clean: bool,
+    // names of the shared (dds, nmf) asset directories in the destination
+    pub assets_dirs: (String, String),
 }
 
 //-------------------------------
@@ -65,6 +117,42 @@ pub struct FromToCommand {
     pub output: PathBuf
 }
 
+pub struct ExtractObjectCommand {
+    pub input: PathBuf,
+    pub object: String,
+    pub output: PathBuf
+}
+
+pub struct RenameObjectCommand {
+    pub input: PathBuf,
+    pub object: String,
+    pub new_name: String,
+    pub output: PathBuf
+}
+
+pub struct MergeCommand {
+    pub base: PathBuf,
+    pub overlay: PathBuf,
+    pub output: PathBuf
+}
+
+pub enum ExportFormat {
+    Obj,
+    Stl,
+}
+
+// What face indices in a written *.obj count from
+pub enum NmfTransform {
+    Scale(f64),
+    Mirror,
+}
+
+pub struct TransformBuildingCommand {
+    pub render: PathBuf,
+    pub transform: NmfTransform,
+    pub output: PathBuf
+}
+
 pub struct ScaleCommand {
     pub input: PathBuf,
     pub factor: f64,
@@ -74,8 +162,7 @@ pub struct ScaleCommand {
 //-------------------------------
 
 pub struct AppSettings {
-    pub path_stock: BasePathBuf,
-    pub path_workshop: BasePathBuf,
+    pub roots: ini::GameRoots,
 
     pub command: AppCommand,
 }
@@ -83,11 +170,20 @@ pub struct AppSettings {
 
 impl AppSettings {
 
-    // mod folder is 7 digits and cannot start from zero.
-    pub const MOD_IDS_START:        usize = 1_000_000;
-    pub const MOD_IDS_END:          usize = 9_999_999;
-    pub const MAX_BUILDINGS_IN_MOD: usize = 100; // [0..99]
-    pub const MAX_SKINS_IN_MOD:     usize = 16;
+    pub const MOD_IDS_START:        usize = modpack::MOD_IDS_START;
+    pub const MOD_IDS_END:          usize = modpack::MOD_IDS_END;
+    pub const MAX_BUILDINGS_IN_MOD: usize = modpack::MAX_BUILDINGS_IN_MOD;
+    pub const MAX_SKINS_IN_MOD:     usize = modpack::MAX_SKINS_IN_MOD;
+
+    // $VISIBILITY values of workshop items: 0 - public, 1 - friends only, 2 - private, 3 - unlisted
+    pub const VISIBILITY_VALUES:    [u8; 4] = [0, 1, 2, 3];
+
+    // Scale factors outside of this range are allowed, but most likely are a mistake
+    pub const SCALE_FACTOR_SANE_MIN: f64 = 0.01;
+    pub const SCALE_FACTOR_SANE_MAX: f64 = 100.0;
+
+    // Max number of decimals for floats written to .ini files (f32 has ~7 significant digits anyway)
+    pub const FLOAT_PRECISION_MAX:   usize = 9;
 
     pub const MAX_MODS:      usize = AppSettings::MOD_IDS_END - AppSettings::MOD_IDS_START;
     pub const MAX_BUILDINGS: usize = AppSettings::MAX_MODS * AppSettings::MAX_BUILDINGS_IN_MOD;
@@ -109,59 +205,254 @@ lazy_static! {
         let cmd_nmf = {
             let cmd_nmf_show = SubCommand::with_name("show")
                 .about("Parse the specified *.nmf and print it's structure")
-                .arg(Arg::with_name("nmf-path").required(true));
+                .arg(Arg::with_name("nmf-path").required(true))
+                .arg(Arg::with_name("matrix")
+                    .long("matrix")
+                    .help("Also print the unknown header bytes of each object: the first 64 as a 4x4 f32 matrix, the rest as raw hex"));
+
+            let cmd_nmf_compare = SubCommand::with_name("compare")
+                .about("Parse two *.nmf files and print the differences of their structure (objects, vertices, faces, submaterials)")
+                .arg(Arg::with_name("nmf-a").required(true))
+                .arg(Arg::with_name("nmf-b").required(true));
+
+            let cmd_nmf_classify = SubCommand::with_name("classify")
+                .about("Rank the specified *.nmf files (or objects of a single *.nmf) by vertex count \
+                        to suggest MODEL / MODEL_LOD / MODEL_LOD2 slots in renderconfig.ini")
+                .arg(Arg::with_name("nmf-path").required(true).multiple(true));
+
+            let cmd_nmf_histogram = SubCommand::with_name("histogram")
+                .about("Print the distribution of face areas of the specified *.nmf, with total and average area")
+                .arg(Arg::with_name("nmf-input").required(true));
+
+            let cmd_nmf_verify_length = SubCommand::with_name("verify-length")
+                .about("Compare the file length stored in the header of the specified *.nmf with the actual file size")
+                .arg(Arg::with_name("nmf-input").required(true));
+
+            let cmd_nmf_check = SubCommand::with_name("check")
+                .about("Parse the specified *.nmf and warn about likely broken meshes: UV coordinates far outside [0, 1]")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("uv-bound")
+                    .long("uv-bound")
+                    .value_name("n")
+                    .default_value("16")
+                    .help("Largest absolute UV coordinate that is not reported (tiled textures legitimately go past 1)"));
 
             let cmd_nmf_toobj = SubCommand::with_name("to-obj")
                 .about("Convert the specified *.nmf to *.obj format")
                 .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("obj-output").required(true))
+                .arg(Arg::with_name("group-by-submaterial")
+                    .long("group-by-submaterial")
+                    .help("Split faces of each object into groups (with usemtl) by their submaterial"))
+                .arg(Arg::with_name("relative-to")
+                    .long("relative-to")
+                    .possible_values(&["file", "object"])
+                    .default_value("file")
+                    .help("Face indices count from the start of the file (1-based), or back from the end of each object's own vertices (negative)"));
+
+            let cmd_nmf_export = SubCommand::with_name("export")
+                .about("Export the specified *.nmf to another format: *.obj, or binary *.stl (geometry only)")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("output").required(true))
+                .arg(Arg::with_name("format")
+                    .long("format")
+                    .possible_values(&["obj", "stl"])
+                    .default_value("obj"));
+
+            let cmd_nmf_extract = SubCommand::with_name("extract-object")
+                .about("Export a single object of the specified *.nmf to *.obj format")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("object-name").required(true))
                 .arg(Arg::with_name("obj-output").required(true));
 
+            let cmd_nmf_dump = SubCommand::with_name("dump-object")
+                .about("Copy the exact bytes of a single object of the specified *.nmf to a standalone file (no re-serialization)")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("object-name").required(true))
+                .arg(Arg::with_name("bin-output").required(true));
+
+            let cmd_nmf_rename = SubCommand::with_name("rename-object")
+                .about("Rename an object of the specified *.nmf (up to 64 bytes), save to a new file")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("object-name").required(true))
+                .arg(Arg::with_name("new-name").required(true))
+                .arg(Arg::with_name("nmf-output").required(true));
+
+            let arg_validate_only = Arg::with_name("validate-only")
+                .long("validate-only")
+                .conflicts_with("nmf-output")
+                .help("Do not write anything: apply the transform and its inverse, report the maximum vertex drift");
+
             let cmd_nmf_scale = SubCommand::with_name("scale")
                 .about("Scale the specified *.nmf by given factor")
                 .arg(Arg::with_name("nmf-input").required(true))
                 .arg(Arg::with_name("factor").required(true))
-                .arg(Arg::with_name("nmf-output").required(true));
+                .arg(Arg::with_name("nmf-output").required_unless("validate-only"))
+                .arg(arg_validate_only.clone());
 
             let cmd_nmf_mirror = SubCommand::with_name("mirror")
                 .about("Mirror the specified *.nmf, save to a new file")
                 .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("nmf-output").required_unless("validate-only"))
+                .arg(arg_validate_only);
+
+            let cmd_nmf_transform_building = SubCommand::with_name("transform-building")
+                .about("Scale or mirror all models (model, lod, lod2, emissive) referenced by the specified renderconfig.ini, \
+                        save them to the output directory. The .ini files are not modified")
+                .arg(Arg::with_name("render-input").required(true))
+                .arg(Arg::with_name("transform")
+                    .required(true)
+                    .help("Scale factor or 'mirror'"))
+                .arg(Arg::with_name("dir-output").required(true));
+
+            let cmd_nmf_recalc_normals = SubCommand::with_name("recalc-normals")
+                .about("Recalculate normals of the specified *.nmf from its faces, save to a new file")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("nmf-output").required(true))
+                .arg(Arg::with_name("flat")
+                    .long("flat")
+                    .conflicts_with("smooth")
+                    .help("Use face normals. Vertices shared by faces with different normals get averaged normals"))
+                .arg(Arg::with_name("smooth")
+                    .long("smooth")
+                    .help("Average normals of adjacent faces (default)"));
+
+            let cmd_nmf_uv = SubCommand::with_name("uv")
+                .setting(clap::AppSettings::AllowNegativeNumbers)
+                .about("Transform texture coordinates of all objects of the specified *.nmf (uv * scale + offset), save to a new file")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("scale-u").required(true))
+                .arg(Arg::with_name("scale-v").required(true))
+                .arg(Arg::with_name("offset-u").required(true))
+                .arg(Arg::with_name("offset-v").required(true))
                 .arg(Arg::with_name("nmf-output").required(true));
 
+            let cmd_nmf_normalize_sm = SubCommand::with_name("normalize-submaterials")
+                .about("Reorder submaterials of the specified *.nmf by first use (unused ones last), save to a new file")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("nmf-output").required(true));
+
+            let cmd_nmf_gen_mtl = SubCommand::with_name("gen-mtl")
+                .about("Write a stub *.mtl with a $SUBMATERIAL token for each submaterial of the specified *.nmf")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("mtl-output").required(true))
+                .arg(Arg::with_name("used-only")
+                    .long("used-only")
+                    .help("Skip submaterials that are not used by any object"));
+
             let cmd_nmf_optimize = SubCommand::with_name("optimize")
                 .about("Optimize the specified *.nmf. Currently removes duplicated vertices data and updates face indices")
                 .arg(Arg::with_name("nmf-input").required(true))
                 .arg(Arg::with_name("nmf-output").required(true));
 
+            let cmd_nmf_repair = SubCommand::with_name("repair")
+                .about("Salvage a damaged *.nmf: fix the header length, drop trailing bytes and objects that fail to parse, recalculate bboxes")
+                .arg(Arg::with_name("nmf-input").required(true))
+                .arg(Arg::with_name("nmf-output").required(true));
+
             SubCommand::with_name("nmf")
                 .about("Operations for *.nmf files")
                 .subcommand(cmd_nmf_show)
+                .subcommand(cmd_nmf_compare)
+                .subcommand(cmd_nmf_classify)
+                .subcommand(cmd_nmf_histogram)
+                .subcommand(cmd_nmf_verify_length)
+                .subcommand(cmd_nmf_check)
                 .subcommand(cmd_nmf_toobj)
+                .subcommand(cmd_nmf_extract)
+                .subcommand(cmd_nmf_dump)
+                .subcommand(cmd_nmf_rename)
+                .subcommand(cmd_nmf_export)
                 .subcommand(cmd_nmf_scale)
                 .subcommand(cmd_nmf_mirror)
                 .subcommand(cmd_nmf_optimize)
+                .subcommand(cmd_nmf_repair)
+                .subcommand(cmd_nmf_uv)
+                .subcommand(cmd_nmf_normalize_sm)
+                .subcommand(cmd_nmf_gen_mtl)
+                .subcommand(cmd_nmf_transform_building)
+                .subcommand(cmd_nmf_recalc_normals)
         };
 
+        let arg_precision = Arg::with_name("precision")
+            .long("precision")
+            .value_name("n")
+            .help(concatcp!("Number of decimals for floats written to modified .ini tokens. \
+                             By default coordinates in building.ini get ", ini::POINT_PRECISION, ", other values are written as is"));
+
+        let arg_more_pairs = Arg::with_name("more-pairs")
+            .multiple(true)
+            .value_names(&["ini-input", "ini-output"])
+            .help("Additional input/output pairs, processed with the same settings");
+
+        let arg_lenient = Arg::with_name("lenient")
+            .long("lenient")
+            .help("Keep tokens of unknown types (e.g. added by a game update) as they are instead of failing");
+
+        let arg_annotated = Arg::with_name("annotated")
+            .long("annotated")
+            .help("Also print the transformed tokens, with '*' marking the ones rewritten in the output file");
+
+        let arg_cost_scaling = Arg::with_name("cost-scaling")
+            .long("cost-scaling")
+            .value_name("mode")
+            .possible_values(&["none", "linear", "cubic"])
+            .default_value("none")
+            .help("How construction costs (COST_WORK, COST_RESOURCE, COST_RESOURCE_AUTO) are scaled: \
+                   'none' keeps them, 'linear' multiplies by the factor, 'cubic' by the factor cubed (by volume)");
+
         let cmd_modbuilding = {
+            let arg_dry_run = Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Parse, check and transform the building in memory, report what would change. Nothing is copied or written");
+
             let cmd_mod_validate = SubCommand::with_name("validate")
                 .about("Checks the specified building mod for errors")
-                .arg(Arg::with_name("dir-input").required(true));
+                .arg(Arg::with_name("dir-input").required(true))
+                .arg(Arg::with_name("strict")
+                    .long("strict")
+                    .help("Treat warnings as errors"))
+                .arg(Arg::with_name("fix")
+                    .long("fix")
+                    .help("Fix bounding boxes, trailing bytes in models and mixed line endings in place before validating (originals are kept as *.bak)"))
+                .arg(Arg::with_name("node-refs")
+                    .long("node-refs")
+                    .help("List the model objects referenced by building.ini nodes and keywords, and the ones nothing references"));
 
             let cmd_modbuilding_scale = SubCommand::with_name("scale")
                 .about("Scales the whole building (models and .ini files) by the specified factor")
                 .arg(Arg::with_name("dir-input").required(true))
                 .arg(Arg::with_name("factor").required(true))
-                .arg(Arg::with_name("dir-output").required(true));
+                .arg(Arg::with_name("dir-output").required(true))
+                .arg(Arg::with_name("models-only")
+                    .long("models-only")
+                    .conflicts_with("ini-only")
+                    .help("Scale only the models (*.nmf), leave .ini files unchanged"))
+                .arg(Arg::with_name("ini-only")
+                    .long("ini-only")
+                    .help("Scale only the .ini files, leave models (*.nmf) unchanged"))
+                .arg(arg_cost_scaling.clone())
+                .arg(arg_precision.clone())
+                .arg(arg_dry_run.clone());
 
             let cmd_modbuilding_mirror = SubCommand::with_name("mirror")
                 .about("Mirrors the whole building (models and .ini files)")
                 .arg(Arg::with_name("dir-input").required(true))
-                .arg(Arg::with_name("dir-output").required(true));
+                .arg(Arg::with_name("dir-output").required(true))
+                .arg(arg_precision.clone())
+                .arg(arg_dry_run);
+
+            let cmd_modbuilding_pack = SubCommand::with_name("pack")
+                .about("Validates the building and packs all its files into a *.zip archive")
+                .arg(Arg::with_name("dir-input").required(true))
+                .arg(Arg::with_name("zip-output").required(true));
 
             SubCommand::with_name("mod-building")
                 .about("Operations for whole mods")
                 .subcommand(cmd_mod_validate)
                 .subcommand(cmd_modbuilding_scale)
                 .subcommand(cmd_modbuilding_mirror)
+                .subcommand(cmd_modbuilding_pack)
         };
 
         let cmd_modpack = {
@@ -169,23 +460,87 @@ lazy_static! {
                 .about("Installs modpack from the specified source directory")
                 .arg(Arg::with_name("dir-source").required(true))
                 .arg(Arg::with_name("dir-destination")
-                    .default_value(r"C:\Program Files (x86)\Steam\steamapps\common\SovietRepublic\media_soviet\workshop_wip"));
+                    .default_value(r"C:\Program Files (x86)\Steam\steamapps\common\SovietRepublic\media_soviet\workshop_wip"))
+                .arg(Arg::with_name("visibility")
+                    .long("visibility")
+                    .value_name("n")
+                    .help("$VISIBILITY of generated workshop items: 0 - public, 1 - friends only, 2 - private, 3 - unlisted")
+                    .default_value("2"))
+                .arg(Arg::with_name("exclude")
+                    .long("exclude")
+                    .value_name("glob")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Skip source directories matching the pattern (relative to the source root, '*', '**' and '?' are supported)"))
+                .arg(Arg::with_name("only")
+                    .long("only")
+                    .value_name("name-or-glob")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Install only the buildings whose directory name or relative path (glob, like --exclude) matches"))
+                .arg(Arg::with_name("max-buildings")
+                    .long("max-buildings")
+                    .value_name("n")
+                    .help("Stop after installing n buildings (partial install, for testing)"))
+                .arg(Arg::with_name("symlink-assets")
+                    .long("symlink-assets")
+                    .help("Link shared textures and models (dds/, nmf/) to their source files instead of copying them. \
+                           Symlinks on unix, hard links on windows; falls back to copying if linking fails"))
+                .arg(Arg::with_name("clean")
+                    .long("clean")
+                    .help("Remove a previous install (mods and asset directories listed in its modpack.log) from the destination first. \
+                           Refuses to run if the destination has no modpack.log"))
+                .arg(Arg::with_name("assets-dir-name")
+                    .long("assets-dir-name")
+                    .value_names(&["dds", "nmf"])
+                    .number_of_values(2)
+                    .help("Names of the shared texture and model directories in the destination (default: dds nmf). \
+                           They are recorded in modpack.log, so --clean removes them whatever names the new install uses"))
+                .arg(Arg::with_name("timings")
+                    .long("timings")
+                    .help("Print time spent in validation, model copying, texture copying and ini rewriting"));
 
             let cmd_modpack_validate = SubCommand::with_name("validate")
                 .about("Checks the modpack source in the specified directory for errors")
+                .arg(Arg::with_name("dir-source").required(true))
+                .arg(Arg::with_name("report")
+                    .long("report")
+                    .value_name("file")
+                    .help("Also write a JSON report: status, errors and warnings of every building"))
+                .arg(Arg::with_name("summary-only")
+                    .long("summary-only")
+                    .help("Print only errors and the totals, no line per valid building and no warnings"));
+
+            let cmd_modpack_stats = SubCommand::with_name("stats")
+                .about("Validates the modpack source and prints its summary: buildings, skins, unique models and textures, mod ids needed")
                 .arg(Arg::with_name("dir-source").required(true));
 
+            let cmd_modpack_new_building = SubCommand::with_name("new-building")
+                .about("Creates a template building source in the specified directory: building.ini, renderconfig.source, building.skins, building.actions")
+                .arg(Arg::with_name("dir").required(true));
+
             SubCommand::with_name("modpack")
                 .about("Modpacks management")
                 .subcommand(cmd_modpack_install)
                 .subcommand(cmd_modpack_validate)
+                .subcommand(cmd_modpack_stats)
+                .subcommand(cmd_modpack_new_building)
         };
 
         let cmd_ini = {
             let cmd_ini_parse = {
                 let cmd_ini_parse_building = SubCommand::with_name("building")
                     .about("Parse the specified building.ini, check for errors, print results")
-                    .arg(Arg::with_name("path").required(true));
+                    .arg(Arg::with_name("path").required(true))
+                    .arg(Arg::with_name("summary")
+                        .long("summary")
+                        .help("Print only the counts of parsed tokens and errors, with tokens grouped by category"))
+                    .arg(Arg::with_name("filter")
+                        .long("filter")
+                        .value_name("category")
+                        .possible_values(ini::building::Token::CATEGORIES)
+                        .conflicts_with("summary")
+                        .help("Print only the tokens of the given category (parse errors are always printed)"));
 
                 let cmd_ini_parse_render = SubCommand::with_name("renderconfig")
                     .about("Parse the specified renderconfig.ini, check for errors, print results")
@@ -193,7 +548,10 @@ lazy_static! {
 
                 let cmd_ini_parse_mtl = SubCommand::with_name("mtl")
                     .about("Parse the specified *.mtl, check for errors, print results")
-                    .arg(Arg::with_name("path").required(true));
+                    .arg(Arg::with_name("path").required(true))
+                    .arg(Arg::with_name("resolve")
+                        .long("resolve")
+                        .help("Also print the resolved path of every texture (relative to the mtl, '~' stock, '#' workshop) and whether it exists"));
 
                 SubCommand::with_name("parse")
                     .about("Parsing and validating *.ini and *.mtl files")
@@ -207,13 +565,21 @@ lazy_static! {
                     .about("Parse the specified building.ini, scale by a given factor, save to a new file")
                     .arg(Arg::with_name("ini-input").required(true))
                     .arg(Arg::with_name("factor").required(true))
-                    .arg(Arg::with_name("ini-output").required(true));
+                    .arg(Arg::with_name("ini-output").required(true))
+                    .arg(arg_more_pairs.clone())
+                    .arg(arg_cost_scaling.clone())
+                    .arg(arg_lenient.clone())
+                    .arg(arg_annotated.clone())
+                    .arg(arg_precision.clone());
 
                 let cmd_ini_scale_render = SubCommand::with_name("renderconfig")
                     .about("Parse the specified renderconfig.ini, scale by a given factor, save to a new file")
                     .arg(Arg::with_name("ini-input").required(true))
                     .arg(Arg::with_name("factor").required(true))
-                    .arg(Arg::with_name("ini-output").required(true));
+                    .arg(Arg::with_name("ini-output").required(true))
+                    .arg(arg_more_pairs.clone())
+                    .arg(arg_annotated.clone())
+                    .arg(arg_precision.clone());
 
                 SubCommand::with_name("scale")
                     .about("Scaling *.ini files")
@@ -226,12 +592,19 @@ lazy_static! {
                 let cmd_ini_mirror_building = SubCommand::with_name("building")
                     .about("Parse the specified building.ini, mirror Z coordinates, save to a new file")
                     .arg(Arg::with_name("ini-input").required(true))
-                    .arg(Arg::with_name("ini-output").required(true));
+                    .arg(Arg::with_name("ini-output").required(true))
+                    .arg(arg_more_pairs.clone())
+                    .arg(arg_lenient)
+                    .arg(arg_annotated.clone())
+                    .arg(arg_precision.clone());
 
                 let cmd_ini_mirror_render = SubCommand::with_name("renderconfig")
                     .about("Parse the specified building.ini, mirror Z coordinates, save to a new file")
                     .arg(Arg::with_name("ini-input").required(true))
-                    .arg(Arg::with_name("ini-output").required(true));
+                    .arg(Arg::with_name("ini-output").required(true))
+                    .arg(arg_more_pairs)
+                    .arg(arg_annotated)
+                    .arg(arg_precision);
 
                 SubCommand::with_name("mirror")
                     .about("Mirroring *.ini files")
@@ -239,11 +612,39 @@ lazy_static! {
                     .subcommand(cmd_ini_mirror_render)
             };
 
+            let cmd_ini_merge = {
+                let cmd_ini_merge_building = SubCommand::with_name("building")
+                    .about("Apply overlay building.ini on top of the base one, save to a new file. \
+                            Singular tokens of the overlay replace the base ones, repeatable tokens (stations, connections, costs, etc.) are appended")
+                    .arg(Arg::with_name("ini-base").required(true))
+                    .arg(Arg::with_name("ini-overlay").required(true))
+                    .arg(Arg::with_name("ini-output").required(true));
+
+                SubCommand::with_name("merge")
+                    .about("Merging *.ini files")
+                    .subcommand(cmd_ini_merge_building)
+            };
+
+            let cmd_ini_list_types = SubCommand::with_name("list-types")
+                .about("Print all building.ini keywords (types, subtypes, resources, etc.) recognized by the parser");
+
+            let cmd_ini_get_models = SubCommand::with_name("get-models")
+                .about("Print resolved paths of the models and materials referenced by the specified renderconfig.ini")
+                .arg(Arg::with_name("path").required(true));
+
+            let cmd_ini_coverage = SubCommand::with_name("coverage")
+                .about("Parse the specified building.ini and report how many tokens are understood, listing unknown keywords and failed tokens")
+                .arg(Arg::with_name("path").required(true));
+
             SubCommand::with_name("ini")
                 .about("Operations for individual text-based files")
                 .subcommand(cmd_ini_parse)
                 .subcommand(cmd_ini_scale)
                 .subcommand(cmd_ini_mirror)
+                .subcommand(cmd_ini_merge)
+                .subcommand(cmd_ini_list_types)
+                .subcommand(cmd_ini_get_models)
+                .subcommand(cmd_ini_coverage)
         };
 
         let m = App::new("wrsr-mt")
@@ -268,73 +669,221 @@ lazy_static! {
             .subcommand(cmd_modpack)
             .get_matches();
 
-        let path_stock    = BasePathBuf::new(m.value_of("stock").unwrap()).unwrap();
-        let path_workshop = BasePathBuf::new(m.value_of("workshop").unwrap()).unwrap();
+        // Relative paths (including game dirs) are resolved against the current directory
+        let run_dir = BasePathBuf::try_new(std::env::current_dir().unwrap()).unwrap();
+
+        let roots = ini::GameRoots {
+            stock:    run_dir.join(m.value_of("stock").unwrap()),
+            workshop: run_dir.join(m.value_of("workshop").unwrap()),
+        };
 
         let command = { 
-            use normpath::BasePathBuf;
-            let run_dir = BasePathBuf::try_new(std::env::current_dir().unwrap()).unwrap();
-            let mk_path = |m: &clap::ArgMatches, p| run_dir.join(m.value_of(p).unwrap()).into_path_buf();
+            let resolve = |p: &str| run_dir.join(p).into_path_buf();
+            let mk_path = |m: &clap::ArgMatches, p| resolve(m.value_of(p).unwrap());
+
+            let parse_factor = |s: &str| -> f64 {
+                let factor = f64::from_str(s).expect("Cannot parse scale factor as float");
+                assert!(factor > 0f64, "Scale factor must be greater than zero (got {})", factor);
+                if !(AppSettings::SCALE_FACTOR_SANE_MIN ..= AppSettings::SCALE_FACTOR_SANE_MAX).contains(&factor) {
+                    eprintln!("WARNING: extreme scale factor {} (expected to be between {} and {})", 
+                              factor, AppSettings::SCALE_FACTOR_SANE_MIN, AppSettings::SCALE_FACTOR_SANE_MAX);
+                }
+                factor
+            };
 
-            let mk_scale = |m, p_in, p_out| -> ScaleCommand {
+            let mk_scale = |m: &clap::ArgMatches, p_in, p_out| -> ScaleCommand {
                 let input = mk_path(m, p_in);
-                let factor = f64::from_str(m.value_of("factor").unwrap()).expect("Cannot parse scale factor as float");
+                let factor = parse_factor(m.value_of("factor").unwrap());
                 let output = mk_path(m, p_out);
                 assert!(input != output, "{} and {} cannot be the same", p_in, p_out);
                 ScaleCommand { input, factor, output }
             };
             
-            let mk_from_to = |m, p_in, p_out| -> FromToCommand {
+            let mk_precision = |m: &clap::ArgMatches| -> Option<usize> {
+                m.value_of("precision").map(|s| {
+                    let prec = usize::from_str(s).expect("Cannot parse precision as integer");
+                    assert!(prec <= AppSettings::FLOAT_PRECISION_MAX, "Precision must not exceed {} (got {})", AppSettings::FLOAT_PRECISION_MAX, prec);
+                    prec
+                })
+            };
+
+            let mk_cost_scaling = |m: &clap::ArgMatches| -> CostScaling {
+                match m.value_of("cost-scaling").unwrap() {
+                    "linear" => CostScaling::Linear,
+                    "cubic"  => CostScaling::Cubic,
+                    _        => CostScaling::None,
+                }
+            };
+
+            let mk_from_to = |m: &clap::ArgMatches, p_in, p_out| -> FromToCommand {
                 let input = mk_path(m, p_in);
                 let output = mk_path(m, p_out);
                 assert!(input != output, "{} and {} cannot be the same", p_in, p_out);
                 FromToCommand { input, output }
             };
 
+            // first pair from the named args, then any number of extra pairs
+            let mk_pairs = |m: &clap::ArgMatches| -> Vec<FromToCommand> {
+                let mut pairs = vec![mk_from_to(m, "ini-input", "ini-output")];
+                let more: Vec<&str> = m.values_of("more-pairs").map(|vs| vs.collect()).unwrap_or_default();
+                assert!(more.len() % 2 == 0, "Extra arguments must come in input/output pairs (got {} values)", more.len());
+                for p in more.chunks(2) {
+                    let (input, output) = (resolve(p[0]), resolve(p[1]));
+                    assert!(input != output, "{} cannot be both input and output", input.display());
+                    pairs.push(FromToCommand { input, output });
+                }
+                pairs
+            };
+
+            let mk_scale_pairs = |m: &clap::ArgMatches| -> Vec<ScaleCommand> {
+                let factor = mk_scale(m, "ini-input", "ini-output").factor;
+                mk_pairs(m).into_iter().map(|FromToCommand { input, output }| ScaleCommand { input, factor, output }).collect()
+            };
+
             match m.subcommand() {
                 ("modpack", Some(m)) => AppCommand::Modpack(match m.subcommand() {
                     ("install", Some(m)) => {
                         let source = mk_path(m, "dir-source");
                         let destination = mk_path(m, "dir-destination");
-                        ModpackCommand::Install(ModpackInstallCommand { source, destination })
+                        let visibility = u8::from_str(m.value_of("visibility").unwrap()).expect("Cannot parse visibility as integer");
+                        assert!(AppSettings::VISIBILITY_VALUES.contains(&visibility), "Invalid visibility {}, allowed values are: {:?}", visibility, AppSettings::VISIBILITY_VALUES);
+                        let exclude = m.values_of("exclude").map(|vs| vs.map(String::from).collect()).unwrap_or_default();
+                        let only = m.values_of("only").map(|vs| vs.map(String::from).collect()).unwrap_or_default();
+                        let max_buildings = m.value_of("max-buildings").map(|n| {
+                            let n = usize::from_str(n).expect("Cannot parse max-buildings as integer");
+                            assert!(n > 0, "max-buildings must be greater than zero");
+                            n
+                        });
+                        let assets_dirs = {
+                            let names = m.values_of("assets-dir-name").map(|vs| vs.collect()).unwrap_or_else(|| vec!["dds", "nmf"]);
+                            let mut names = names.into_iter().map(|n| {
+                                let mut cs = std::path::Path::new(n).components();
+                                let plain = matches!((cs.next(), cs.next()), (Some(std::path::Component::Normal(_)), None));
+                                assert!(plain, "Asset directory name must be a single directory name (got '{}')", n);
+                                assert!(!n.bytes().all(|b| b.is_ascii_digit()), "Asset directory name cannot be a number, it would clash with mod ids (got '{}')", n);
+                                String::from(n)
+                            });
+                            let (dds, nmf) = (names.next().unwrap(), names.next().unwrap());
+                            assert!(dds != nmf, "Asset directory names must differ (got '{}' twice)", dds);
+                            (dds, nmf)
+                        };
+                        ModpackCommand::Install(ModpackInstallCommand { source, destination, visibility, exclude, only, max_buildings, link_assets: m.is_present("symlink-assets"), timings: m.is_present("timings"), clean: m.is_present("clean"), assets_dirs })
                     },
-                    ("validate", Some(m)) => ModpackCommand::Validate(mk_path(m, "dir-source")),
+                    ("validate", Some(m)) => ModpackCommand::Validate(mk_path(m, "dir-source"), m.value_of("report").map(|_| mk_path(m, "report")), m.is_present("summary-only")),
+                    ("stats", Some(m))    => ModpackCommand::Stats(mk_path(m, "dir-source")),
+                    ("new-building", Some(m)) => ModpackCommand::NewBuilding(mk_path(m, "dir")),
                     (cname, _)            => panic!("Unknown modpack subcommand '{}'", cname)
                 }),
 
                 ("ini", Some(m)) => AppCommand::Ini( match m.subcommand() {
                     ("parse", Some(m)) => match m.subcommand() {
-                        ("building",     Some(m)) => IniCommand::ParseBuilding(mk_path(m, "path")),
+                        ("building",     Some(m)) => IniCommand::ParseBuilding(mk_path(m, "path"), m.is_present("summary"), m.value_of("filter").map(String::from)),
                         ("renderconfig", Some(m)) => IniCommand::ParseRender(mk_path(m, "path")),
-                        ("mtl",          Some(m)) => IniCommand::ParseMtl(mk_path(m, "path")),
+                        ("mtl",          Some(m)) => IniCommand::ParseMtl(mk_path(m, "path"), m.is_present("resolve")),
                         (cname, _)                => panic!("Unknown ini parse subcommand '{}'" , cname)
                     },
                     ("scale", Some(m)) => match m.subcommand() {
-                        ("building", Some(m))     => IniCommand::ScaleBuilding(mk_scale(m, "ini-input", "ini-output")),
-                        ("renderconfig", Some(m)) => IniCommand::ScaleRender(mk_scale(m, "ini-input", "ini-output")),
+                        ("building", Some(m))     => IniCommand::ScaleBuilding(mk_scale_pairs(m), mk_cost_scaling(m), mk_precision(m), m.is_present("lenient"), m.is_present("annotated")),
+                        ("renderconfig", Some(m)) => IniCommand::ScaleRender(mk_scale_pairs(m), mk_precision(m), m.is_present("annotated")),
                         (cname, _)                => panic!("Unknown ini scale subcommand '{}'" , cname)
                     },
                     ("mirror", Some(m)) => match m.subcommand() {
-                        ("building", Some(m))     => IniCommand::MirrorBuilding(mk_from_to(m, "ini-input", "ini-output")),
-                        ("renderconfig", Some(m)) => IniCommand::MirrorRender(mk_from_to(m, "ini-input", "ini-output")),
+                        ("building", Some(m))     => IniCommand::MirrorBuilding(mk_pairs(m), mk_precision(m), m.is_present("lenient"), m.is_present("annotated")),
+                        ("renderconfig", Some(m)) => IniCommand::MirrorRender(mk_pairs(m), mk_precision(m), m.is_present("annotated")),
                         (cname, _)                => panic!("Unknown ini mirror subcommand '{}'" , cname)
                     },
+                    ("merge", Some(m)) => match m.subcommand() {
+                        ("building", Some(m))     => {
+                            let base = mk_path(m, "ini-base");
+                            let overlay = mk_path(m, "ini-overlay");
+                            let output = mk_path(m, "ini-output");
+                            assert!(output != base && output != overlay, "ini-output cannot be the same as the input files");
+                            IniCommand::MergeBuilding(MergeCommand { base, overlay, output })
+                        },
+                        (cname, _)                => panic!("Unknown ini merge subcommand '{}'" , cname)
+                    },
+                    ("list-types", Some(_)) => IniCommand::ListTypes,
+                    ("get-models", Some(m)) => IniCommand::GetModels(mk_path(m, "path")),
+                    ("coverage",   Some(m)) => IniCommand::Coverage(mk_path(m, "path")),
                     (cname, _) => panic!("Unknown ini subcommand '{}'" , cname)
                 }),
 
                 ("mod-building", Some(m)) => AppCommand::ModBuilding(match m.subcommand() {
-                    ("validate", Some(m)) => ModCommand::Validate(mk_path(m, "dir-input")),
-                    ("scale", Some(m))    => ModCommand::Scale(mk_scale(m, "dir-input", "dir-output")),
-                    ("mirror", Some(m))   => ModCommand::Mirror(mk_from_to(m, "dir-input", "dir-output")),
+                    ("validate", Some(m)) => ModCommand::Validate(mk_path(m, "dir-input"), m.is_present("strict"), m.is_present("fix"), m.is_present("node-refs")),
+                    ("scale", Some(m))    => {
+                        let parts = ModParts { models: !m.is_present("ini-only"), ini: !m.is_present("models-only") };
+                        ModCommand::Scale(mk_scale(m, "dir-input", "dir-output"), parts, mk_cost_scaling(m), mk_precision(m), m.is_present("dry-run"))
+                    },
+                    ("mirror", Some(m))   => ModCommand::Mirror(mk_from_to(m, "dir-input", "dir-output"), mk_precision(m), m.is_present("dry-run")),
+                    ("pack", Some(m))     => ModCommand::Pack(mk_from_to(m, "dir-input", "zip-output")),
                     (cname, _)            => panic!("Unknown mod subcommand '{}'" , cname)
                 }),
 
                 ("nmf", Some(m)) => AppCommand::Nmf(match m.subcommand() {
-                    ("show",     Some(m)) => NmfCommand::Show(mk_path(m, "nmf-path")),
-                    ("to-obj",   Some(m)) => NmfCommand::ToObj(   mk_from_to(m, "nmf-input", "obj-output")),
+                    ("show",     Some(m)) => NmfCommand::Show(mk_path(m, "nmf-path"), m.is_present("matrix")),
+                    ("compare",  Some(m)) => NmfCommand::Compare(mk_path(m, "nmf-a"), mk_path(m, "nmf-b")),
+                    ("histogram", Some(m)) => NmfCommand::Histogram(mk_path(m, "nmf-input")),
+                    ("verify-length", Some(m)) => NmfCommand::VerifyLength(mk_path(m, "nmf-input")),
+                    ("check",    Some(m)) => {
+                        let uv_bound = f32::from_str(m.value_of("uv-bound").unwrap()).expect("Cannot parse uv-bound as float");
+                        NmfCommand::Check(mk_path(m, "nmf-input"), uv_bound)
+                    },
+                    ("classify", Some(m)) => NmfCommand::Classify(m.values_of("nmf-path").unwrap().map(PathBuf::from).collect()),
+                    ("to-obj",   Some(m)) => {
+                        let index_base = match m.value_of("relative-to").unwrap() {
+                            "object" => wrsr_mt::nmf::export::ObjIndexBase::Object,
+                            _        => wrsr_mt::nmf::export::ObjIndexBase::File,
+                        };
+                        NmfCommand::ToObj(mk_from_to(m, "nmf-input", "obj-output"), m.is_present("group-by-submaterial"), index_base)
+                    },
+                    ("export",   Some(m)) => {
+                        let format = match m.value_of("format").unwrap() {
+                            "stl" => ExportFormat::Stl,
+                            _     => ExportFormat::Obj,
+                        };
+                        NmfCommand::Export(mk_from_to(m, "nmf-input", "output"), format)
+                    },
+                    ("extract-object", Some(m)) => NmfCommand::ExtractObject(ExtractObjectCommand {
+                        input:  mk_path(m, "nmf-input"),
+                        object: m.value_of("object-name").unwrap().to_string(),
+                        output: mk_path(m, "obj-output"),
+                    }),
+                    ("dump-object", Some(m)) => NmfCommand::DumpObject(ExtractObjectCommand {
+                        input:  mk_path(m, "nmf-input"),
+                        object: m.value_of("object-name").unwrap().to_string(),
+                        output: mk_path(m, "bin-output"),
+                    }),
+                    ("rename-object", Some(m)) => NmfCommand::RenameObject(RenameObjectCommand {
+                        input:    mk_path(m, "nmf-input"),
+                        object:   m.value_of("object-name").unwrap().to_string(),
+                        new_name: m.value_of("new-name").unwrap().to_string(),
+                        output:   mk_path(m, "nmf-output"),
+                    }),
+                    ("scale",    Some(m)) if m.is_present("validate-only") =>
+                        NmfCommand::CheckReversible(mk_path(m, "nmf-input"), NmfTransform::Scale(parse_factor(m.value_of("factor").unwrap()))),
+                    ("mirror",   Some(m)) if m.is_present("validate-only") =>
+                        NmfCommand::CheckReversible(mk_path(m, "nmf-input"), NmfTransform::Mirror),
                     ("scale",    Some(m)) => NmfCommand::Scale(   mk_scale(  m, "nmf-input", "nmf-output")),
                     ("mirror",   Some(m)) => NmfCommand::Mirror(  mk_from_to(m, "nmf-input", "nmf-output")),
                     ("optimize", Some(m)) => NmfCommand::Optimize(mk_from_to(m, "nmf-input", "nmf-output")),
+                    ("repair",   Some(m)) => NmfCommand::Repair(mk_from_to(m, "nmf-input", "nmf-output")),
+                    ("gen-mtl",  Some(m)) => NmfCommand::GenMtl(mk_from_to(m, "nmf-input", "mtl-output"), m.is_present("used-only")),
+                    ("normalize-submaterials", Some(m)) => NmfCommand::NormalizeSubmaterials(mk_from_to(m, "nmf-input", "nmf-output")),
+                    ("uv",       Some(m)) => {
+                        let get = |name| f32::from_str(m.value_of(name).unwrap()).unwrap_or_else(|_| panic!("Cannot parse {} as float", name));
+                        NmfCommand::Uv(mk_from_to(m, "nmf-input", "nmf-output"), (get("scale-u"), get("scale-v")), (get("offset-u"), get("offset-v")))
+                    },
+                    ("recalc-normals", Some(m)) => {
+                        let mode = if m.is_present("flat") { wrsr_mt::nmf::NormalsMode::Flat } else { wrsr_mt::nmf::NormalsMode::Smooth };
+                        NmfCommand::RecalcNormals(mk_from_to(m, "nmf-input", "nmf-output"), mode)
+                    },
+                    ("transform-building", Some(m)) => {
+                        let transform = match m.value_of("transform").unwrap() {
+                            "mirror" => NmfTransform::Mirror,
+                            factor   => NmfTransform::Scale(parse_factor(factor)),
+                        };
+                        NmfCommand::TransformBuilding(TransformBuildingCommand { render: mk_path(m, "render-input"), transform, output: mk_path(m, "dir-output") })
+                    },
 
                     (cname, _) => panic!("Unknown nmf subcommand '{}'" , cname)
                 }),
@@ -347,8 +896,7 @@ lazy_static! {
         };
 
         AppSettings {
-            path_stock,
-            path_workshop,
+            roots,
             command
         }
     };
@@ -1,6 +1,8 @@
 use std::fmt::{Formatter, Error, Display};
 use std::io::{Write};
 
+use crate::ini::common::FloatParam;
+
 use super::{BuildingType,
             BuildingSubtype,
             ResourceVisualization,
@@ -11,15 +13,14 @@ use super::{BuildingType,
 type IOResult = Result<(), std::io::Error>;
 
 impl Token<'_> {
-    pub fn serialize_token<W: Write>(&self, mut wr: W) -> IOResult {
+    pub fn serialize_token<W: Write>(&self, mut wr: W, float_prec: Option<usize>) -> IOResult {
 
-        // float serialization precision
-        const PREC: usize = 4;
+        let prec = float_prec.unwrap_or(crate::ini::POINT_PRECISION);
 
         macro_rules! write_pts {
             ($pfx:expr, $($i:ident),+) => {{
                 write!(wr, "{}", $pfx)?;
-                $(write!(wr, "\r\n{:.prec$} {:.prec$} {:.prec$}", $i.x, $i.y, $i.z, prec = PREC)?;)+
+                $(write!(wr, "\r\n{:.prec$} {:.prec$} {:.prec$}", $i.x, $i.y, $i.z, prec = prec)?;)+
                 Ok(())
             }};
         }
@@ -27,7 +28,7 @@ impl Token<'_> {
         macro_rules! write_x_pts {
             ($pfx:expr, $x:expr, $($i:ident),+) => {{
                 write!(wr, "{} {}", $pfx, $x)?;
-                $(write!(wr, "\r\n{:.prec$} {:.prec$} {:.prec$}", $i.x, $i.y, $i.z, prec = PREC)?;)+
+                $(write!(wr, "\r\n{:.prec$} {:.prec$} {:.prec$}", $i.x, $i.y, $i.z, prec = prec)?;)+
                 Ok(())
             }};
         }
@@ -35,7 +36,7 @@ impl Token<'_> {
         macro_rules! write_tag_pts {
             ($pfx:expr, $tag:expr, $($i:ident),+) => {{
                 write!(wr, "{}{}", $pfx, $tag)?;
-                $(write!(wr, "\r\n{:.prec$} {:.prec$} {:.prec$}", $i.x, $i.y, $i.z, prec = PREC)?;)+
+                $(write!(wr, "\r\n{:.prec$} {:.prec$} {:.prec$}", $i.x, $i.y, $i.z, prec = prec)?;)+
                 Ok(())
             }};
         }
@@ -59,28 +60,28 @@ impl Token<'_> {
             Self::OffsetConnection((i, p))         => write_x_pts!(Self::OFFSET_CONNECTION_XYZW, i, p),
 
             Self::ConnectionsSpace(r)                => write!(wr, "{}\r\n{:.prec$} {:.prec$}\r\n{:.prec$} {:.prec$}",         
-                                                               Self::CONNECTIONS_SPACE, r.x1, r.z1, r.x2, r.z2, prec = PREC),
+                                                               Self::CONNECTIONS_SPACE, r.x1, r.z1, r.x2, r.z2, prec = prec),
             Self::ConnectionsRoadDeadSquare(r)       => write!(wr, "{}\r\n{:.prec$} {:.prec$}\r\n{:.prec$} {:.prec$}",
-                                                               Self::CONNECTIONS_ROAD_DEAD_SQUARE,    r.x1, r.z1, r.x2, r.z2, prec = PREC),
+                                                               Self::CONNECTIONS_ROAD_DEAD_SQUARE,    r.x1, r.z1, r.x2, r.z2, prec = prec),
             Self::ConnectionsAirportDeadSquare(r)    => write!(wr, "{}\r\n{:.prec$} {:.prec$}\r\n{:.prec$} {:.prec$}",
-                                                               Self::CONNECTIONS_AIRPORT_DEAD_SQUARE, r.x1, r.z1, r.x2, r.z2, prec = PREC),
+                                                               Self::CONNECTIONS_AIRPORT_DEAD_SQUARE, r.x1, r.z1, r.x2, r.z2, prec = prec),
             Self::ConnectionsWaterDeadSquare((x, r)) => write!(wr, "{}\r\n{:.prec$}\r\n{:.prec$} {:.prec$}\r\n{:.prec$} {:.prec$}",
-                                                               Self::CONNECTIONS_ROAD_DEAD_SQUARE, x, r.x1, r.z1, r.x2, r.z2, prec = PREC),
+                                                               Self::CONNECTIONS_WATER_DEAD_SQUARE, x, r.x1, r.z1, r.x2, r.z2, prec = prec),
 
             Self::Particle((t, p, a, s))           => write!(wr, "{} {} {:.prec$} {:.prec$} {:.prec$} {:.prec$} {:.prec$}", 
-                                                             Self::PARTICLE, t, p.x, p.y, p.z, a, s, prec = PREC),
+                                                             Self::PARTICLE, t, p.x, p.y, p.z, a, s, prec = prec),
             Self::ParticleReactor(p)               => write_pts!(Self::PARTICLE_REACTOR, p),
             Self::ParticleSnowRemove((p, i, r))    => write!(wr, "{} {:.prec$} {:.prec$} {:.prec$} {} {:.prec$}", 
-                                                             Self::PARTICLE_SNOW_REMOVE, p.x, p.y, p.z, i, r, prec = PREC),
+                                                             Self::PARTICLE_SNOW_REMOVE, p.x, p.y, p.z, i, r, prec = prec),
             Self::TextCaption((a, b))              => write_pts!(Self::TEXT_CAPTION, a, b),
             Self::WorkerRenderingArea((a, b))      => write_pts!(Self::WORKER_RENDERING_AREA, a, b),
             Self::ResourceVisualization(ResourceVisualization { storage_id, position: p, rotation, scale: s, numstep_x: (x1, x2), numstep_z: (z1, z2) }) => 
                 write!(wr, "{} {}\nposition {:.prec$} {:.prec$} {:.prec$}\n\
                                   rotation {:.prec$}\n\
                                   scale {:.prec$} {:.prec$} {:.prec$}\n\
-                                  numstep_x {:.prec$} {}\n\
-                                  numstep_t {:.prec$} {}", 
-                       Self::RESOURCE_VISUALIZATION, storage_id, p.x, p.y, p.z, rotation, s.x, s.y, s.z, x1, x2, z1, z2, prec = PREC),
+                                  numstepx {:.prec$} {}\n\
+                                  numstept {:.prec$} {}", 
+                       Self::RESOURCE_VISUALIZATION, storage_id, p.x, p.y, p.z, rotation, s.x, s.y, s.z, x1, x2, z1, z2, prec = prec),
             Self::ResourceIncreasePoint((i, p))        => write_x_pts!(Self::RESOURCE_INCREASE_POINT, i, p),
             Self::ResourceIncreaseConvPoint((i, a, b)) => write_x_pts!(Self::RESOURCE_INCREASE_CONV_POINT, i, a, b),
             Self::ResourceFillingPoint(p)              => write_pts!(Self::RESOURCE_FILLING_POINT, p),
@@ -88,6 +89,11 @@ impl Token<'_> {
 
             Self::CostWorkVehicleStation((a, b))       => write_pts!(Self::COST_WORK_VEHICLE_STATION, a, b),
 
+            Self::HeliportArea(x)                  => write!(wr, "{} {}", Self::HELIPORT_AREA, FloatParam(*x, float_prec)),
+            Self::HarborTerrainFrom(x)             => write!(wr, "{} {}", Self::HARBOR_OVER_TERRAIN_FROM, FloatParam(*x, float_prec)),
+            Self::HarborWaterFrom(x)               => write!(wr, "{} {}", Self::HARBOR_OVER_WATER_FROM, FloatParam(*x, float_prec)),
+            Self::HarborExtendWhenBuilding(x)      => write!(wr, "{} {}", Self::HARBOR_EXTEND_WHEN_BULDING, FloatParam(*x, float_prec)),
+
             t => write!(wr, "{}", t)
         }
 
@@ -236,6 +242,8 @@ impl Display for Token<'_> {
             Self::CostResourceAuto((t, x))         => write!(f, "{} {} {}", Self::COST_RESOURCE_AUTO, t, x),
             Self::CostWorkVehicleStation((a, b))   => write!(f, "{} {} {}", Self::COST_WORK_VEHICLE_STATION, a, b),
             Self::CostWorkVehicleStationNode(p)    => write!(f, "{} {}",    Self::COST_WORK_VEHICLE_STATION_NODE, p),
+
+            Self::Unknown(s)                       => write!(f, "{}",       s),
         }
     }
 }
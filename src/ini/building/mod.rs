@@ -1,9 +1,9 @@
 mod display;
 mod parse;
 
-use crate::ini::common::{Point3f, Rect, QuotedStringParam, IdStringParam, CostKeywordParam};
+use crate::ini::common::{Point3f, Rect, QuotedStringParam, IdStringParam, CostKeywordParam, StrValue};
 
-pub use parse::{parse_tokens, parse_tokens_strict};
+pub use parse::{parse_tokens, parse_tokens_strict, parse_tokens_lenient, coverage};
 
 //#[derive(Clone)]
 pub enum Token<'a> {
@@ -145,6 +145,9 @@ pub enum Token<'a> {
 
     CostWorkVehicleStation((Point3f, Point3f)),
     CostWorkVehicleStationNode(IdStringParam<'a>),
+
+    // Token of a type this tool does not know (lenient parsing only), kept as the raw chunk
+    Unknown(&'a str),
 }
 
 
@@ -288,10 +291,218 @@ impl<'a> Token<'a> {
 }
 
 
+impl Token<'_> {
+    // Tokens that can appear in building.ini multiple times (stations, connections, costs, etc.).
+    // Any other token is expected to be defined once per building.
+    pub fn is_repeatable(&self) -> bool {
+        matches!(self,
+            Self::Consumption(_)                 | Self::ConsumptionPerSec(_)            | Self::Production(_)            |
+            Self::Storage(_)                     | Self::StorageSpecial(_)               | Self::StorageFuel(_)           |
+            Self::StorageExport(_)               | Self::StorageImport(_)                | Self::StorageImportCarplant(_) |
+            Self::StorageExportSpecial(_)        | Self::StorageImportSpecial(_)         | Self::StorageDemandBasic(_)    |
+            Self::StorageDemandMediumAdvanced(_) | Self::StorageDemandAdvanced(_)        | Self::StorageDemandHotel(_)    |
+            Self::StorageLivingAuto(_)           |
+            Self::VehicleStation(_)              | Self::VehicleStationDetourPoint(_)    | Self::VehicleStationDetourPid(_) |
+            Self::VehicleParking(_)              | Self::VehicleParkingDetourPoint(_)    | Self::VehicleParkingDetourPid(_) |
+            Self::VehicleParkingPersonal(_)      |
+            Self::AirplaneStation(_)             | Self::HeliportStation(_)              | Self::ShipStation(_)           |
+            Self::Connection2Points(_)           | Self::Connection1Point(_)             | Self::OffsetConnection(_)      |
+            Self::Particle(_)                    | Self::ParticleReactor(_)              | Self::ParticleSnowRemove(_)    |
+            Self::ResourceVisualization(_)       | Self::ResourceIncreasePoint(_)        | Self::ResourceIncreaseConvPoint(_) |
+            Self::ResourceFillingPoint(_)        | Self::ResourceFillingConvPoint(_)     |
+            Self::AnimationMesh(_)               | Self::UndergroundMesh(_)              |
+            Self::CostWork(_)                    | Self::CostWorkBuildingNode(_)         | Self::CostWorkBuildingKeyword(_) |
+            Self::CostResource(_)                | Self::CostResourceAuto(_)             |
+            Self::CostWorkVehicleStation(_)      | Self::CostWorkVehicleStationNode(_)
+        )
+    }
+
+    pub const CATEGORIES: &'static [&'static str] = &[
+        "general", "workers", "production", "utilities", "storage", "vehicles",
+        "connection", "attractive", "pollution", "visual", "cost", "unknown"
+    ];
+
+    // Coarse group of the token (one of CATEGORIES), used for parse summaries and filtering
+    pub fn category(&self) -> &'static str {
+        use Token as T;
+        match self {
+            T::NameStr(_) | T::Name(_) | T::BuildingType(_) | T::BuildingSubtype(_) | T::HeatEnable | T::HeatDisable |
+            T::CivilBuilding | T::MonumentTrespass | T::QualityOfLiving(_) => "general",
+
+            T::WorkersNeeded(_) | T::ProfessorsNeeded(_) | T::CitizenAbleServe(_) => "workers",
+
+            T::Consumption(_) | T::ConsumptionPerSec(_) | T::Production(_) | T::ProductionSun(_) | T::ProductionWind(_) |
+            T::SeasonalTempMin(_) | T::SeasonalTempMax(_) | T::ResourceSource(_) => "production",
+
+            T::EleConsumWorkerFactorNight(_) | T::EleConsumWorkerFactorBase(_) | T::EleConsumServeFactorNight(_) |
+            T::EleConsumServeFactorBase(_) | T::EleConsumCargoLoadFactor(_) | T::EleConsumCargoUnloadFactor(_) |
+            T::NoEleWorkFactorBase(_) | T::NoEleWorkFactorNight(_) | T::NoHeatWorkFactor(_) => "utilities",
+
+            T::Storage(_) | T::StorageSpecial(_) | T::StorageFuel(_) | T::StorageExport(_) | T::StorageImport(_) |
+            T::StorageImportCarplant(_) | T::StorageExportSpecial(_) | T::StorageImportSpecial(_) | T::StorageDemandBasic(_) |
+            T::StorageDemandMediumAdvanced(_) | T::StorageDemandAdvanced(_) | T::StorageDemandHotel(_) |
+            T::StoragePackFrom(_) | T::StorageUnpackTo(_) | T::StorageLivingAuto(_) => "storage",
+
+            T::EngineSpeed(_) | T::CablewayHeavy | T::CablewayLight | T::VehicleLoadingFactor(_) | T::VehicleUnloadingFactor(_) |
+            T::RoadNotFlip | T::RoadElectric | T::VehicleCannotSelect | T::LongTrains | T::WorkingVehiclesNeeded(_) |
+            T::VehicleStation(_) | T::VehicleStationNotBlock | T::VehicleStationDetourPoint(_) | T::VehicleStationDetourPid(_) |
+            T::VehicleParking(_) | T::VehicleParkingDetourPoint(_) | T::VehicleParkingDetourPid(_) | T::VehicleParkingPersonal(_) |
+            T::AirplaneStation(_) | T::HeliportStation(_) | T::ShipStation(_) | T::HeliportArea(_) |
+            T::HarborTerrainFrom(_) | T::HarborWaterFrom(_) | T::HarborExtendWhenBuilding(_) => "vehicles",
+
+            T::Connection2Points(_) | T::Connection1Point(_) | T::OffsetConnection(_) | T::ConnectionRailDeadend |
+            T::ConnectionsSpace(_) | T::ConnectionsRoadDeadSquare(_) | T::ConnectionsAirportDeadSquare(_) |
+            T::ConnectionsWaterDeadSquare(_) => "connection",
+
+            T::AttractionType(_) | T::AttractionRememberUsage | T::AttractiveScoreBase(_) | T::AttractiveScoreAlcohol(_) |
+            T::AttractiveScoreCulture(_) | T::AttractiveScoreReligion(_) | T::AttractiveScoreSport(_) |
+            T::AttractiveFactorNature(_) | T::AttractiveFactorNatureAdd(_) | T::AttractiveFactorPollution(_) |
+            T::AttractiveFactorPollutionAdd(_) | T::AttractiveFactorSight(_) | T::AttractiveFactorSightAdd(_) |
+            T::AttractiveFactorWater(_) | T::AttractiveFactorWaterAdd(_) => "attractive",
+
+            T::PollutionHigh | T::PollutionMedium | T::PollutionSmall => "pollution",
+
+            T::Particle(_) | T::ParticleReactor(_) | T::ParticleSnowRemove(_) | T::TextCaption(_) | T::WorkerRenderingArea(_) |
+            T::ResourceVisualization(_) | T::ResourceIncreasePoint(_) | T::ResourceIncreaseConvPoint(_) |
+            T::ResourceFillingPoint(_) | T::ResourceFillingConvPoint(_) | T::WorkingSfx(_) | T::AnimationFps(_) |
+            T::AnimationMesh(_) | T::UndergroundMesh(_) => "visual",
+
+            T::CostWork(_) | T::CostWorkBuildingNode(_) | T::CostWorkBuildingKeyword(_) | T::CostWorkBuildingAll |
+            T::CostResource(_) | T::CostResourceAuto(_) | T::CostWorkVehicleStation(_) | T::CostWorkVehicleStationNode(_) => "cost",
+
+            T::Unknown(_) => "unknown",
+        }
+    }
+
+    // All positional points of the token (in the order they appear in the file).
+    // This is the single list of point-carrying tokens used by coordinate transforms.
+    pub fn points_mut(&mut self) -> [Option<&mut Point3f>; 2] {
+        use Token as T;
+        match self {
+            T::VehicleStation((p1, p2))               => [Some(p1), Some(p2)],
+            T::VehicleStationDetourPoint(p1)          => [Some(p1), None],
+            T::VehicleStationDetourPid((_, p1))       => [Some(p1), None],
+            T::VehicleParking((p1, p2))               => [Some(p1), Some(p2)],
+            T::VehicleParkingDetourPoint(p1)          => [Some(p1), None],
+            T::VehicleParkingDetourPid((_, p1))       => [Some(p1), None],
+            T::VehicleParkingPersonal((p1, p2))       => [Some(p1), Some(p2)],
+
+            T::AirplaneStation((_, p1, p2))           => [Some(p1), Some(p2)],
+            T::HeliportStation((p1, p2))              => [Some(p1), Some(p2)],
+            T::ShipStation((p1, p2))                  => [Some(p1), Some(p2)],
+
+            T::Connection2Points((_, p1, p2))         => [Some(p1), Some(p2)],
+            T::Connection1Point((_, p1))              => [Some(p1), None],
+            T::OffsetConnection((_, p1))              => [Some(p1), None],
+
+            T::Particle((_, p1, _, _))                => [Some(p1), None],
+            T::ParticleReactor(p1)                    => [Some(p1), None],
+            T::ParticleSnowRemove((p1, _, _))         => [Some(p1), None],
+
+            T::TextCaption((p1, p2))                  => [Some(p1), Some(p2)],
+            T::WorkerRenderingArea((p1, p2))          => [Some(p1), Some(p2)],
+            T::ResourceVisualization(rv)              => [Some(&mut rv.position), None],
+            T::ResourceIncreasePoint((_, p1))         => [Some(p1), None],
+            T::ResourceIncreaseConvPoint((_, p1, p2)) => [Some(p1), Some(p2)],
+            T::ResourceFillingPoint(p1)               => [Some(p1), None],
+            T::ResourceFillingConvPoint((p1, p2))     => [Some(p1), Some(p2)],
+
+            T::CostWorkVehicleStation((p1, p2))       => [Some(p1), Some(p2)],
+
+            // No wildcard here on purpose: a newly added token must be explicitly put into one of the groups
+            T::NameStr(_) | T::Name(_) | T::BuildingType(_) | T::BuildingSubtype(_) | T::HeatEnable
+          | T::HeatDisable | T::CivilBuilding | T::MonumentTrespass | T::QualityOfLiving(_)
+          | T::WorkersNeeded(_) | T::ProfessorsNeeded(_) | T::CitizenAbleServe(_) | T::Consumption(_)
+          | T::ConsumptionPerSec(_) | T::Production(_) | T::ProductionSun(_) | T::ProductionWind(_)
+          | T::SeasonalTempMin(_) | T::SeasonalTempMax(_) | T::EleConsumWorkerFactorNight(_)
+          | T::EleConsumWorkerFactorBase(_) | T::EleConsumServeFactorNight(_) | T::EleConsumServeFactorBase(_)
+          | T::EleConsumCargoLoadFactor(_) | T::EleConsumCargoUnloadFactor(_) | T::NoEleWorkFactorBase(_)
+          | T::NoEleWorkFactorNight(_) | T::NoHeatWorkFactor(_) | T::EngineSpeed(_) | T::CablewayHeavy
+          | T::CablewayLight | T::ResourceSource(_) | T::Storage(_) | T::StorageSpecial(_) | T::StorageFuel(_)
+          | T::StorageExport(_) | T::StorageImport(_) | T::StorageImportCarplant(_) | T::StorageExportSpecial(_)
+          | T::StorageImportSpecial(_) | T::StorageDemandBasic(_) | T::StorageDemandMediumAdvanced(_)
+          | T::StorageDemandAdvanced(_) | T::StorageDemandHotel(_) | T::StoragePackFrom(_)
+          | T::StorageUnpackTo(_) | T::StorageLivingAuto(_) | T::VehicleLoadingFactor(_)
+          | T::VehicleUnloadingFactor(_) | T::RoadNotFlip | T::RoadElectric | T::VehicleCannotSelect
+          | T::LongTrains | T::WorkingVehiclesNeeded(_) | T::VehicleStationNotBlock | T::HeliportArea(_)
+          | T::HarborTerrainFrom(_) | T::HarborWaterFrom(_) | T::HarborExtendWhenBuilding(_)
+          | T::ConnectionRailDeadend | T::ConnectionsSpace(_) | T::ConnectionsRoadDeadSquare(_)
+          | T::ConnectionsAirportDeadSquare(_) | T::ConnectionsWaterDeadSquare(_) | T::AttractionType(_)
+          | T::AttractionRememberUsage | T::AttractiveScoreBase(_) | T::AttractiveScoreAlcohol(_)
+          | T::AttractiveScoreCulture(_) | T::AttractiveScoreReligion(_) | T::AttractiveScoreSport(_)
+          | T::AttractiveFactorNature(_) | T::AttractiveFactorNatureAdd(_) | T::AttractiveFactorPollution(_)
+          | T::AttractiveFactorPollutionAdd(_) | T::AttractiveFactorSight(_) | T::AttractiveFactorSightAdd(_)
+          | T::AttractiveFactorWater(_) | T::AttractiveFactorWaterAdd(_) | T::PollutionHigh | T::PollutionMedium
+          | T::PollutionSmall | T::WorkingSfx(_) | T::AnimationFps(_) | T::AnimationMesh(_)
+          | T::UndergroundMesh(_) | T::CostWork(_) | T::CostWorkBuildingNode(_) | T::CostWorkBuildingKeyword(_)
+          | T::CostWorkBuildingAll | T::CostResource(_) | T::CostResourceAuto(_)
+          | T::CostWorkVehicleStationNode(_) | T::Unknown(_)
+                => [None, None]
+        }
+    }
+}
+
+
+// All keywords of token parameters recognized by the parser, grouped by category
+pub const KEYWORD_TABLES: [(&str, &[&str]); 12] = [
+    ("Building types (TYPE_*)",                  BuildingType::KEYWORDS),
+    ("Building subtypes (SUBTYPE_*)",            BuildingSubtype::KEYWORDS),
+    ("Resources",                                ResourceType::KEYWORDS),
+    ("Resource sources (RESOURCE_SOURCE_*)",     ResourceSourceType::KEYWORDS),
+    ("Storage cargo types",                      StorageCargoType::KEYWORDS),
+    ("Construction phases",                      ConstructionPhase::KEYWORDS),
+    ("Construction auto-costs",                  ConstructionAutoCost::KEYWORDS),
+    ("Connections, 2 points (CONNECTION_*)",     Connection2PType::KEYWORDS),
+    ("Connections, 1 point (CONNECTION_*)",      Connection1PType::KEYWORDS),
+    ("Airplane stations (AIRPLANE_STATION_*)",   AirplaneStationType::KEYWORDS),
+    ("Attraction types (ATTRACTIVE_TYPE_*)",     AttractionType::KEYWORDS),
+    ("Particles",                                ParticleType::KEYWORDS),
+];
+
+
 impl<'t> super::IniToken for Token<'t> {
-    fn serialize<W: std::io::Write>(&self, wr: W) -> Result<(), std::io::Error> {
-        self.serialize_token(wr)
+    const SEPARATOR: &'static str = "\r\n$";
+
+    fn serialize<W: std::io::Write>(&self, wr: W, prec: Option<usize>) -> Result<(), std::io::Error> {
+        self.serialize_token(wr, prec)
+    }
+}
+
+
+// How much of a building.ini the parser understands (see coverage)
+pub struct Coverage<'a> {
+    pub tokens: usize,
+    // known type, all parameters parsed
+    pub parsed: usize,
+    // keywords of unknown token types (without '$') with their number of occurrences, in order of appearance
+    pub unknown: Vec<(&'a str, usize)>,
+    // known type, but the parameters failed to parse or were not parsed completely: (chunk, error)
+    pub failed: Vec<(&'a str, String)>,
+}
+
+impl Coverage<'_> {
+    pub fn percent(&self) -> f64 {
+        if self.tokens == 0 { 100.0 } else { self.parsed as f64 * 100.0 / self.tokens as f64 }
+    }
+}
+
+
+// Starting point for a new building.ini: name, type and a pedestrian connection, all meant to be edited
+pub fn write_stub<W: std::io::Write>(mut wr: W, name: &str) -> std::io::Result<()> {
+    use super::IniToken;
+
+    let tokens = [
+        Token::NameStr(QuotedStringParam(StrValue::Borrowed(name))),
+        Token::BuildingType(BuildingType::Shop),
+        Token::Connection2Points((Connection2PType::Pedestrian, Point3f { x: 0.0, y: 0.0, z: 0.0 }, Point3f { x: 1.0, y: 0.0, z: 0.0 })),
+    ];
+
+    write!(wr, "-- other TYPE_* and CONNECTION_* keywords are listed by 'ini list-types'")?;
+    for t in tokens.iter() {
+        write!(wr, "{}", Token::SEPARATOR)?;
+        t.serialize(&mut wr, None)?;
     }
+    write!(wr, "\r\n")
 }
 
 
@@ -421,6 +632,26 @@ impl BuildingType {
     const TYPE_SUBSTATION:               &'static str = "SUBSTATION";
     const TYPE_TRANSFORMATOR:            &'static str = "TRANSFORMATOR";
     const TYPE_UNIVERSITY:               &'static str = "UNIVERSITY";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::TYPE_AIRPLANE_GATE, Self::TYPE_AIRPLANE_PARKING, Self::TYPE_AIRPLANE_TOWER,
+        Self::TYPE_ATTRACTION, Self::TYPE_BROADCAST, Self::TYPE_CAR_DEALER, Self::TYPE_CARGO_STATION,
+        Self::TYPE_CHURCH, Self::TYPE_CITYHALL, Self::TYPE_CONSTRUCTION_OFFICE,
+        Self::TYPE_CONSTRUCTION_OFFICE_RAIL, Self::TYPE_CONTAINER_FACILITY, Self::TYPE_COOLING_TOWER,
+        Self::TYPE_CUSTOMHOUSE, Self::TYPE_DISTRIBUTION_OFFICE, Self::TYPE_ELETRIC_EXPORT,
+        Self::TYPE_ELETRIC_IMPORT, Self::TYPE_ENGINE, Self::TYPE_FACTORY, Self::TYPE_FARM, Self::TYPE_FIELD,
+        Self::TYPE_FIRESTATION, Self::TYPE_FORKLIFT_GARAGE, Self::TYPE_GARBAGE_OFFICE,
+        Self::TYPE_GAS_STATION, Self::TYPE_HEATING_ENDSTATION, Self::TYPE_HEATING_PLANT,
+        Self::TYPE_HEATING_SWITCH, Self::TYPE_HOSPITAL, Self::TYPE_HOTEL, Self::TYPE_KINDERGARTEN,
+        Self::TYPE_KINO, Self::TYPE_LIVING, Self::TYPE_MINE_BAUXITE, Self::TYPE_MINE_COAL,
+        Self::TYPE_MINE_GRAVEL, Self::TYPE_MINE_IRON, Self::TYPE_MINE_OIL, Self::TYPE_MINE_URANIUM,
+        Self::TYPE_MINE_WOOD, Self::TYPE_MONUMENT, Self::TYPE_PARKING, Self::TYPE_PASSANGER_STATION,
+        Self::TYPE_PEDESTRIAN_BRIDGE, Self::TYPE_POLICE_STATION, Self::TYPE_POLLUTION_METER,
+        Self::TYPE_POWERPLANT, Self::TYPE_PRODUCTION_LINE, Self::TYPE_PUB, Self::TYPE_RAIL_TRAFO,
+        Self::TYPE_RAILDEPO, Self::TYPE_ROADDEPO, Self::TYPE_SCHOOL, Self::TYPE_SHIP_DOCK, Self::TYPE_SHOP,
+        Self::TYPE_SPORT, Self::TYPE_STORAGE, Self::TYPE_SUBSTATION, Self::TYPE_TRANSFORMATOR,
+        Self::TYPE_UNIVERSITY
+    ];
 }
 
 
@@ -461,6 +692,13 @@ impl BuildingSubtype {
     const SUBTYPE_TECHNICAL:          &'static str = "TECHNICAL";
     const SUBTYPE_TELEVISION:         &'static str = "TELEVISION";
     const SUBTYPE_TROLLEYBUS:         &'static str = "TROLLEYBUS";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::SUBTYPE_AIRCUSTOM, Self::SUBTYPE_AIRPLANE, Self::SUBTYPE_CABLEWAY, Self::SUBTYPE_HOSTEL,
+        Self::SUBTYPE_MEDICAL, Self::SUBTYPE_RADIO, Self::SUBTYPE_RAIL, Self::SUBTYPE_RESTAURANT,
+        Self::SUBTYPE_ROAD, Self::SUBTYPE_SHIP, Self::SUBTYPE_SOVIET, Self::SUBTYPE_SPACE_FOR_VEHICLES,
+        Self::SUBTYPE_TECHNICAL, Self::SUBTYPE_TELEVISION, Self::SUBTYPE_TROLLEYBUS
+    ];
 }
 
 
@@ -498,6 +736,11 @@ impl StorageCargoType {
     //const HEATING:   &'static str = "RESOURCE_TRANSPORT_HEATING";
     const NUCLEAR1:  &'static str = "RESOURCE_TRANSPORT_NUCLEAR1";
     const NUCLEAR2:  &'static str = "RESOURCE_TRANSPORT_NUCLEAR2";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::PASSANGER, Self::CEMENT, Self::COVERED, Self::GRAVEL, Self::OIL, Self::OPEN, Self::COOLER,
+        Self::CONCRETE, Self::LIVESTOCK, Self::GENERAL, Self::VEHICLES, Self::NUCLEAR1, Self::NUCLEAR2
+    ];
 }
 
 
@@ -532,6 +775,13 @@ impl ParticleType {
     const FOUNTAIN_1          : &'static str = "fountain1";
     const FOUNTAIN_2          : &'static str = "fountain2";
     const FOUNTAIN_3          : &'static str = "fountain3";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::RESIDENTIAL_HEATING, Self::FACTORY_BIG_BLACK, Self::FACTORY_MEDIUM_BLACK,
+        Self::FACTORY_SMALL_BLACK, Self::FACTORY_BIG_GRAY, Self::FACTORY_MEDIUM_GRAY,
+        Self::FACTORY_SMALL_GRAY, Self::FACTORY_BIG_WHITE, Self::FACTORY_MEDIUM_WHITE,
+        Self::FACTORY_SMALL_WHITE, Self::FOUNTAIN_1, Self::FOUNTAIN_2, Self::FOUNTAIN_3
+    ];
 }
 
 
@@ -571,6 +821,13 @@ impl ConstructionPhase {
     const STEEL_LAYING:     &'static str = "SOVIET_CONSTRUCTION_STEEL_LAYING";
     const TUNNELING:        &'static str = "SOVIET_CONSTRUCTION_TUNNELING";
     const WIRE_LAYING:      &'static str = "SOVIET_CONSTRUCTION_WIRE_LAYING";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::ASPHALT_LAYING, Self::ASPHALT_ROLLING, Self::BOARDS_LAYING, Self::BRICKS_LAYING,
+        Self::BRIDGE_BUILDING, Self::GRAVEL_LAYING, Self::GROUNDWORKS, Self::INTERIOR_WORKS,
+        Self::PANELS_LAYING, Self::RAILWAY_LAYING, Self::ROOFTOP_BUILDING, Self::SKELETON_CASTING,
+        Self::STEEL_LAYING, Self::TUNNELING, Self::WIRE_LAYING
+    ];
 }
 
 
@@ -606,6 +863,12 @@ impl ConstructionAutoCost {
     const ROOF_WOOD_BRICK:    &'static str = "roof_woodbrick";
     const ROOF_STEEL:         &'static str = "roof_steel";
     const ROOF_WOOD_STEEL:    &'static str = "roof_woodsteel";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::GROUND, Self::GROUND_ASPHALT, Self::WALL_CONCRETE, Self::WALL_PANELS, Self::WALL_BRICK,
+        Self::WALL_STEEL, Self::WALL_WOOD, Self::TECH_STEEL, Self::ELECTRO_STEEL, Self::TECH_ELECTRO_STEEL,
+        Self::ROOF_WOOD_BRICK, Self::ROOF_STEEL, Self::ROOF_WOOD_STEEL
+    ];
 }
 
 
@@ -699,6 +962,16 @@ impl ResourceType {
     const WOOD:          &'static str = "wood";
     const WORKERS:       &'static str = "workers";
     const YELLOWCAKE:    &'static str = "yellowcake";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::ALCOHOL, Self::ALUMINA, Self::ALUMINIUM, Self::ASPHALT, Self::BAUXITE, Self::BITUMEN,
+        Self::BOARDS, Self::BRICKS, Self::CEMENT, Self::CHEMICALS, Self::CLOTHES, Self::COAL, Self::CONCRETE,
+        Self::CROPS, Self::ELECTRO_COMP, Self::ELECTRICITY, Self::ELECTRONICS, Self::FABRIC, Self::FOOD,
+        Self::FUEL, Self::GRAVEL, Self::HEAT, Self::IRON, Self::LIVESTOCK, Self::MECH_COMP, Self::MEAT,
+        Self::NUCLEAR_FUEL, Self::NUCLEAR_WASTE, Self::OIL, Self::PLASTIC, Self::PREFABS, Self::RAW_BAUXITE,
+        Self::RAW_COAL, Self::RAW_GRAVEL, Self::RAW_IRON, Self::STEEL, Self::UF_6, Self::URANIUM,
+        Self::VEHICLES, Self::WOOD, Self::WORKERS, Self::YELLOWCAKE
+    ];
 }
 
 
@@ -765,6 +1038,17 @@ impl Connection2PType {
     const CONN_ELECTRIC_L_IN:  &'static str = "ELETRIC_LOW_INPUT";
     const CONN_ELECTRIC_L_OUT: &'static str = "ELETRIC_LOW_OUTPUT";
     const CONN_FENCE:          &'static str = "FENCE";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::CONN_AIRROAD, Self::CONN_PED, Self::CONN_PED_NOTPICK, Self::CONN_ROAD,
+        Self::CONN_ROAD_ALLOWPASS, Self::CONN_ROAD_BORDER, Self::CONN_ROAD_IN, Self::CONN_ROAD_OUT,
+        Self::CONN_RAIL, Self::CONN_RAIL_ALLOWPASS, Self::CONN_RAIL_BORDER, Self::CONN_RAIL_HEIGHT,
+        Self::CONN_HEATING_BIG, Self::CONN_HEATING_SMALL, Self::CONN_STEAM_IN, Self::CONN_STEAM_OUT,
+        Self::CONN_PIPE_IN, Self::CONN_PIPE_OUT, Self::CONN_BULK_IN, Self::CONN_BULK_OUT,
+        Self::CONN_CABLEWAY, Self::CONN_FACTORY, Self::CONN_CONVEYOR_IN, Self::CONN_CONVEYOR_OUT,
+        Self::CONN_ELECTRIC_H_IN, Self::CONN_ELECTRIC_H_OUT, Self::CONN_ELECTRIC_L_IN,
+        Self::CONN_ELECTRIC_L_OUT, Self::CONN_FENCE
+    ];
 }
 
 
@@ -784,6 +1068,10 @@ impl Connection1PType {
     const WATER_DEAD:      &'static str = "WATER_DEAD";
     const AIRPORT_DEAD:    &'static str = "AIRPORT_DEAD";
     const ADVANCED_POINT:  &'static str = "ADVANCED_POINT";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::ROAD_DEAD, Self::PEDESTRIAN_DEAD, Self::WATER_DEAD, Self::AIRPORT_DEAD, Self::ADVANCED_POINT
+    ];
 }
 
 
@@ -800,6 +1088,11 @@ impl AirplaneStationType {
     const AIRPLANE_STATION_40M: &'static str = "40M";
     const AIRPLANE_STATION_50M: &'static str = "50M";
     const AIRPLANE_STATION_75M: &'static str = "75M";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::AIRPLANE_STATION_30M, Self::AIRPLANE_STATION_40M, Self::AIRPLANE_STATION_50M,
+        Self::AIRPLANE_STATION_75M
+    ];
 }
 
 #[derive(Clone, Copy)]
@@ -819,6 +1112,11 @@ impl AttractionType {
     const ATTRACTION_TYPE_SIGHT:   &'static str = "SIGHT";
     const ATTRACTION_TYPE_SWIM:    &'static str = "SWIM";
     const ATTRACTION_TYPE_ZOO:     &'static str = "ZOO";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::ATTRACTION_TYPE_CARUSEL, Self::ATTRACTION_TYPE_GALLERY, Self::ATTRACTION_TYPE_MUSEUM,
+        Self::ATTRACTION_TYPE_SIGHT, Self::ATTRACTION_TYPE_SWIM, Self::ATTRACTION_TYPE_ZOO
+    ];
 }
 
 
@@ -847,6 +1145,13 @@ impl ResourceSourceType {
     const RES_SOURCE_OPEN_BRICKS:     &'static str = "OPEN_BRICKS";
     const RES_SOURCE_OPEN_PANELS:     &'static str = "OPEN_PANELS";
     const RES_SOURCE_WORKERS:         &'static str = "WORKERS";
+
+    pub const KEYWORDS: &'static [&'static str] = &[
+        Self::RES_SOURCE_ASPHALT, Self::RES_SOURCE_CONCRETE, Self::RES_SOURCE_COVERED,
+        Self::RES_SOURCE_COVERED_ELECTRO, Self::RES_SOURCE_GRAVEL, Self::RES_SOURCE_OPEN,
+        Self::RES_SOURCE_OPEN_BOARDS, Self::RES_SOURCE_OPEN_BRICKS, Self::RES_SOURCE_OPEN_PANELS,
+        Self::RES_SOURCE_WORKERS
+    ];
 }
 
 
@@ -859,3 +1164,61 @@ pub struct ResourceVisualization {
     pub numstep_x: (f32, u32),
     pub numstep_z: (f32, u32),
 }
+
+
+#[cfg(test)]
+mod tests {
+    use crate::ini;
+
+    // Every token is marked as modified, so it is written by its serializer and not copied from the source
+    fn rewrite(src: &str) -> String {
+        let mut ini = ini::parse_building_ini(src).unwrap();
+        for t in ini.tokens_mut() {
+            t.token_mut();
+        }
+
+        let mut out = Vec::new();
+        ini.write_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn concrete_and_nuclear_storage_round_trip() {
+        let src = "$STORAGE RESOURCE_TRANSPORT_CONCRETE 100\r\n\
+                   $STORAGE RESOURCE_TRANSPORT_NUCLEAR1 2\r\n\
+                   $STORAGE_IMPORT RESOURCE_TRANSPORT_NUCLEAR2 3\r\n\
+                   end\r\n";
+        let out = rewrite(src);
+        assert_eq!(out.replace(".0000", ""), src);
+        assert_eq!(rewrite(&out), out);
+    }
+
+    #[test]
+    fn water_dead_square_round_trip() {
+        let out = rewrite("$CONNECTIONS_WATER_DEAD_SQUARE 1.5 -1 -2 3 4\r\nend\r\n");
+        assert_eq!(out, "$CONNECTIONS_WATER_DEAD_SQUARE\r\n1.5000\r\n-1.0000 -2.0000\r\n3.0000 4.0000\r\nend\r\n");
+        assert_eq!(rewrite(&out), out);
+    }
+
+    // 3 known, 3 unknown (2 distinct), 1 bad value, 1 with trailing junk
+    #[test]
+    fn coverage_counts_parsed_unknown_and_failed_tokens() {
+        let src = "$NAME_STR \"test\"\r\n\
+                   $WORKERS_NEEDED 10\r\n\
+                   $NEW_THING 1 2\r\n\
+                   $HELIPORT_AREA 5\r\n\
+                   $OTHER_NEW\r\n\
+                   $WORKERS_NEEDED many\r\n\
+                   $NEW_THING 3\r\n\
+                   $CIVIL_BUILDING junk\r\n\
+                   end\r\n";
+        let cov = super::coverage(src);
+
+        assert_eq!((cov.tokens, cov.parsed), (8, 3));
+        assert_eq!(cov.percent(), 37.5);
+        assert_eq!(cov.unknown, vec![("NEW_THING", 2), ("OTHER_NEW", 1)]);
+        assert_eq!(cov.failed.len(), 2);
+        assert!(cov.failed[0].0.contains("many"), "{:?}", cov.failed);
+        assert!(cov.failed[1].0.contains("junk") && cov.failed[1].1.contains("Remaining"), "{:?}", cov.failed);
+    }
+}
@@ -2,7 +2,8 @@ use lazy_static::lazy_static;
 use const_format::concatcp;
 use regex::Regex;
 
-use super::{BuildingType,
+use super::{Coverage,
+            BuildingType,
             BuildingSubtype,
             StorageCargoType,
             ParticleType,
@@ -21,13 +22,15 @@ use super::{BuildingType,
 
 use crate::ini::common::{ParseSlice, 
                          ParseResult, 
-                         ParseError, 
+                         StrictParseResult, 
                          Point3f,
                          Rect,
                          QuotedStringParam,
                          IdStringParam,
                          CostKeywordParam,
                          RX_REMAINDER, 
+                         RX_LINE_BREAKS,
+                         RX_LEADING_COMMENTS,
                          chop_param, 
                          parse_param,
                          parse_tokens_with,
@@ -38,7 +41,13 @@ use crate::ini::common::{ParseSlice,
 
 impl<'a> Token<'a> {
 
-    fn parse(src: &'a str) -> ParseResult<Self> {
+    #[inline]
+    fn parse(src: &'a str) -> ParseResult<'a, Self> {
+        Self::parse_with(src, false)
+    }
+
+    // lenient: unknown token types become Token::Unknown instead of an error
+    fn parse_with(src: &'a str, lenient: bool) -> ParseResult<'a, Self> {
         lazy_static! {
             static ref RX_TYPE: Regex = Regex::new(concatcp!(
                 r"(?s)^(", 
@@ -199,12 +208,13 @@ impl<'a> Token<'a> {
             Self::COST_WORK_VEHICLE_STATION        => parse!(CostWorkVehicleStation,      (Point3f, Point3f)),
             Self::COST_WORK_VEHICLE_STATION_NODE   => parse!(CostWorkVehicleStationNode,  IdStringParam),
 
+            _ if lenient => Ok((Self::Unknown(src.trim_end()), None)),
             _ => Err(format!("Unknown token type: \"${}\"", t_type))
         }
     }
 
 
-    fn parse_connection(src: Option<&'a str>) -> ParseResult<Token<'a>> {
+    fn parse_connection(src: Option<&'a str>) -> ParseResult<'a, Token<'a>> {
         lazy_static! {
             static ref RX_TYPE: Regex = Regex::new(r"(?s)^([A-Z_]+)(\s*(.*))").unwrap();
         }
@@ -294,7 +304,7 @@ impl BuildingType {
 }
 
 impl ParseSlice<'_> for BuildingType {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([A-Z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -329,7 +339,7 @@ impl BuildingSubtype {
 
 
 impl ParseSlice<'_> for BuildingSubtype {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([A-Z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -378,7 +388,7 @@ impl Connection2PType {
 
 
 impl ParseSlice<'_> for Connection2PType {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([A-Z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -402,7 +412,7 @@ impl Connection1PType {
 }
 
 impl ParseSlice<'_> for Connection1PType {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([A-Z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -434,7 +444,7 @@ impl StorageCargoType {
 }
 
 impl ParseSlice<'_> for StorageCargoType {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([0-9A-Z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -466,7 +476,7 @@ impl ParticleType {
 }
 
 impl ParseSlice<'_> for ParticleType {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([0-9a-z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -500,7 +510,7 @@ impl ConstructionPhase {
 }
 
 impl ParseSlice<'_> for ConstructionPhase {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([A-Z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -533,7 +543,7 @@ impl ConstructionAutoCost {
 }
 
 impl ParseSlice<'_> for ConstructionAutoCost {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([a-z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -595,7 +605,7 @@ impl ResourceType {
 }
 
 impl ParseSlice<'_> for ResourceType {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([a-z0-9_]+)", RX_REMAINDER)).unwrap();
         }
@@ -618,7 +628,7 @@ impl AirplaneStationType {
 }
 
 impl ParseSlice<'_> for AirplaneStationType {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([0-9]+M)", RX_REMAINDER)).unwrap();
         }
@@ -643,7 +653,7 @@ impl AttractionType {
 }
 
 impl ParseSlice<'_> for AttractionType {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([A-Z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -673,7 +683,7 @@ impl ResourceSourceType {
 }
 
 impl ParseSlice<'_> for ResourceSourceType {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([A-Z_]+)", RX_REMAINDER)).unwrap();
         }
@@ -684,7 +694,7 @@ impl ParseSlice<'_> for ResourceSourceType {
 
 
 impl ParseSlice<'_> for ResourceVisualization {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX_ALL: Regex = Regex::new(concatcp!(r"(?s)^([a-z]+)", RX_REMAINDER)).unwrap();
             // these seem to be just for information. Only correct number-order matters
@@ -713,7 +723,7 @@ impl ParseSlice<'_> for ResourceVisualization {
 
 
 lazy_static! {
-    static ref RX_SPLIT: Regex = Regex::new(concatcp!("(^|", r"(\s*((--|//)[^\n]*)?\r?\n)+", r")(\$|end\s*(\r?\n\s*)*)")).unwrap();
+    static ref RX_SPLIT: Regex = Regex::new(concatcp!("(", RX_LEADING_COMMENTS, "|", RX_LINE_BREAKS, r")(\$|end\s*((\r\n|\r|\n)\s*)*)")).unwrap();
 }
 
 
@@ -724,6 +734,37 @@ pub fn parse_tokens<'s>(src: &'s str) -> Vec<(&'s str, ParseResult<'s, Token<'s>
 
 
 #[inline]
-pub fn parse_tokens_strict<'a>(src: &'a str) -> Result<Vec<(&'a str, Token<'a>)>, Vec<(&'a str, ParseError)>> {
+pub fn parse_tokens_strict<'a>(src: &'a str) -> StrictParseResult<'a, Token<'a>> {
     parse_tokens_strict_with(src, &RX_SPLIT, Token::parse)
 }
+
+
+// Same as parse_tokens_strict, but tokens of unknown types are kept as Token::Unknown
+#[inline]
+pub fn parse_tokens_lenient<'a>(src: &'a str) -> StrictParseResult<'a, Token<'a>> {
+    parse_tokens_strict_with(src, &RX_SPLIT, |s| Token::parse_with(s, true))
+}
+
+
+// Parses every token of src, telling apart unknown token types (e.g. added by a game update) from parse errors of known ones
+pub fn coverage(src: &str) -> Coverage<'_> {
+    let mut cov = Coverage { tokens: 0, parsed: 0, unknown: Vec::with_capacity(0), failed: Vec::with_capacity(0) };
+
+    for (t_str, t_val) in parse_tokens_with(src, &RX_SPLIT, |s| Token::parse_with(s, true)) {
+        cov.tokens += 1;
+        match t_val {
+            Ok((Token::Unknown(s), _)) => {
+                let keyword = s.split_whitespace().next().unwrap_or(s);
+                match cov.unknown.iter_mut().find(|(k, _)| *k == keyword) {
+                    Some((_, n)) => *n += 1,
+                    None => cov.unknown.push((keyword, 1)),
+                }
+            },
+            Ok((_, Some(rest))) if !rest.is_empty() => cov.failed.push((t_str, format!("Token parsed incomplete. Remaining: {}", rest))),
+            Ok(_) => cov.parsed += 1,
+            Err(e) => cov.failed.push((t_str, e)),
+        }
+    }
+
+    cov
+}
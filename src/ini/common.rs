@@ -10,6 +10,8 @@ pub type ParseError = String;
 
 pub type ParseResult<'a, T> = Result<(T, Option<&'a str>), ParseError>;
 
+pub type StrictParseResult<'a, T> = Result<Vec<(&'a str, T)>, Vec<(&'a str, ParseError)>>;
+
 #[derive(Clone)]
 pub struct Point3f {
     pub x: f32,
@@ -70,14 +72,14 @@ impl<'a> CostKeywordParam<'a> {
 
 
 pub trait ParseSlice<'a> {
-    fn parse(src: Option<&'a str>) -> ParseResult<Self> where Self: Sized;
+    fn parse(src: Option<&'a str>) -> ParseResult<'a, Self> where Self: Sized;
 }
 
 impl<'a, T1, T2> ParseSlice<'a> for (T1, T2)
 where T1: ParseSlice<'a>,
       T2: ParseSlice<'a>
 {
-    fn parse(src: Option<&'a str>) -> ParseResult<Self> {
+    fn parse(src: Option<&'a str>) -> ParseResult<'a, Self> {
         let (t1, src) = T1::parse(src)?;
         let (t2, src) = T2::parse(src)?;
         Ok(((t1, t2), src))
@@ -90,7 +92,7 @@ where T1: ParseSlice<'a>,
       T2: ParseSlice<'a>,
       T3: ParseSlice<'a>
 {
-    fn parse(src: Option<&'a str>) -> ParseResult<Self> {
+    fn parse(src: Option<&'a str>) -> ParseResult<'a, Self> {
         let (t1, src) = T1::parse(src)?;
         let (t2, src) = T2::parse(src)?;
         let (t3, src) = T3::parse(src)?;
@@ -105,7 +107,7 @@ where T1: ParseSlice<'a>,
       T3: ParseSlice<'a>,
       T4: ParseSlice<'a>
 {
-    fn parse(src: Option<&'a str>) -> ParseResult<Self> {
+    fn parse(src: Option<&'a str>) -> ParseResult<'a, Self> {
         let (t1, src) = T1::parse(src)?;
         let (t2, src) = T2::parse(src)?;
         let (t3, src) = T3::parse(src)?;
@@ -116,7 +118,7 @@ where T1: ParseSlice<'a>,
 
 
 impl ParseSlice<'_> for Point3f {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         let((x, y, z), src) = <(f32, f32, f32) as ParseSlice>::parse(src)?;
         Ok((Point3f { x, y, z }, src))
     }
@@ -124,7 +126,7 @@ impl ParseSlice<'_> for Point3f {
 
 
 impl ParseSlice<'_> for Rect {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         let((x1, z1, x2, z2), src) = <(f32, f32, f32, f32) as ParseSlice>::parse(src)?;
         Ok((Rect { x1, z1, x2, z2 }, src))
     }
@@ -133,7 +135,7 @@ impl ParseSlice<'_> for Rect {
 
 
 impl ParseSlice<'_> for f32 {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^(-?[0-9]*\.?[0-9]+)", RX_REMAINDER)).unwrap();
         }
@@ -144,7 +146,7 @@ impl ParseSlice<'_> for f32 {
 
 
 impl ParseSlice<'_> for u8 {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([0-9]+)", RX_REMAINDER)).unwrap();
         }
@@ -155,7 +157,7 @@ impl ParseSlice<'_> for u8 {
 
 
 impl ParseSlice<'_> for u32 {
-    fn parse(src: Option<&str>) -> ParseResult<Self> {
+    fn parse(src: Option<&str>) -> ParseResult<'_, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([0-9]+)", RX_REMAINDER)).unwrap();
         }
@@ -166,7 +168,7 @@ impl ParseSlice<'_> for u32 {
 
 
 impl<'a> ParseSlice<'a> for QuotedStringParam<'a> {
-    fn parse(src: Option<&'a str>) -> ParseResult<Self> {
+    fn parse(src: Option<&'a str>) -> ParseResult<'a, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!("(?s)^\"([^\"\\n]+)\"", RX_REMAINDER)).unwrap();
         }
@@ -177,7 +179,7 @@ impl<'a> ParseSlice<'a> for QuotedStringParam<'a> {
 
 
 impl<'a> ParseSlice<'a> for IdStringParam<'a> {
-    fn parse(src: Option<&'a str>) -> ParseResult<Self> {
+    fn parse(src: Option<&'a str>) -> ParseResult<'a, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(concatcp!(r"(?s)^([^[:space:]]+)", RX_REMAINDER)).unwrap();
         }
@@ -188,7 +190,7 @@ impl<'a> ParseSlice<'a> for IdStringParam<'a> {
 
 
 impl<'a> ParseSlice<'a> for CostKeywordParam<'a> {
-    fn parse(src: Option<&'a str>) -> ParseResult<Self> {
+    fn parse(src: Option<&'a str>) -> ParseResult<'a, Self> {
         lazy_static! {
             static ref RX: Regex = Regex::new(r"^\$(.+)").unwrap();
         }
@@ -208,6 +210,18 @@ impl<'a> ParseSlice<'a> for CostKeywordParam<'a> {
 //-----------------------------------------------------------------
 
 
+// A float in a serialized token: with a fixed number of decimals if a precision is set, otherwise as is
+pub struct FloatParam(pub f32, pub Option<usize>);
+
+impl Display for FloatParam {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self.1 {
+            Some(prec) => write!(f, "{:.prec$}", self.0, prec = prec),
+            None       => write!(f, "{}", self.0),
+        }
+    }
+}
+
 impl Display for Point3f {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         write!(f, "({}, {}, {})", self.x, self.y, self.z)
@@ -256,8 +270,16 @@ impl Display for CostKeywordParam<'_> {
 
 pub const RX_REMAINDER: &str = r"($|\s*(.*))";
 
+// One or more line breaks (LF, CRLF or bare CR), each optionally followed by a line that holds only a '--' or '//' comment.
+// Text after a token on the same line is kept: values like texture paths may contain '--' or '//'.
+// Shared by the token splitters of all ini files.
+pub const RX_LINE_BREAKS: &str = r"(\s*(\r\n|\r|\n)(\s*(--|//)[^\r\n]*)?)+";
+
+// Whitespace and comment lines at the start of a file
+pub const RX_LEADING_COMMENTS: &str = concatcp!(r"^\s*((--|//)[^\r\n]*)?(", RX_LINE_BREAKS, ")?");
+
 
-pub fn chop_param<'a, 'b>(src: Option<&'a str>, rx: &'b Regex) -> ParseResult<'a, &'a str> {
+pub fn chop_param<'a>(src: Option<&'a str>, rx: &Regex) -> ParseResult<'a, &'a str> {
     let src = src.ok_or(String::from("Chop param failed: not enough data"))?;
 
     match rx.captures(src) {
@@ -310,7 +332,7 @@ where F: Fn(&'a str) -> ParseResult<T>
 }
 
 
-pub fn parse_tokens_strict_with<'a, T, F>(src: &'a str, rx: &Regex, f: F) -> Result<Vec<(&'a str, T)>, Vec<(&'a str, ParseError)>>
+pub fn parse_tokens_strict_with<'a, T, F>(src: &'a str, rx: &Regex, f: F) -> StrictParseResult<'a, T>
 where F: Fn(&'a str) -> ParseResult<T>
 {
     let mut res = Vec::with_capacity(100);
@@ -1,14 +1,17 @@
 use std::fmt;
 use lazy_static::lazy_static;
 use regex::Regex;
+use const_format::concatcp;
 
 use crate::ini::common::{IdStringParam,
                          ParseSlice,
-                         ParseError,
+                         StrictParseResult,
                          ParseResult,
                          chop_param,
                          parse_tokens_with,
                          parse_tokens_strict_with,
+                         RX_LINE_BREAKS,
+                         RX_LEADING_COMMENTS,
                          };
 
 pub type Color = (f32, f32, f32, f32);
@@ -39,7 +42,7 @@ impl<'a> Token<'a> {
     const SPECULAR_POWER:    &'static str = "$SPECULARPOWER";
     const END:               &'static str = "$END";
 
-    fn parse(src: &'a str) -> ParseResult<Self> {
+    fn parse(src: &'a str) -> ParseResult<'a, Self> {
         lazy_static! {
             static ref RX_TYPE: Regex = Regex::new(r"^(\$?[0-9A-Z_]+)(\s+(.+))?$").unwrap();
         }
@@ -90,7 +93,10 @@ impl fmt::Display for Token<'_> {
 
 
 impl super::IniToken for Token<'_> {
-    fn serialize<W: std::io::Write>(&self, mut wr: W) -> Result<(), std::io::Error>{
+    const SEPARATOR: &'static str = "\r\n";
+
+    // colors are written as is, precision is not applied to them
+    fn serialize<W: std::io::Write>(&self, mut wr: W, _prec: Option<usize>) -> Result<(), std::io::Error>{
         match self {
             Self::DiffuseColor((r, g, b, a))  => write!(wr, "{} {} {} {} {}", Self::DIFFUSE_COLOR,  r, g, b, a),
             Self::SpecularColor((r, g, b, a)) => write!(wr, "{} {} {} {} {}", Self::SPECULAR_COLOR, r, g, b, a),
@@ -102,7 +108,7 @@ impl super::IniToken for Token<'_> {
 
 
 lazy_static! {
-    static ref RX_SPLIT: Regex = Regex::new(r"(?s)(\s*\n)+\s*").unwrap();
+    static ref RX_SPLIT: Regex = Regex::new(concatcp!("(", RX_LEADING_COMMENTS, "|", RX_LINE_BREAKS, r")\s*")).unwrap();
 }
 
 
@@ -113,6 +119,23 @@ pub fn parse_tokens<'a>(src: &'a str) -> Vec<(&'a str, ParseResult<'a, Token<'a>
 
 
 #[inline]
-pub fn parse_tokens_strict<'a>(src: &'a str) -> Result<Vec<(&'a str, Token<'a>)>, Vec<(&'a str, ParseError)>> {
+pub fn parse_tokens_strict<'a>(src: &'a str) -> StrictParseResult<'a, Token<'a>> {
     parse_tokens_strict_with(src, &RX_SPLIT, Token::parse)
 }
+
+
+// Starting point for a new mtl: a $SUBMATERIAL token per name, with a commented-out texture placeholder
+pub fn write_stub<'a, W, I>(mut wr: W, submaterials: I) -> std::io::Result<()>
+where W: std::io::Write,
+      I: Iterator<Item = &'a str>
+{
+    use super::IniToken;
+
+    for sm in submaterials {
+        Token::Submaterial(IdStringParam::new_borrowed(sm)).serialize(&mut wr, None)?;
+        write!(wr, "{}", Token::SEPARATOR)?;
+        write!(wr, "-- {} 0 <texture.dds>{}{}", Token::TEXTURE_MTL, Token::SEPARATOR, Token::SEPARATOR)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use std::mem::discriminant;
+
+use super::{BuildingIni, IniTokenState};
+
+
+// Applies the overlay on top of the base file:
+// singular tokens replace the first token of the same kind in the base (or are added, if the base has none),
+// repeatable tokens (see BuildingToken::is_repeatable) are always added after the base tokens.
+pub fn merge_building<'a>(base: &mut BuildingIni<'a>, overlay: BuildingIni<'a>) {
+    for t in overlay.into_tokens() {
+        if !t.is_repeatable() {
+            let kind = discriminant(&t);
+            if let Some(t_state) = base.tokens_mut().find(|t_state| discriminant(t_state.token()) == kind) {
+                *t_state = IniTokenState::Modified(t);
+                continue;
+            }
+        }
+
+        base.push_token(t);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ini::parse_building_ini;
+
+    #[test]
+    fn overlay_replaces_singular_and_appends_repeatable_tokens() {
+        let mut base = parse_building_ini("$WORKERS_NEEDED 10\r\n$CITIZEN_ABLE_SERVE 4\r\n$STORAGE RESOURCE_TRANSPORT_CONCRETE 100\r\nend\r\n").unwrap();
+        let overlay = parse_building_ini("$WORKERS_NEEDED 25\r\n$STORAGE RESOURCE_TRANSPORT_CONCRETE 50\r\n").unwrap();
+        merge_building(&mut base, overlay);
+
+        let mut out = Vec::new();
+        base.write_to(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "$WORKERS_NEEDED 25\r\n$CITIZEN_ABLE_SERVE 4\r\n$STORAGE RESOURCE_TRANSPORT_CONCRETE 100\r\n\
+                    $STORAGE RESOURCE_TRANSPORT_CONCRETE 50\r\nend\r\n");
+    }
+}
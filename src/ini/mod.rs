@@ -2,6 +2,8 @@ use std::io::Write;
 use std::path::Path;
 use std::fmt;
 
+use normpath::BasePathBuf;
+
 pub mod common;
 
 pub mod building;
@@ -9,16 +11,23 @@ pub mod renderconfig;
 pub mod material;
 
 pub mod transform;
+pub mod merge;
 
-use common::{ParseError, IdStringParam};
-use crate::cfg::APP_SETTINGS;
+use common::{ParseError, IdStringParam, Point3f};
 
 
 //---------------------------------------------
 
 
+// Number of decimals for coordinates in serialized (modified) building.ini tokens, same as in stock game files.
+// Used unless a float precision is set; other floats are then written in their shortest exact form.
+pub const POINT_PRECISION: usize = 4;
+
 pub trait IniToken: Sized {
-    fn serialize<W: Write>(&self, wr: W) -> std::io::Result<()>;
+    // Written before tokens that were added to a file (see IniFile::push_token)
+    const SEPARATOR: &'static str;
+
+    fn serialize<W: Write>(&self, wr: W, prec: Option<usize>) -> std::io::Result<()>;
 }
 
 
@@ -42,6 +51,22 @@ impl<T> IniTokenState<T> {
             Some(t) => *self = Self::Modified(t)
         }
     }
+
+    // Marks the token as modified and gives mutable access to it
+    pub fn token_mut(&mut self) -> &mut T {
+        if let Self::Original(t) = self {
+            unsafe {
+                // move the token into the Modified state, without cloning it
+                let t = std::ptr::read(t);
+                std::ptr::write(self, Self::Modified(t));
+            }
+        }
+
+        match self {
+            Self::Original(t) => t,
+            Self::Modified(t) => t
+        }
+    }
 }
 
 
@@ -61,7 +86,8 @@ impl<'a, T: ?Sized> Captures<'a> for T {}
 
 pub struct IniFile<'a, T: IniToken> {
     ini_slice: &'a str,
-    tokens: Vec<(&'a str, IniTokenState<T>)>
+    tokens: Vec<(&'a str, IniTokenState<T>)>,
+    float_precision: Option<usize>,
 }
 
 
@@ -72,10 +98,15 @@ impl<'a, T> IniFile<'a, T> where T: IniToken {
             ini_slice, 
             tokens: tokens.into_iter()
                           .map(|(chunk, t_val)| (chunk, IniTokenState::Original(t_val)))
-                          .collect()
+                          .collect(),
+            float_precision: None,
         }
     }
 
+    pub fn set_float_precision(&mut self, prec: Option<usize>) {
+        self.float_precision = prec;
+    }
+
     pub fn tokens(&self) -> impl Iterator<Item = &T> + Clone {
         self.tokens.iter().map(|(_, t)| t.token())
     }
@@ -84,6 +115,29 @@ impl<'a, T> IniFile<'a, T> where T: IniToken {
         self.tokens.iter_mut().map(|(_, t)| t)
     }
 
+    // Token list for printing. Annotated mode marks modified tokens with '*' (see IniTokenState's Display),
+    // clean mode prints all tokens as they are
+    pub fn display_tokens(&self, annotated: bool) -> TokensDisplay<'_, 'a, T> {
+        TokensDisplay { file: self, annotated }
+    }
+
+    pub fn into_tokens(self) -> impl Iterator<Item = T> + Captures<'a> {
+        self.tokens.into_iter().map(|(_, t)| match t {
+            IniTokenState::Original(t) => t,
+            IniTokenState::Modified(t) => t
+        })
+    }
+
+    // Adds a new token right after the last one. New tokens have empty source chunks.
+    pub fn push_token(&mut self, token: T) {
+        let chunk = match self.tokens.last() {
+            Some((t_str, _)) => &t_str[t_str.len() ..],
+            None => &self.ini_slice[.. 0]
+        };
+
+        self.tokens.push((chunk, IniTokenState::Modified(token)));
+    }
+
     pub fn write_file<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
         let mut new_ini_file = std::io::BufWriter::new(std::fs::OpenOptions::new().write(true).create(true).truncate(true).open(path)?);
         self.write_to(&mut new_ini_file)?;
@@ -105,11 +159,16 @@ impl<'a, T> IniFile<'a, T> where T: IniToken {
                             // can write original chunk
                             let chunk = std::slice::from_raw_parts(chunk_start, chunk_len as usize);
                             wr.write_all(chunk)?;
-
-                            chunk_start = chunk_end.add(t_str.len());
                         }
 
-                        t.serialize(&mut wr)?;
+                        // skip the original token text (even when it is right at chunk_start),
+                        // so that neither it nor anything after it (like the 'end' marker) gets duplicated
+                        chunk_start = chunk_end.add(t_str.len());
+                        if t_str.is_empty() {
+                            // token was added, not present in the original file
+                            wr.write_all(T::SEPARATOR.as_bytes())?;
+                        }
+                        t.serialize(&mut wr, self.float_precision)?;
                     }
                 }
             }
@@ -130,6 +189,26 @@ impl<'a, T> IniFile<'a, T> where T: IniToken {
 }
 
 
+pub struct TokensDisplay<'f, 'a, T: IniToken> {
+    file: &'f IniFile<'a, T>,
+    annotated: bool
+}
+
+impl<T: IniToken + fmt::Display> fmt::Display for TokensDisplay<'_, '_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for (_, t_state) in self.file.tokens.iter() {
+            if self.annotated {
+                writeln!(f, "{}", t_state)?;
+            } else {
+                writeln!(f, "{}", t_state.token())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
 // --------------------- BUILDING.INI ---------------------------------
 
 
@@ -141,6 +220,11 @@ pub fn parse_building_ini<'a>(src: &'a str) -> Result<BuildingIni<'a>, Vec<(&'a
     building::parse_tokens_strict(src).map(|tokens| BuildingIni::from_parts(src, tokens))
 }
 
+// Unknown token types do not fail the parse: they are kept as BuildingToken::Unknown and written back as is
+pub fn parse_building_ini_lenient<'a>(src: &'a str) -> Result<BuildingIni<'a>, Vec<(&'a str, ParseError)>> {
+    building::parse_tokens_lenient(src).map(|tokens| BuildingIni::from_parts(src, tokens))
+}
+
 
 pub enum BuildingNodeRef<'a> {
     Exact(&'a str),
@@ -148,9 +232,23 @@ pub enum BuildingNodeRef<'a> {
 }
 
 
-impl BuildingIni<'_> {
+impl<'a> BuildingIni<'a> {
+
+    // Mutable access to every positional point in the file. Only the tokens that carry points are marked as modified.
+    pub fn point_tokens_mut(&mut self) -> impl Iterator<Item = &mut Point3f> + Captures<'a> {
+        self.tokens.iter_mut()
+            .filter_map(|(_, t_state)| {
+                let has_points = match t_state {
+                    IniTokenState::Original(t) => t.points_mut()[0].is_some(),
+                    IniTokenState::Modified(t) => t.points_mut()[0].is_some(),
+                };
+
+                if has_points { Some(t_state.token_mut()) } else { None }
+            })
+            .flat_map(|t| IntoIterator::into_iter(t.points_mut()).flatten())
+    }
 
-    pub fn get_model_refs(&self) -> impl Iterator<Item = BuildingNodeRef> {
+    pub fn get_model_refs(&self) -> impl Iterator<Item = BuildingNodeRef<'_>> {
         self.tokens().filter_map(|t| match t {
             BuildingToken::StorageLivingAuto(id)          => Some(BuildingNodeRef::Exact(id.as_str())),
             BuildingToken::CostWorkBuildingNode(id)       => Some(BuildingNodeRef::Exact(id.as_str())),
@@ -159,6 +257,19 @@ impl BuildingIni<'_> {
             _ => None
         })
     }
+
+    // First $NAME or $NAME_STR token, if any
+    pub fn get_name(&self) -> Option<&BuildingToken<'_>> {
+        self.tokens().find(|t| matches!(t, BuildingToken::NameStr(_) | BuildingToken::Name(_)))
+    }
+
+    // Tokens kept as is by the lenient parse (without the leading '$'). Transforms do not touch them.
+    pub fn unknown_tokens(&self) -> impl Iterator<Item = &str> {
+        self.tokens().filter_map(|t| match t {
+            BuildingToken::Unknown(chunk) => Some(*chunk),
+            _ => None
+        })
+    }
 }
 
 
@@ -186,17 +297,26 @@ pub fn parse_mtl<'a>(src: &'a str) -> Result<MaterialMtl<'a>, Vec<(&'a str, Pars
 use std::path::PathBuf;
 
 impl MaterialMtl<'_> {
-    pub fn get_texture_paths<F: Fn(&IdStringParam<'_>) -> PathBuf>(&self, path_resolver: F) -> Vec<PathBuf> {
+    // Texture tokens are resolved against the stock root, TextureMtl ones with path_resolver
+    pub fn get_texture_paths<F: Fn(&IdStringParam<'_>) -> PathBuf>(&self, roots: &GameRoots, path_resolver: F) -> Vec<PathBuf> {
         use crate::ini::MaterialToken as MT;
 
         self.tokens().filter_map(|t| match t {
-            MT::Texture((_, s))         => Some(resolve_stock_path(s)),
-            MT::TextureNoMip((_, s))    => Some(resolve_stock_path(s)),
+            MT::Texture((_, s))         => Some(roots.resolve_stock_path(s)),
+            MT::TextureNoMip((_, s))    => Some(roots.resolve_stock_path(s)),
             MT::TextureMtl((_, s))      => Some(path_resolver(s)),
             MT::TextureNoMipMtl((_, s)) => Some(path_resolver(s)),
             _ => None
         }).collect()
     }
+
+    // Names of $SUBMATERIAL tokens, in file order
+    pub fn submaterial_names(&self) -> Vec<&str> {
+        self.tokens().filter_map(|t| match t {
+            MaterialToken::Submaterial(sm) => Some(sm.as_str()),
+            _ => None
+        }).collect()
+    }
 }
 
 
@@ -210,17 +330,156 @@ pub fn normalize_join(root: &Path, tail: &IdStringParam) -> PathBuf {
     root.into_path_buf()
 }
 
-#[inline]
-pub fn resolve_stock_path(token: &IdStringParam<'_>) -> PathBuf {
-    APP_SETTINGS.path_stock.join(token.as_str()).into_path_buf()
+// Game directories that path tokens are resolved against: '~' and plain texture tokens
+// to the stock game files, '#' to the workshop directory
+#[derive(Clone)]
+pub struct GameRoots {
+    pub stock: BasePathBuf,
+    pub workshop: BasePathBuf,
+}
+
+impl GameRoots {
+    #[inline]
+    pub fn resolve_stock_path(&self, token: &IdStringParam<'_>) -> PathBuf {
+        self.stock.join(token.as_str()).into_path_buf()
+    }
+
+    pub fn resolve_source_path(&self, local_root: &Path, tail: &IdStringParam) -> PathBuf {
+        let mut iter = tail.as_str().chars();
+        let pfx = iter.next().expect("resolve_source_path called with empty tail");
+        match pfx {
+            '#' => self.workshop.join(iter.as_str()).into_path_buf(),
+            '~' => self.stock.join(iter.as_str()).into_path_buf(),
+            _   => normalize_join(local_root, tail)
+        }
+    }
 }
 
-pub fn resolve_source_path(local_root: &Path, tail: &IdStringParam) -> PathBuf {
-    let mut iter = tail.as_str().chars();
-    let pfx = iter.next().expect("resolve_source_path called with empty tail");
-    match pfx {
-        '#' => APP_SETTINGS.path_workshop.join(iter.as_str()).into_path_buf(),
-        '~' => APP_SETTINGS.path_stock.join(iter.as_str()).into_path_buf(),
-        _   => normalize_join(local_root, tail)
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ini::transform::{self, CostScaling};
+
+    fn written<T: IniToken>(ini: &IniFile<'_, T>) -> String {
+        let mut out = Vec::new();
+        ini.write_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // A modified token right at the start of the file used to be written twice, together with the rest of the file
+    #[test]
+    fn end_terminator_is_written_once() {
+        let src = "$HELIPORT_AREA 5\r\n$WORKERS_NEEDED 10\r\n$HARBOR_OVER_WATER_FROM 1\r\nend\r\n";
+        let mut ini = parse_building_ini(src).unwrap();
+        transform::scale_building(&mut ini, 2.0, &CostScaling::None);
+
+        let out = written(&ini);
+        assert_eq!(out, "$HELIPORT_AREA 10\r\n$WORKERS_NEEDED 10\r\n$HARBOR_OVER_WATER_FROM 2\r\nend\r\n");
+        assert_eq!(out.matches("end").count(), 1);
+    }
+
+    // Rects and scalars are not points: 2 + 1 + 1 here
+    #[test]
+    fn point_tokens_yields_every_point() {
+        let src = "$WORKERS_NEEDED 10\r\n\
+                   $VEHICLE_STATION 1 0 1 2 0 2\r\n\
+                   $CONNECTION_ROAD_DEAD 3 0 3\r\n\
+                   $CONNECTIONS_SPACE -1 -1 1 1\r\n\
+                   $HELIPORT_AREA 5\r\n\
+                   $RESOURCE_VISUALIZATION 0 position 4 0 4 rotation 0 scale 1 1 1 numstepx 1 2 numstept 1 2\r\n\
+                   end\r\n";
+        let mut ini = parse_building_ini(src).unwrap();
+
+        let xs = ini.point_tokens_mut().map(|p| p.x).collect::<Vec<_>>();
+        assert_eq!(xs, vec![1., 2., 3., 4.]);
+    }
+
+    #[test]
+    fn float_precision_applies_to_modified_tokens() {
+        let src = "$CONNECTION_ROAD_DEAD 1 0 1\r\n$HELIPORT_AREA 5\r\nend\r\n";
+        let mut ini = parse_building_ini(src).unwrap();
+        transform::scale_building(&mut ini, 1.0 / 3.0, &CostScaling::None);
+
+        // by default only coordinates get a fixed number of decimals
+        assert_eq!(written(&ini), "$CONNECTION_ROAD_DEAD\r\n0.3333 0.0000 0.3333\r\n$HELIPORT_AREA 1.6666666\r\nend\r\n");
+
+        ini.set_float_precision(Some(2));
+        assert_eq!(written(&ini), "$CONNECTION_ROAD_DEAD\r\n0.33 0.00 0.33\r\n$HELIPORT_AREA 1.67\r\nend\r\n");
+
+        ini.set_float_precision(Some(0));
+        assert_eq!(written(&ini), "$CONNECTION_ROAD_DEAD\r\n0 0 0\r\n$HELIPORT_AREA 2\r\nend\r\n");
+    }
+
+    #[test]
+    fn scaled_lights_keep_their_float_format() {
+        let mut ini = parse_renderconfig_ini("LIGHT 0.5 1 -1.25 3\r\n").unwrap();
+        transform::scale_render(&mut ini, 2.0);
+        assert_eq!(written(&ini), "LIGHT 1 2 -2.5 3\r\n");
+
+        ini.set_float_precision(Some(1));
+        assert_eq!(written(&ini), "LIGHT 1.0 2.0 -2.5 3.0\r\n");
+    }
+
+    fn token_strings<T: IniToken + fmt::Display>(ini: &IniFile<'_, T>) -> Vec<String> {
+        ini.tokens().map(|t| t.to_string()).collect()
+    }
+
+    // src is written with LF; CRLF, bare CR and mixed line breaks must give the same tokens
+    fn line_break_variants(src: &str) -> Vec<String> {
+        let mixed = src.lines().enumerate()
+            .map(|(i, l)| format!("{}{}", l, ["\r\n", "\n", "\r"][i % 3]))
+            .collect::<String>();
+        vec![src.to_string(), src.replace('\n', "\r\n"), src.replace('\n', "\r"), mixed]
+    }
+
+    #[test]
+    fn line_breaks_do_not_change_tokens() {
+        let building = "$NAME_STR \"b1\"\n-- comment\n$WORKERS_NEEDED 10\n\n$CONNECTION_ROAD_DEAD 1 0 1\nend\n";
+        let render = "MODEL model.nmf\n// comment\nMATERIAL model.mtl\n\nMODEL_LOD lod.nmf 100\n";
+        let mtl = "$SUBMATERIAL mat_a\n-- comment\n$TEXTURE_MTL 0 tex.dds\n\n$SUBMATERIAL mat_b\n$TEXTURE 1 tex2.dds\n";
+
+        let expected = token_strings(&parse_building_ini(building).unwrap());
+        assert_eq!(expected.len(), 3);
+        for src in line_break_variants(building) {
+            assert_eq!(token_strings(&parse_building_ini(&src).unwrap()), expected, "{:?}", src);
+        }
+
+        let expected = token_strings(&parse_renderconfig_ini(render).unwrap());
+        assert_eq!(expected.len(), 3);
+        for src in line_break_variants(render) {
+            assert_eq!(token_strings(&parse_renderconfig_ini(&src).unwrap()), expected, "{:?}", src);
+        }
+
+        let expected = token_strings(&parse_mtl(mtl).unwrap());
+        assert_eq!(expected.len(), 4);
+        for src in line_break_variants(mtl) {
+            assert_eq!(token_strings(&parse_mtl(&src).unwrap()), expected, "{:?}", src);
+        }
+    }
+
+    // only whole-line comments are skipped, '--' and '//' inside a token belong to its value
+    #[test]
+    fn comment_markers_inside_values_are_kept() {
+        let render = "-- header\r\nMODEL models/lod--1.nmf\r\n// note\r\nMATERIAL ~/mtl//a.mtl\r\n";
+        assert_eq!(token_strings(&parse_renderconfig_ini(render).unwrap()), ["MODEL models/lod--1.nmf", "MATERIAL ~/mtl//a.mtl"]);
+
+        let mtl = "$SUBMATERIAL mat--a\r\n  -- comment\r\n$TEXTURE_MTL 0 tex--v2.dds\r\n";
+        let tokens = token_strings(&parse_mtl(mtl).unwrap());
+        assert_eq!(tokens.len(), 2);
+        assert!(tokens[0].ends_with("mat--a") && tokens[1].ends_with("tex--v2.dds"), "{:?}", tokens);
+
+        let building = "-- header\r\n$NAME_STR \"b1 -- test\"\r\nend\r\n";
+        assert_eq!(token_strings(&parse_building_ini(building).unwrap()), ["NAME_STR \"b1 -- test\""]);
+    }
+
+    #[test]
+    fn submaterial_names_are_in_file_order() {
+        let src = "$SUBMATERIAL glass\r\n$TEXTURE_MTL 0 tex.dds\r\n$SUBMATERIAL wall\r\n$TEXTURE 0 wall.dds\r\n$SUBMATERIAL roof\r\n";
+        let mtl = parse_mtl(src).unwrap();
+        assert_eq!(mtl.submaterial_names(), vec!["glass", "wall", "roof"]);
+
+        let mtl = parse_mtl("$TEXTURE 0 tex.dds\r\n").unwrap();
+        assert!(mtl.submaterial_names().is_empty());
     }
 }
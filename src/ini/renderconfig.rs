@@ -1,15 +1,19 @@
 use std::fmt;
 use lazy_static::lazy_static;
 use regex::Regex;
+use const_format::concatcp;
 
 use crate::ini::common::{Point3f, 
+                         FloatParam,
                          IdStringParam,
                          ParseSlice,
-                         ParseError,
+                         StrictParseResult,
                          ParseResult,
                          chop_param,
                          parse_tokens_with,
                          parse_tokens_strict_with,
+                         RX_LINE_BREAKS,
+                         RX_LEADING_COMMENTS,
                          };
 
 pub type LightColor = (f32, f32, f32);
@@ -73,7 +77,7 @@ impl<'a> Token<'a> {
     const LIGHT_RGB:                 &'static str = "LIGHT_RGB";
     const LIGHT_RGB_BLINK:           &'static str = "LIGHT_RGB_BLICK";
 
-    fn parse(src: &'a str) -> ParseResult<Self> {
+    fn parse(src: &'a str) -> ParseResult<'a, Self> {
         lazy_static! {
             static ref RX_TYPE: Regex = Regex::new(r"^(\$?[0-9A-Z_]+)(\s+(.+))?$").unwrap();
         }
@@ -158,11 +162,17 @@ impl fmt::Display for Token<'_> {
 
 
 impl super::IniToken for Token<'_> {
-    fn serialize<W: std::io::Write>(&self, mut wr: W) -> Result<(), std::io::Error>{
+    const SEPARATOR: &'static str = "\r\n";
+
+    fn serialize<W: std::io::Write>(&self, mut wr: W, prec: Option<usize>) -> Result<(), std::io::Error>{
+        let f = |x: f32| FloatParam(x, prec);
         match self {
-            Self::Light((pt, x))                    => write!(wr, "{} {} {} {} {}", Self::LIGHT, pt.x, pt.y, pt.z, x),
-            Self::LightRgb((pt, x, (r, g, b)))      => write!(wr, "{} {} {} {} {} {} {} {}", Self::LIGHT_RGB, pt.x, pt.y, pt.z, x, r, g, b),
-            Self::LightRgbBlink((pt, x, (r, g, b))) => write!(wr, "{} {} {} {} {} {} {} {}", Self::LIGHT_RGB_BLINK, pt.x, pt.y, pt.z, x, r, g, b),
+            Self::Light((pt, x))                    => write!(wr, "{} {} {} {} {}", 
+                                                              Self::LIGHT, f(pt.x), f(pt.y), f(pt.z), f(*x)),
+            Self::LightRgb((pt, x, (r, g, b)))      => write!(wr, "{} {} {} {} {} {} {} {}", 
+                                                              Self::LIGHT_RGB, f(pt.x), f(pt.y), f(pt.z), f(*x), r, g, b),
+            Self::LightRgbBlink((pt, x, (r, g, b))) => write!(wr, "{} {} {} {} {} {} {} {}", 
+                                                              Self::LIGHT_RGB_BLINK, f(pt.x), f(pt.y), f(pt.z), f(*x), r, g, b),
             s => write!(wr, "{}", s)
         }
     }
@@ -170,7 +180,7 @@ impl super::IniToken for Token<'_> {
 
 
 lazy_static! {
-    static ref RX_SPLIT: Regex = Regex::new(r"(?s)(^\s|(\s*\n)+)\s*").unwrap();
+    static ref RX_SPLIT: Regex = Regex::new(concatcp!("(", RX_LEADING_COMMENTS, "|", RX_LINE_BREAKS, r")\s*")).unwrap();
 }
 
 
@@ -181,6 +191,20 @@ pub fn parse_tokens<'a>(src: &'a str) -> Vec<(&'a str, ParseResult<'a, Token<'a>
 
 
 #[inline]
-pub fn parse_tokens_strict<'a>(src: &'a str) -> Result<Vec<(&'a str, Token<'a>)>, Vec<(&'a str, ParseError)>> {
+pub fn parse_tokens_strict<'a>(src: &'a str) -> StrictParseResult<'a, Token<'a>> {
     parse_tokens_strict_with(src, &RX_SPLIT, Token::parse)
 }
+
+
+// Starting point for a new renderconfig: MODEL and MATERIAL tokens with the specified paths
+pub fn write_stub<W: std::io::Write>(mut wr: W, model: &str, material: &str) -> std::io::Result<()> {
+    use super::IniToken;
+
+    let tokens = [Token::Model(IdStringParam::new_borrowed(model)), Token::Material(IdStringParam::new_borrowed(material))];
+    for t in tokens.iter() {
+        t.serialize(&mut wr, None)?;
+        write!(wr, "{}", Token::SEPARATOR)?;
+    }
+
+    Ok(())
+}
@@ -3,8 +3,26 @@ use crate::ini::common::{Point3f, Rect};
 use crate::ini::BuildingToken;
 
 
-pub fn scale_building(file: &mut ini::BuildingIni<'_>, factor: f64) {
+// How construction costs (COST_WORK, COST_RESOURCE, COST_RESOURCE_AUTO) follow the geometry scale
+pub enum CostScaling {
+    None,
+    Linear,
+    Cubic,
+}
+
+
+// Scalar tokens that are lengths in meters follow the geometry linearly: HELIPORT_AREA (size of the landing area,
+// a length despite the name), HARBOR_OVER_TERRAIN_FROM / HARBOR_OVER_WATER_FROM / HARBOR_EXTEND_AREA_WHEN_BULDING (x distances),
+// the snow removal radius and the resource visualization steps. Rates, factors and capacities (PRODUCTION, STORAGE, ...) are kept,
+// construction costs depend on cost_scaling.
+pub fn scale_building(file: &mut ini::BuildingIni<'_>, factor: f64, cost_scaling: &CostScaling) {
     let mul = |x: f32| { ((x as f64) * factor) as f32 };
+    let cost_factor = match cost_scaling {
+        CostScaling::None   => None,
+        CostScaling::Linear => Some(factor),
+        CostScaling::Cubic  => Some(factor * factor * factor),
+    };
+    let mul_cost = |x: f32| cost_factor.map(|f| ((x as f64) * f) as f32);
 
     for (_, t_state) in file.tokens.iter_mut() {
         t_state.modify(|t_source| {
@@ -15,24 +33,31 @@ pub fn scale_building(file: &mut ini::BuildingIni<'_>, factor: f64) {
                     T::HarborTerrainFrom(x)          => Some(T::HarborTerrainFrom(mul(*x))),
                     T::HarborWaterFrom(x)            => Some(T::HarborWaterFrom(mul(*x))),
                     T::HarborExtendWhenBuilding(x)   => Some(T::HarborExtendWhenBuilding(mul(*x))),
-                    T::ParticleSnowRemove((p, i, r)) => Some(T::ParticleSnowRemove((p.scaled(factor), *i, mul(*r)))),
+                    T::ParticleSnowRemove((p, i, r)) => Some(T::ParticleSnowRemove((p.clone(), *i, mul(*r)))),
+
+                    T::CostWork((phase, x))          => mul_cost(*x).map(|x| T::CostWork((phase.clone(), x))),
+                    T::CostResource((res, x))        => mul_cost(*x).map(|x| T::CostResource((res.clone(), x))),
+                    T::CostResourceAuto((auto, x))   => mul_cost(*x).map(|x| T::CostResourceAuto((auto.clone(), x))),
 
                     T::ResourceVisualization(rv) => Some(T::ResourceVisualization (RV {
                         storage_id: rv.storage_id,
-                        position:   rv.position.scaled(factor),
+                        position:   rv.position.clone(),
                         rotation:   rv.rotation,
                         scale:      rv.scale.scaled(factor),
                         numstep_x:  (mul(rv.numstep_x.0), rv.numstep_x.1),
                         numstep_z:  (mul(rv.numstep_z.0), rv.numstep_z.1),
                     })),
-                    other => transform_point(other, |p| p.scaled(factor))
-                                 .or_else(|| transform_rect(t_source, |r| Rect { x1: mul(r.x1), 
-                                                                                 x2: mul(r.x2), 
-                                                                                 z1: mul(r.z1), 
-                                                                                 z2: mul(r.z2) }))
+                    other => transform_rect(other, |r| Rect { x1: mul(r.x1), 
+                                                              x2: mul(r.x2), 
+                                                              z1: mul(r.z1), 
+                                                              z2: mul(r.z2) })
                 }
             })
     }
+
+    for p in file.point_tokens_mut() {
+        *p = p.scaled(factor);
+    }
 }
 
 
@@ -57,29 +82,21 @@ pub fn offset_building(file: &mut ini::BuildingIni<'_>, dx: f32, dy: f32, dz: f3
     for (_, t_state) in file.tokens.iter_mut() {
         t_state.modify(|t_source| {
                 use crate::ini::BuildingToken as T;
-                use crate::ini::building::ResourceVisualization as RV;
                 match t_source {
                     T::HarborTerrainFrom(x)          => Some(T::HarborTerrainFrom(*x + dx)),
                     T::HarborWaterFrom(x)            => Some(T::HarborWaterFrom(*x + dx)),
                     T::HarborExtendWhenBuilding(x)   => Some(T::HarborExtendWhenBuilding(*x - dx)),
-                    T::ParticleSnowRemove((p, i, r)) => Some(T::ParticleSnowRemove((p.offset(dx, dy, dz), *i, *r))),
-
-                    T::ResourceVisualization(rv) => Some(T::ResourceVisualization (RV {
-                        storage_id: rv.storage_id,
-                        position:   rv.position.offset(dx, dy, dz),
-                        rotation:   rv.rotation,
-                        scale:      rv.scale.clone(),
-                        numstep_x:  rv.numstep_x,
-                        numstep_z:  rv.numstep_z,
-                    })),
-                    other => transform_point(other, |p| p.offset(dx, dy, dz))
-                                 .or_else(|| transform_rect(t_source, |r| Rect { x1: r.x1 + dx, 
-                                                                                 x2: r.x2 + dx, 
-                                                                                 z1: r.z1 + dz, 
-                                                                                 z2: r.z2 + dz }))
+                    other => transform_rect(other, |r| Rect { x1: r.x1 + dx, 
+                                                              x2: r.x2 + dx, 
+                                                              z1: r.z1 + dz, 
+                                                              z2: r.z2 + dz })
                 }
             })
     }
+
+    for p in file.point_tokens_mut() {
+        *p = p.offset(dx, dy, dz);
+    }
 }
 
 pub fn offset_render(f: &mut ini::RenderIni<'_>, dx: f32, dy: f32, dz: f32) {
@@ -110,23 +127,25 @@ pub fn mirror_z_building(file: &mut ini::BuildingIni<'_>) {
         t_state.modify(|t_source| match t_source {
             T::ResourceVisualization(rv) => Some(T::ResourceVisualization (RV {
                 storage_id: rv.storage_id,
-                position:   mirror_z_point(&rv.position),
+                position:   rv.position.clone(),
                 rotation:   0f32 - rv.rotation,
                 scale:      rv.scale.clone(),
                 numstep_x:  rv.numstep_x,
                 numstep_z:  ((0f32 - rv.numstep_z.0), rv.numstep_z.1),
             })),
             // must flip these points, otherwise the text faces backwards
-            T::TextCaption((p1, p2)) => Some(T::TextCaption((mirror_z_point(p2), mirror_z_point(p1)))),
-            T::ParticleSnowRemove((p, i, r)) => Some(T::ParticleSnowRemove((mirror_z_point(p), *i, *r))),
-            other => transform_point(other, |p| mirror_z_point(p))
-                     .or_else(|| transform_rect(t_source, |r|
+            T::TextCaption((p1, p2)) => Some(T::TextCaption((p2.clone(), p1.clone()))),
+            other => transform_rect(other, |r|
                         Rect {  x1: r.x1, 
                                 z1: 0f32 - r.z1, 
                                 x2: r.x2, 
-                                z2: 0f32 - r.z2 }))
+                                z2: 0f32 - r.z2 })
         });
     }
+
+    for p in file.point_tokens_mut() {
+        *p = mirror_z_point(p);
+    }
 }
 
 pub fn mirror_z_render(f: &mut ini::RenderIni<'_>) {
@@ -146,49 +165,291 @@ pub fn mirror_z_render(f: &mut ini::RenderIni<'_>) {
 //----------------------------------------------------------------------------------------------
 
 
-fn transform_point<'a, F: Fn(&Point3f) -> Point3f>(t: &BuildingToken<'a>, f: F) -> Option<BuildingToken<'a>> {
-    use crate::ini::BuildingToken as T;
-    match t {
-        T::VehicleStation((p1, p2))               => Some(T::VehicleStation((                f(p1), f(p2)  ))),
-        T::VehicleStationDetourPoint(p1)          => Some(T::VehicleStationDetourPoint(      f(p1)          )),
-        T::VehicleStationDetourPid((i, p1))       => Some(T::VehicleStationDetourPid((  *i,  f(p1)         ))),
-        T::VehicleParking((p1, p2))               => Some(T::VehicleParking((                f(p1), f(p2)  ))),
-        T::VehicleParkingDetourPoint(p1)          => Some(T::VehicleParkingDetourPoint(      f(p1)          )),
-        T::VehicleParkingDetourPid((i, p1))       => Some(T::VehicleParkingDetourPid((  *i,  f(p1)         ))),
-        T::VehicleParkingPersonal((p1, p2))       => Some(T::VehicleParkingPersonal((        f(p1), f(p2)  ))),
-
-        T::AirplaneStation((t, p1, p2))           => Some(T::AirplaneStation((          *t,  f(p1), f(p2)  ))),
-        T::HeliportStation((p1, p2))              => Some(T::HeliportStation((               f(p1), f(p2)  ))),
-        T::ShipStation((p1, p2))                  => Some(T::ShipStation((                   f(p1), f(p2)  ))),
-
-        T::Connection2Points((t, p1, p2))         => Some(T::Connection2Points((        *t,  f(p1), f(p2)  ))),
-        T::Connection1Point((t, p1))              => Some(T::Connection1Point((         *t,  f(p1)         ))),
-        T::OffsetConnection((i, p1))              => Some(T::OffsetConnection((         *i,  f(p1)         ))),
-
-        T::Particle((t, p1, x, y))                => Some(T::Particle((                  *t, f(p1), *x, *y ))),
-        T::ParticleReactor(p1)                    => Some(T::ParticleReactor(                f(p1)          )),
-
-        T::TextCaption((p1, p2))                  => Some(T::TextCaption((                   f(p1), f(p2)  ))),
-        T::WorkerRenderingArea((p1, p2))          => Some(T::WorkerRenderingArea((           f(p1), f(p2)  ))),
-        T::ResourceIncreasePoint((i, p1))         => Some(T::ResourceIncreasePoint((    *i,  f(p1)         ))),
-        T::ResourceIncreaseConvPoint((i, p1, p2)) => Some(T::ResourceIncreaseConvPoint((*i,  f(p1), f(p2)  ))),
-        T::ResourceFillingPoint(p1)               => Some(T::ResourceFillingPoint(           f(p1)          )),
-        T::ResourceFillingConvPoint((p1, p2))     => Some(T::ResourceFillingConvPoint((      f(p1), f(p2)  ))),
-
-        T::CostWorkVehicleStation((p1, p2))       => Some(T::CostWorkVehicleStation((        f(p1), f(p2)  ))),
-
-        _ => None 
-    }
-}
-
-
 fn transform_rect<'a, F: Fn(&Rect) -> Rect>(t: &BuildingToken<'a>, f: F) -> Option<BuildingToken<'a>> {
     use crate::ini::BuildingToken as T;
     match t {
         T::ConnectionsSpace(r)                 => Some(T::ConnectionsSpace(f(r))),
         T::ConnectionsRoadDeadSquare(r)        => Some(T::ConnectionsRoadDeadSquare(f(r))),
+        T::ConnectionsAirportDeadSquare(r)     => Some(T::ConnectionsAirportDeadSquare(f(r))),
         T::ConnectionsWaterDeadSquare((x, r))  => Some(T::ConnectionsWaterDeadSquare((*x, f(r)))),
         _ => None 
     }
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ini::IniTokenState;
+    use std::collections::HashSet;
+    use std::mem::discriminant;
+
+    // Whether scale_building must change the token. No wildcard on purpose: a newly added token
+    // does not compile here until it is put into one of the groups (and into SAMPLE if it is geometric).
+    fn is_geometric(t: &BuildingToken<'_>) -> bool {
+        use crate::ini::BuildingToken as T;
+        match t {
+            // points
+            T::VehicleStation(_) | T::VehicleStationDetourPoint(_) | T::VehicleStationDetourPid(_) | T::VehicleParking(_)
+          | T::VehicleParkingDetourPoint(_) | T::VehicleParkingDetourPid(_) | T::VehicleParkingPersonal(_)
+          | T::AirplaneStation(_) | T::HeliportStation(_) | T::ShipStation(_) | T::Connection2Points(_)
+          | T::Connection1Point(_) | T::OffsetConnection(_) | T::Particle(_) | T::ParticleReactor(_)
+          | T::ParticleSnowRemove(_) | T::TextCaption(_) | T::WorkerRenderingArea(_) | T::ResourceVisualization(_)
+          | T::ResourceIncreasePoint(_) | T::ResourceIncreaseConvPoint(_) | T::ResourceFillingPoint(_)
+          | T::ResourceFillingConvPoint(_) | T::CostWorkVehicleStation(_)
+            // rects
+          | T::ConnectionsSpace(_) | T::ConnectionsRoadDeadSquare(_) | T::ConnectionsAirportDeadSquare(_)
+          | T::ConnectionsWaterDeadSquare(_)
+            // lengths
+          | T::HeliportArea(_) | T::HarborTerrainFrom(_) | T::HarborWaterFrom(_) | T::HarborExtendWhenBuilding(_)
+                => true,
+
+            T::NameStr(_) | T::Name(_) | T::BuildingType(_) | T::BuildingSubtype(_) | T::HeatEnable
+          | T::HeatDisable | T::CivilBuilding | T::MonumentTrespass | T::QualityOfLiving(_)
+          | T::WorkersNeeded(_) | T::ProfessorsNeeded(_) | T::CitizenAbleServe(_) | T::Consumption(_)
+          | T::ConsumptionPerSec(_) | T::Production(_) | T::ProductionSun(_) | T::ProductionWind(_)
+          | T::SeasonalTempMin(_) | T::SeasonalTempMax(_) | T::EleConsumWorkerFactorNight(_)
+          | T::EleConsumWorkerFactorBase(_) | T::EleConsumServeFactorNight(_) | T::EleConsumServeFactorBase(_)
+          | T::EleConsumCargoLoadFactor(_) | T::EleConsumCargoUnloadFactor(_) | T::NoEleWorkFactorBase(_)
+          | T::NoEleWorkFactorNight(_) | T::NoHeatWorkFactor(_) | T::EngineSpeed(_) | T::CablewayHeavy
+          | T::CablewayLight | T::ResourceSource(_) | T::Storage(_) | T::StorageSpecial(_) | T::StorageFuel(_)
+          | T::StorageExport(_) | T::StorageImport(_) | T::StorageImportCarplant(_) | T::StorageExportSpecial(_)
+          | T::StorageImportSpecial(_) | T::StorageDemandBasic(_) | T::StorageDemandMediumAdvanced(_)
+          | T::StorageDemandAdvanced(_) | T::StorageDemandHotel(_) | T::StoragePackFrom(_)
+          | T::StorageUnpackTo(_) | T::StorageLivingAuto(_) | T::VehicleLoadingFactor(_)
+          | T::VehicleUnloadingFactor(_) | T::RoadNotFlip | T::RoadElectric | T::VehicleCannotSelect
+          | T::LongTrains | T::WorkingVehiclesNeeded(_) | T::VehicleStationNotBlock | T::ConnectionRailDeadend
+          | T::AttractionType(_) | T::AttractionRememberUsage | T::AttractiveScoreBase(_) | T::AttractiveScoreAlcohol(_)
+          | T::AttractiveScoreCulture(_) | T::AttractiveScoreReligion(_) | T::AttractiveScoreSport(_)
+          | T::AttractiveFactorNature(_) | T::AttractiveFactorNatureAdd(_) | T::AttractiveFactorPollution(_)
+          | T::AttractiveFactorPollutionAdd(_) | T::AttractiveFactorSight(_) | T::AttractiveFactorSightAdd(_)
+          | T::AttractiveFactorWater(_) | T::AttractiveFactorWaterAdd(_) | T::PollutionHigh | T::PollutionMedium
+          | T::PollutionSmall | T::WorkingSfx(_) | T::AnimationFps(_) | T::AnimationMesh(_)
+          | T::UndergroundMesh(_) | T::CostWork(_) | T::CostWorkBuildingNode(_) | T::CostWorkBuildingKeyword(_)
+          | T::CostWorkBuildingAll | T::CostResource(_) | T::CostResourceAuto(_)
+          | T::CostWorkVehicleStationNode(_) | T::Unknown(_)
+                => false,
+        }
+    }
+
+    // Number of token types in the `true` arm of is_geometric
+    const GEOMETRIC_TOKENS: usize = 32;
+
+    // One line per geometric token type, plus a few that must stay untouched
+    const SAMPLE: &str = "\
+$WORKERS_NEEDED 10\r\n\
+$VEHICLE_STATION 1 0 1 2 0 2\r\n\
+$STATION_NOT_BLOCK_DETOUR_POINT 1 0 1\r\n\
+$STATION_NOT_BLOCK_DETOUR_POINT_PID 3 1 0 1\r\n\
+$VEHICLE_PARKING 1 0 1 2 0 2\r\n\
+$VEHICLE_PARKING_ADVANCED_POINT 1 0 1\r\n\
+$VEHICLE_PARKING_ADVANCED_POINT_PID 3 1 0 1\r\n\
+$VEHICLE_PARKING_PERSONAL 1 0 1 2 0 2\r\n\
+$AIRPLANE_STATION_30M 1 0 1 2 0 2\r\n\
+$HELIPORT_STATION 1 0 1 2 0 2\r\n\
+$SHIP_STATION 1 0 1 2 0 2\r\n\
+$HELIPORT_AREA 5\r\n\
+$HARBOR_OVER_TERRAIN_FROM 5\r\n\
+$HARBOR_OVER_WATER_FROM 5\r\n\
+$HARBOR_EXTEND_AREA_WHEN_BULDING 5\r\n\
+$CONNECTION_ROAD 1 0 1 2 0 2\r\n\
+$CONNECTION_ROAD_DEAD 1 0 1\r\n\
+$OFFSET_CONNECTION_XYZW 0 1 0 1\r\n\
+$CONNECTIONS_SPACE -1 -2 3 4\r\n\
+$CONNECTIONS_ROAD_DEAD_SQUARE -1 -2 3 4\r\n\
+$CONNECTIONS_AIRPORT_DEAD_SQUARE -1 -2 3 4\r\n\
+$CONNECTIONS_WATER_DEAD_SQUARE 1 -1 -2 3 4\r\n\
+$PARTICLE factory_big_black 1 0 1 1 1\r\n\
+$PARTICLE_REACTOR 1 0 1\r\n\
+$PARTICLE_SNOWREMOVE 1 0 1 2 5\r\n\
+$TEXT_CAPTION 1 0 1 2 0 2\r\n\
+$WORKER_RENDERING_AREA 1 0 1 2 0 2\r\n\
+$RESOURCE_VISUALIZATION 0 position 1 0 1 rotation 90 scale 1 1 1 numstepx 1 2 numstept 1 2\r\n\
+$RESOURCE_INCREASE_POINT 0 1 0 1\r\n\
+$RESOURCE_INCREASE_CONVEYOR_POINT 0 1 0 1 2 0 2\r\n\
+$RESOURCE_FILLING_POINT 1 0 1\r\n\
+$RESOURCE_FILLING_CONVEYOR_POINT 1 0 1 2 0 2\r\n\
+$COST_WORK_VEHICLE_STATION 1 0 1 2 0 2\r\n\
+$COST_WORK SOVIET_CONSTRUCTION_GROUNDWORKS 100\r\n\
+end\r\n";
+
+    #[test]
+    fn scale_changes_every_geometric_token() {
+        let mut ini = ini::parse_building_ini(SAMPLE).unwrap();
+        scale_building(&mut ini, 2.0, &CostScaling::None);
+
+        let mut seen = HashSet::new();
+        for (chunk, t_state) in ini.tokens.iter() {
+            let (t, modified) = match t_state {
+                IniTokenState::Original(t) => (t, false),
+                IniTokenState::Modified(t) => (t, true),
+            };
+
+            assert_eq!(modified, is_geometric(t), "{}", chunk.trim_end());
+            if modified {
+                seen.insert(discriminant(t));
+            }
+        }
+
+        assert_eq!(seen.len(), GEOMETRIC_TOKENS, "SAMPLE misses a geometric token type");
+    }
+
+    #[test]
+    fn scale_doubles_points_rects_and_lengths() {
+        use crate::ini::BuildingToken as T;
+
+        let mut ini = ini::parse_building_ini(SAMPLE).unwrap();
+        scale_building(&mut ini, 2.0, &CostScaling::None);
+
+        for t in ini.tokens() {
+            match t {
+                T::VehicleStation((p1, p2)) => {
+                    assert_eq!((p1.x, p1.y, p1.z), (2., 0., 2.));
+                    assert_eq!((p2.x, p2.y, p2.z), (4., 0., 4.));
+                },
+                T::ConnectionsAirportDeadSquare(r) | T::ConnectionsWaterDeadSquare((_, r)) =>
+                    assert_eq!((r.x1, r.z1, r.x2, r.z2), (-2., -4., 6., 8.)),
+                T::HeliportArea(x) | T::HarborExtendWhenBuilding(x) => assert_eq!(*x, 10.),
+                T::ParticleSnowRemove((p, _, r)) => assert_eq!((p.x, *r), (2., 10.)),
+                _ => {}
+            }
+        }
+    }
+
+    // annotated mode marks exactly the tokens that scaling rewrote, clean mode has no markers
+    #[test]
+    fn annotated_display_marks_transformed_tokens() {
+        let mut ini = ini::parse_building_ini("$WORKERS_NEEDED 10\r\n$HELIPORT_AREA 5\r\nend\r\n").unwrap();
+        scale_building(&mut ini, 2.0, &CostScaling::None);
+
+        let annotated = ini.display_tokens(true).to_string();
+        let lines = annotated.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(!lines[0].starts_with('*'), "{}", lines[0]);
+        assert!(lines[1].starts_with("* ") && lines[1].contains("HELIPORT_AREA"), "{}", lines[1]);
+
+        assert!(!ini.display_tokens(false).to_string().contains('*'));
+    }
+
+    // unknown tokens survive a lenient scale byte for byte, and are listed for the warning
+    #[test]
+    fn lenient_scale_passes_unknown_tokens_through() {
+        let src = "$HELIPORT_AREA 5\r\n$FUTURE_KEYWORD 1.5 abc\r\nend\r\n";
+        assert!(ini::parse_building_ini(src).is_err());
+
+        let mut ini = ini::parse_building_ini_lenient(src).unwrap();
+        assert_eq!(ini.unknown_tokens().collect::<Vec<_>>(), vec!["FUTURE_KEYWORD 1.5 abc"]);
+
+        scale_building(&mut ini, 2.0, &CostScaling::None);
+        let mut out = Vec::new();
+        ini.write_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("$FUTURE_KEYWORD 1.5 abc\r\n"), "{}", out);
+        assert!(out.contains("HELIPORT_AREA 10"), "{}", out);
+    }
+
+    fn resource_visualization<'a>(ini: &'a ini::BuildingIni<'_>) -> &'a ini::building::ResourceVisualization {
+        ini.tokens().find_map(|t| match t {
+            BuildingToken::ResourceVisualization(rv) => Some(rv),
+            _ => None
+        }).unwrap()
+    }
+
+    #[test]
+    fn scale_moves_resource_visualization_keeps_rotation() {
+        let src = "$RESOURCE_VISUALIZATION 0 position 1 2 3 rotation 90 scale 1 1 1 numstepx 0.5 4 numstept -0.5 3\r\nend\r\n";
+        let mut ini = ini::parse_building_ini(src).unwrap();
+        scale_building(&mut ini, 2.0, &CostScaling::None);
+
+        let rv = resource_visualization(&ini);
+        assert_eq!((rv.position.x, rv.position.y, rv.position.z), (2., 4., 6.));
+        assert_eq!(rv.rotation, 90.);
+        assert_eq!((rv.scale.x, rv.scale.y, rv.scale.z), (2., 2., 2.));
+        assert_eq!((rv.numstep_x, rv.numstep_z), ((1., 4), (-1., 3)));
+
+        mirror_z_building(&mut ini);
+        let rv = resource_visualization(&ini);
+        assert_eq!((rv.position.x, rv.position.z), (2., -6.));
+        assert_eq!(rv.rotation, -90.);
+    }
+
+    #[test]
+    fn cost_scaling_modes() {
+        let src = "$COST_WORK SOVIET_CONSTRUCTION_GROUNDWORKS 10\r\n\
+                   $COST_RESOURCE concrete 3\r\n\
+                   $COST_RESOURCE_AUTO ground_asphalt 1\r\n\
+                   $WORKERS_NEEDED 10\r\n\
+                   end\r\n";
+        let scaled = |cost_scaling: CostScaling| {
+            let mut ini = ini::parse_building_ini(src).unwrap();
+            scale_building(&mut ini, 2.0, &cost_scaling);
+            let mut out = Vec::new();
+            ini.write_to(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(scaled(CostScaling::None), src);
+        assert_eq!(scaled(CostScaling::Linear), "$COST_WORK SOVIET_CONSTRUCTION_GROUNDWORKS 20\r\n\
+                                                 $COST_RESOURCE concrete 6\r\n\
+                                                 $COST_RESOURCE_AUTO ground_asphalt 2\r\n\
+                                                 $WORKERS_NEEDED 10\r\n\
+                                                 end\r\n");
+        assert_eq!(scaled(CostScaling::Cubic), "$COST_WORK SOVIET_CONSTRUCTION_GROUNDWORKS 80\r\n\
+                                                $COST_RESOURCE concrete 24\r\n\
+                                                $COST_RESOURCE_AUTO ground_asphalt 8\r\n\
+                                                $WORKERS_NEEDED 10\r\n\
+                                                end\r\n");
+    }
+
+    fn offset_connection(ini: &ini::BuildingIni<'_>) -> (u32, (f32, f32, f32)) {
+        ini.tokens().find_map(|t| match t {
+            BuildingToken::OffsetConnection((i, p)) => Some((*i, (p.x, p.y, p.z))),
+            _ => None
+        }).unwrap()
+    }
+
+    // the connection index is kept, only the point moves
+    #[test]
+    fn offset_connection_is_scaled_and_mirrored() {
+        let mut ini = ini::parse_building_ini("$OFFSET_CONNECTION_XYZW 3 1.5 2 -4\r\nend\r\n").unwrap();
+
+        scale_building(&mut ini, 2.0, &CostScaling::None);
+        assert_eq!(offset_connection(&ini), (3, (3., 4., -8.)));
+
+        scale_building(&mut ini, 0.5, &CostScaling::None);
+        assert_eq!(offset_connection(&ini), (3, (1.5, 2., -4.)));
+
+        mirror_z_building(&mut ini);
+        assert_eq!(offset_connection(&ini), (3, (1.5, 2., 4.)));
+    }
+
+    // lengths follow the factor linearly, capacities are kept
+    #[test]
+    fn scale_harbor_building_lengths() {
+        use crate::ini::BuildingToken as T;
+
+        let src = "$HELIPORT_AREA 15\r\n\
+                   $HARBOR_OVER_TERRAIN_FROM -12.5\r\n\
+                   $HARBOR_OVER_WATER_FROM 8\r\n\
+                   $HARBOR_EXTEND_AREA_WHEN_BULDING 20\r\n\
+                   $STORAGE RESOURCE_TRANSPORT_COVERED 100\r\n\
+                   end\r\n";
+        let mut ini = ini::parse_building_ini(src).unwrap();
+        scale_building(&mut ini, 2.0, &CostScaling::None);
+
+        let mut seen = 0;
+        for t in ini.tokens() {
+            match t {
+                T::HeliportArea(x)             => assert_eq!(*x, 30.),
+                T::HarborTerrainFrom(x)        => assert_eq!(*x, -25.),
+                T::HarborWaterFrom(x)          => assert_eq!(*x, 16.),
+                T::HarborExtendWhenBuilding(x) => assert_eq!(*x, 40.),
+                T::Storage((_, x))             => assert_eq!(*x, 100.),
+                _ => unreachable!()
+            }
+            seen += 1;
+        }
+        assert_eq!(seen, 5);
+    }
+}
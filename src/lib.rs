@@ -0,0 +1,70 @@
+//! Modding tools for "Workers & Resources: Soviet Republic".
+//!
+//! The `wrsr-mt` binary is a thin CLI over this library: the parsers and writers for
+//! `*.nmf` models (`nmf`), `building.ini`/`renderconfig.ini`/`*.mtl` files (`ini`),
+//! whole building definitions (`building_def`) and modpacks (`modpack`).
+//!
+//! Paths in ini files that point into the game directories are resolved against an explicit
+//! [`ini::GameRoots`], the library never reads the command line.
+//!
+//! ```
+//! use wrsr_mt::ini::{self, BuildingToken};
+//!
+//! let src = "$NAME_STR \"Test building\"\r\n$WORKERS_NEEDED 25\r\nend";
+//! let building_ini = ini::parse_building_ini(src).unwrap();
+//!
+//! let workers = building_ini.tokens().find_map(|t| match t {
+//!     BuildingToken::WorkersNeeded(n) => Some(*n),
+//!     _ => None
+//! });
+//! assert_eq!(workers, Some(25));
+//! assert!(building_ini.get_name().is_some());
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+pub mod nmf;
+pub mod ini;
+
+pub mod building_def;
+pub mod modpack;
+
+#[cfg(test)]
+mod test_util;
+
+//mod data;
+//mod input;
+//mod output;
+
+
+pub const RENDERCONFIG_INI: &str = "renderconfig.ini";
+pub const BUILDING_INI:     &str = "building.ini";
+
+
+pub fn read_to_buf(path: &Path, buf: &mut Vec<u8>) -> Result<(), std::io::Error> {
+    use std::io::Read;
+    use std::convert::TryInto;
+    buf.clear();
+
+    let mut file = fs::File::open(path)?;
+    let meta = file.metadata()?;
+    let sz: usize = meta.len().try_into().expect("Cannot get file length");
+    buf.reserve(sz);
+    file.read_to_end(buf)?;
+    Ok(())
+}
+
+
+pub fn read_to_string_buf<P: AsRef<Path>>(path: P, buf: &mut String) -> Result<(), std::io::Error> {
+    use std::io::Read;
+    use std::convert::TryInto;
+    buf.clear();
+
+    let mut file = fs::File::open(path)?;
+    let meta = file.metadata()?;
+    let sz: usize = meta.len().try_into().expect("Cannot get file length");
+    buf.reserve(sz);
+    file.read_to_string(buf)?;
+    Ok(())
+}
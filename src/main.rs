@@ -4,19 +4,10 @@ use std::path::{Path, PathBuf};
 
 use const_format::concatcp;
 
-mod nmf;
-mod ini;
-
-mod building_def;
-mod modpack;
+use wrsr_mt::{nmf, ini, building_def, modpack, read_to_buf, read_to_string_buf};
 
 mod cfg;
 
-//mod data;
-//mod input;
-//mod output;
-
-
 use cfg::{AppSettings, APP_SETTINGS, RENDERCONFIG_INI, BUILDING_INI};
 
 
@@ -28,12 +19,17 @@ fn main() {
             print_dirs();
 
             match cmd {
-                cfg::ModpackCommand::Install(cfg::ModpackInstallCommand { source, destination }) => {
+                cfg::ModpackCommand::Install(cfg::ModpackInstallCommand { source, destination, visibility, exclude, only, max_buildings: install_limit, link_assets, timings: print_timings, clean, assets_dirs }) => {
                     println!("Installing from source: {}", source.display());
                     assert!(source.exists(), "Modpack source directory does not exist!");
                     println!("Reading modpack sources...");
 
-                    match modpack::read_validate_sources(source.as_path()) {
+                    let mut timings = modpack::InstallTimings::default();
+                    let t_start = std::time::Instant::now();
+                    let sources = modpack::read_validate_sources(source.as_path(), &APP_SETTINGS.roots, &exclude[..], &only[..], &mut Vec::new(), false);
+                    timings.validation = t_start.elapsed();
+
+                    match sources {
                         Ok((buildings, skins_count)) => {
                             println!("Found {} buildings, {} skins", buildings.len(), skins_count);
                             let max_buildings = AppSettings::MAX_BUILDINGS - (skins_count / AppSettings::MAX_SKINS_IN_MOD + 1) * AppSettings::MAX_BUILDINGS_IN_MOD;
@@ -43,29 +39,44 @@ fn main() {
 
                             let mut log_path = destination.to_path_buf();
                             log_path.push(modpack::MODPACK_LOG);
-                            if log_path.exists() {
+                            if *clean {
+                                assert!(log_path.exists(), "Cannot clean: destination has no {}, so it does not look like a previous modpack install.", modpack::MODPACK_LOG);
+                                let removed = modpack::uninstall(destination).expect("Failed to remove the previous install");
+                                println!("Removed previous install ({} mods)", removed);
+                            } else if log_path.exists() {
                                 panic!("Cannot proceed: target directory has {}, which indicates that a modpack has already been installed here.", modpack::MODPACK_LOG);
                             }
 
                             let log_file = fs::OpenOptions::new().write(true).create_new(true).open(log_path).expect("Cannot create log file");
                             let mut log_file = std::io::BufWriter::new(log_file);
 
-                            modpack::install(buildings, destination, &mut log_file);
+                            let options = modpack::InstallOptions {
+                                visibility: *visibility,
+                                max_buildings: *install_limit,
+                                link_assets: *link_assets,
+                                assets_dirs: (&assets_dirs.0, &assets_dirs.1),
+                            };
+                            modpack::install(buildings, destination, &APP_SETTINGS.roots, &options, &mut log_file, &mut timings);
 
                             log_file.flush().unwrap();
                             println!("Modpack installed");
+                            if *print_timings {
+                                println!("{}", timings);
+                            }
                         },
                         Err(e) => {
                             panic!("FAILED: encountered {} errors when reading sources", e);
                         }
                     }
                 },
-                cfg::ModpackCommand::Validate(source) => {
+                cfg::ModpackCommand::Validate(source, report_path, summary_only) => {
                     println!("Validating modpack at {}", source.display());
                     assert!(source.exists(), "Modpack source directory does not exist!");
                     println!("Reading modpack sources...");
 
-                    match modpack::read_validate_sources(source.as_path()) {
+                    let mut reports = Vec::<modpack::BuildingReport>::with_capacity(1000);
+                    let result = modpack::read_validate_sources(source.as_path(), &APP_SETTINGS.roots, &[], &[], &mut reports, *summary_only);
+                    match &result {
                         Ok((buildings, skins_count)) => {
                             println!("OK: found {} buildings, {} skins", buildings.len(), skins_count);
                         },
@@ -73,6 +84,42 @@ fn main() {
                             eprintln!("FAILED: encountered {} errors", e);
                         }
                     }
+
+                    if *summary_only {
+                        let warnings = reports.iter().map(|r| r.warnings.len()).sum::<usize>();
+                        if warnings > 0 {
+                            println!("{} warnings (not shown, see --report)", warnings);
+                        }
+                    }
+
+                    if let Some(report_path) = report_path {
+                        let errors = *result.as_ref().err().unwrap_or(&0);
+                        let f = fs::File::create(report_path).expect("Cannot create the report file");
+                        modpack::write_report(std::io::BufWriter::new(f), source, &reports[..], errors).expect("Cannot write the report file");
+                        println!("Report written to {}", report_path.display());
+                    }
+                },
+                cfg::ModpackCommand::Stats(source) => {
+                    assert!(source.exists(), "Modpack source directory does not exist!");
+                    println!("Reading modpack sources...");
+
+                    match modpack::read_validate_sources(source.as_path(), &APP_SETTINGS.roots, &[], &[], &mut Vec::new(), false) {
+                        Ok((buildings, skins_count)) => {
+                            let stats = modpack::SourceStats::collect(&buildings[..], skins_count).expect("Cannot read source assets");
+                            println!("{}", stats);
+                        },
+                        Err(e) => {
+                            eprintln!("FAILED: encountered {} errors", e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                cfg::ModpackCommand::NewBuilding(dir) => {
+                    let files = modpack::new_building(dir).unwrap_or_else(|e| panic!("Cannot create the building template: {}", e));
+                    for f in files.iter() {
+                        println!("Created {}", f.display());
+                    }
+                    println!("Done. Put the model and material in place, then check the building with 'modpack validate'");
                 },
             }
         },
@@ -80,137 +127,358 @@ fn main() {
 
         cfg::AppCommand::Nmf(cmd) => {
             match cmd {
-                cfg::NmfCommand::Show(path) => {
+                cfg::NmfCommand::Show(path, matrix) => {
                     let nmf = nmf::NmfInfo::from_path(path).expect("Failed to read the nmf file");
                     println!("{}", nmf);
+                    for o in nmf.empty_objects() {
+                        println!("WARNING: object '{}' has no geometry", o.name.as_str());
+                    }
+
+                    if *matrix {
+                        let nmf = nmf::NmfBufFull::from_path(path).expect("Failed to read the nmf file");
+                        for o in nmf.objects.iter() {
+                            let bytes = o.header_opaque();
+                            println!("\n{}:", o.name());
+                            for row in bytes[.. 64].chunks(16) {
+                                for f in row.chunks(4) {
+                                    print!(" {:>14.6}", f32::from_le_bytes([f[0], f[1], f[2], f[3]]));
+                                }
+                                println!();
+                            }
+                            for row in bytes[64 ..].chunks(16) {
+                                for w in row.chunks(4) {
+                                    print!(" {:02x}{:02x}{:02x}{:02x}", w[0], w[1], w[2], w[3]);
+                                }
+                                println!();
+                            }
+                        }
+                    }
                 },
 
-                cfg::NmfCommand::ToObj(cfg::FromToCommand { input, output }) => {
+                cfg::NmfCommand::Compare(path_a, path_b) => {
+                    let nmf_a = nmf::NmfInfo::from_path(path_a).expect("Failed to read the first nmf file");
+                    let nmf_b = nmf::NmfInfo::from_path(path_b).expect("Failed to read the second nmf file");
+                    let diffs = nmf_a.compare(&nmf_b);
+                    if diffs.is_empty() {
+                        println!("Identical structure");
+                    } else {
+                        for d in diffs.iter() {
+                            println!("{}", d);
+                        }
+                        println!("{} differences", diffs.len());
+                        std::process::exit(1);
+                    }
+                },
+
+                cfg::NmfCommand::Classify(paths) => {
+                    // Higher detail goes first; a single file ranks its own objects
+                    let mut ranked: Vec<(String, u64)> = if let [path] = &paths[..] {
+                        let nmf = nmf::NmfInfo::from_path(path).expect("Failed to read the nmf file");
+                        nmf.objects.iter().map(|o| (o.name.as_str().to_string(), o.vertices() as u64)).collect()
+                    } else {
+                        paths.iter().map(|p| {
+                            let nmf = nmf::NmfInfo::from_path(p).unwrap_or_else(|e| panic!("Failed to read the nmf file {}: {}", p.display(), e));
+                            (p.display().to_string(), nmf.vertices_count())
+                        }).collect()
+                    };
+
+                    ranked.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+                    const SLOTS: [&str; 3] = ["MODEL", "MODEL_LOD", "MODEL_LOD2"];
+                    for (i, (name, vx)) in ranked.iter().enumerate() {
+                        println!("{:2}) {:<10} {:>8} vertices  {}", i, SLOTS.get(i).unwrap_or(&"-"), vx, name);
+                    }
+                },
+
+                cfg::NmfCommand::Histogram(path) => {
+                    let nmf = nmf::NmfBufFull::from_path(path).expect("Failed to read the nmf file");
+                    let areas: Vec<f32> = nmf.objects.iter().flat_map(|o| o.face_areas()).collect();
+                    if areas.is_empty() {
+                        println!("No faces");
+                        return;
+                    }
+
+                    let total: f64 = areas.iter().map(|a| *a as f64).sum();
+                    println!("Faces: {}, total area: {:.4}, average: {:.6}", areas.len(), total, total / areas.len() as f64);
+
+                    // buckets by decade of the area; zero-area (degenerate) faces are counted separately
+                    let mut buckets = std::collections::BTreeMap::<i32, usize>::new();
+                    let mut degenerate = 0_usize;
+                    for a in areas.iter() {
+                        if *a > 0f32 {
+                            *buckets.entry(a.log10().floor() as i32).or_insert(0) += 1;
+                        } else {
+                            degenerate += 1;
+                        }
+                    }
+
+                    let max_count = buckets.values().copied().max().unwrap_or(0).max(1);
+                    for (decade, count) in buckets.iter() {
+                        let bar = "#".repeat((count * 40 + max_count - 1) / max_count);
+                        println!("  [{:>9}, {:>9})  {:>8}  {}", format!("{:e}", 10f64.powi(*decade)), format!("{:e}", 10f64.powi(decade + 1)), count, bar);
+                    }
+
+                    if degenerate > 0 {
+                        println!("  {:<22}  {:>8}", "degenerate (0)", degenerate);
+                    }
+                },
+
+                cfg::NmfCommand::VerifyLength(path) => {
+                    let (declared, actual) = nmf::declared_length(path).expect("Failed to read the nmf header");
+                    println!("Header length: {}", declared);
+                    println!("File length:   {}", actual);
+                    if declared as u64 == actual {
+                        println!("OK");
+                    } else {
+                        println!("MISMATCH: file is {} by {} bytes", if actual > declared as u64 { "longer" } else { "shorter" }, (actual as i64 - declared as i64).abs());
+                        std::process::exit(1);
+                    }
+                },
+
+                cfg::NmfCommand::Check(path, uv_bound) => {
+                    let nmf = nmf::NmfBufFull::from_path(path).expect("Failed to read the nmf file");
+                    let warnings = nmf.check(*uv_bound);
+                    for w in warnings.iter() {
+                        println!("WARNING: {}", w);
+                    }
+                    if warnings.is_empty() {
+                        println!("OK");
+                    }
+                },
+
+                cfg::NmfCommand::ToObj(cfg::FromToCommand { input, output }, group_by_sm, index_base) => {
                     let nmf = nmf::NmfBufFull::from_path(input).expect("Failed to read the nmf file");
 
-                    let f_out = fs::OpenOptions::new()
-                                    .write(true)
-                                    .create_new(true)
-                                    .open(output)
-                                    .expect("Cannot create output file");
-
-                    let mut wr = std::io::BufWriter::new(f_out);
-
-                    use nmf::object_full::{RawVertex, RawPoint};
-                    let mut vx_map = ahash::AHashMap::<&RawVertex, usize>::with_capacity(0);
-                    let mut n1_map = ahash::AHashMap::<&RawVertex, usize>::with_capacity(0);
-                    let mut uv_map = ahash::AHashMap::<&RawPoint, usize>::with_capacity(0);
-                    let mut vx_vec = Vec::<usize>::with_capacity(0);
-                    let mut n1_vec = Vec::<usize>::with_capacity(0);
-                    let mut uv_vec = Vec::<usize>::with_capacity(0);
-
-                    let mut d_vx = 0_usize;
-                    let mut d_n1 = 0_usize;
-                    let mut d_uv = 0_usize;
-
-                    for obj in nmf.objects.iter() {
-                        let verts = obj.vertices();
-
-                        vx_map.clear();
-                        n1_map.clear();
-                        uv_map.clear();
-
-                        vx_vec.clear();
-                        n1_vec.clear();
-                        uv_vec.clear();
-
-                        vx_map.reserve(verts.len());
-                        n1_map.reserve(verts.len());
-                        uv_map.reserve(verts.len());
-
-                        vx_vec.reserve(verts.len());
-                        n1_vec.reserve(verts.len());
-                        uv_vec.reserve(verts.len());
-
-                        writeln!(wr, "o {}", obj.name()).unwrap();
-
-                        for v in verts {
-                            if !vx_map.contains_key(v) {
-                                d_vx += 1;
-                                vx_map.insert(v, d_vx);
-                                vx_vec.push(d_vx);
-                                writeln!(wr, "v {:.6} {:.6} {:.6}", v.x, v.y, v.z).unwrap();
-                            } else {
-                                vx_vec.push(vx_map[v]);
+                    let submaterials = if *group_by_sm { Some(&nmf.submaterials[..]) } else { None };
+                    nmf::export::write_obj(nmf.objects.iter(), create_output(output), submaterials, index_base).expect("Failed writing the obj file");
+                    println!("Done");
+                },
+
+                cfg::NmfCommand::Export(cfg::FromToCommand { input, output }, format) => {
+                    let nmf = nmf::NmfBufFull::from_path(input).expect("Failed to read the nmf file");
+                    match format {
+                        cfg::ExportFormat::Obj => nmf::export::write_obj(nmf.objects.iter(), create_output(output), None, &nmf::export::ObjIndexBase::File),
+                        cfg::ExportFormat::Stl => nmf::export::write_stl(nmf.objects.iter(), create_output(output)),
+                    }.expect("Failed writing the output file");
+                    println!("Done");
+                },
+
+                cfg::NmfCommand::ExtractObject(cfg::ExtractObjectCommand { input, object, output }) => {
+                    let nmf = nmf::NmfBufFull::from_path(input).expect("Failed to read the nmf file");
+
+                    match nmf.objects.iter().find(|o| o.name() == object) {
+                        Some(obj) => {
+                            nmf::export::write_obj(std::iter::once(obj), create_output(output), None, &nmf::export::ObjIndexBase::File).expect("Failed writing the obj file");
+                            println!("Done");
+                        },
+                        None => {
+                            eprintln!("Object '{}' not found. Available objects:", object);
+                            for o in nmf.objects.iter() {
+                                eprintln!("    {}", o.name());
                             }
+                            std::process::exit(1);
                         }
+                    }
+                },
 
-                        let uvs = obj.uv_map();
-                        for uv in uvs {
-                            if !uv_map.contains_key(uv) {
-                                d_uv += 1;
-                                uv_map.insert(uv, d_uv);
-                                uv_vec.push(d_uv);
-                                writeln!(wr, "vt {:.6} {:.6}", uv.x, 1f32 - uv.y).unwrap();
-                            } else {
-                                uv_vec.push(uv_map[uv]);
+                cfg::NmfCommand::DumpObject(cfg::ExtractObjectCommand { input, object, output }) => {
+                    let bytes = fs::read(input).expect("Failed to read the nmf file");
+                    let nmf = nmf::NmfInfo::from_bytes(&bytes).expect("Failed to parse the nmf file");
+
+                    match nmf.objects.iter().find(|o| o.name.as_str() == object) {
+                        Some(obj) => {
+                            let range = obj.byte_range();
+                            fs::write(output, &bytes[range.start as usize .. range.end as usize]).expect("Failed to write the object bytes");
+                            println!("Done: {} bytes ({}..{})", range.end - range.start, range.start, range.end);
+                        },
+                        None => {
+                            eprintln!("Object '{}' not found. Available objects:", object);
+                            for name in nmf.object_names() {
+                                eprintln!("    {}", name);
                             }
+                            std::process::exit(1);
                         }
+                    }
+                },
+
+                cfg::NmfCommand::RenameObject(cfg::RenameObjectCommand { input, object, new_name, output }) => {
+                    let mut nmf = nmf::NmfBufFull::from_path(input).expect("Failed to read the nmf file");
+                    assert!(nmf.objects.iter().all(|o| o.name() != new_name), "Object '{}' already exists", new_name);
 
-                        let ns = obj.normals_1();
-                        for n in ns {
-                            if !n1_map.contains_key(n) {
-                                d_n1 += 1;
-                                n1_map.insert(n, d_n1);
-                                n1_vec.push(d_n1);
-                                writeln!(wr, "vn {:.6} {:.6} {:.6}", n.x, n.y, n.z).unwrap();
-                            } else {
-                                n1_vec.push(n1_map[n]);
+                    match nmf.objects.iter_mut().find(|o| o.name() == object) {
+                        Some(obj) => {
+                            assert!(obj.set_name(new_name), "Invalid object name '{}': must be 1 to 64 bytes long, without NUL", new_name);
+                            nmf.write_to_file(output).unwrap();
+                            println!("Done");
+                        },
+                        None => {
+                            eprintln!("Object '{}' not found. Available objects:", object);
+                            for o in nmf.objects.iter() {
+                                eprintln!("    {}", o.name());
                             }
+                            std::process::exit(1);
                         }
+                    }
+                },
 
-                        writeln!(wr, "s off").unwrap();
+                cfg::NmfCommand::Scale(cfg::ScaleCommand { input, factor, output }) => {
+                    let mut nmf = nmf::NmfBufFull::from_path_parallel(input).expect("Failed to read the nmf file");
+                    for o in nmf.objects.iter_mut() {
+                        o.scale(*factor).unwrap_or_else(|e| panic!("Scaling object {} failed: {}", o.name(), e));
+                    }
+                    nmf.write_to_file(output).unwrap();
+                    println!("Done");
+                },
 
-                        for f in obj.faces() {
-                            let v1  = vx_vec[f.v1 as usize];
-                            let n1  = n1_vec[f.v1 as usize];
-                            let uv1 = uv_vec[f.v1 as usize];
+                cfg::NmfCommand::Mirror(cfg::FromToCommand { input, output }) => {
+                    let mut nmf = nmf::NmfBufFull::from_path_parallel(input).expect("Failed to read the nmf file");
+                    for o in nmf.objects.iter_mut() {
+                        o.mirror_z();
+                    }
+                    nmf.write_to_file(output).unwrap();
+                    println!("Done");
+                },
 
-                            let v2  = vx_vec[f.v2 as usize];
-                            let n2  = n1_vec[f.v2 as usize];
-                            let uv2 = uv_vec[f.v2 as usize];
+                cfg::NmfCommand::CheckReversible(input, transform) => {
+                    let mut nmf = nmf::NmfBufFull::from_path_parallel(input).expect("Failed to read the nmf file");
+                    let original: Vec<Vec<nmf::object_full::RawVertex>> = nmf.objects.iter().map(|o| o.vertices().to_vec()).collect();
 
-                            let v3  = vx_vec[f.v3 as usize];
-                            let n3  = n1_vec[f.v3 as usize];
-                            let uv3 = uv_vec[f.v3 as usize];
+                    for o in nmf.objects.iter_mut() {
+                        match transform {
+                            cfg::NmfTransform::Scale(factor) => {
+                                o.scale(*factor).unwrap_or_else(|e| panic!("Scaling object {} failed: {}", o.name(), e));
+                                o.scale(1f64 / *factor).unwrap_or_else(|e| panic!("Scaling object {} back failed: {}", o.name(), e));
+                            },
+                            cfg::NmfTransform::Mirror => {
+                                o.mirror_z();
+                                o.mirror_z();
+                            },
+                        }
+                    }
 
-                            write!(wr, "f {}/{}/{}",   v1, uv1, n1).unwrap();
-                            write!(wr, "  {}/{}/{}",   v2, uv2, n2).unwrap();
-                            write!(wr, "  {}/{}/{}\n", v3, uv3, n3).unwrap();
+                    let mut max_drift = (0f32, "");
+                    for (o, orig) in nmf.objects.iter().zip(original.iter()) {
+                        for (v, v0) in o.vertices().iter().zip(orig.iter()) {
+                            let drift = (v.x - v0.x).abs().max((v.y - v0.y).abs()).max((v.z - v0.z).abs());
+                            if drift > max_drift.0 {
+                                max_drift = (drift, o.name());
+                            }
                         }
                     }
 
-                    wr.flush().expect("Failed flushing the output");
+                    let (drift, name) = max_drift;
+                    if drift > 0f32 {
+                        println!("Max coordinate drift: {:e} (object '{}')", drift, name);
+                    } else {
+                        println!("Max coordinate drift: 0 (exactly reversible)");
+                    }
+                },
+
+                cfg::NmfCommand::TransformBuilding(cfg::TransformBuildingCommand { render, transform, output }) => {
+                    let render_root = render.parent().expect("Cannot get renderconfig directory");
+                    let bld_def = building_def::ModBuildingDef::from_render_path(&render_root.join(BUILDING_INI), render, &APP_SETTINGS.roots, ini::normalize_join, false)
+                        .expect("Cannot parse renderconfig");
+
+                    let models = std::iter::once(&bld_def.model)
+                        .chain(bld_def.model_lod.iter())
+                        .chain(bld_def.model_lod2.iter())
+                        .chain(bld_def.model_e.iter());
+
+                    let mut done = Vec::<&Path>::with_capacity(4);
+                    for path in models {
+                        if done.contains(&path.as_path()) {
+                            continue;
+                        }
+
+                        // keep the layout of models in the renderconfig directory, the rest goes to the output root
+                        let out_path = match path.strip_prefix(render_root) {
+                            Ok(rel_path) => output.join(rel_path),
+                            Err(_) => output.join(path.file_name().unwrap()),
+                        };
+                        assert!(&out_path != path, "Output cannot overwrite the source model {}", path.display());
+
+                        let mut nmf = nmf::NmfBufFull::from_path_parallel(path).expect("Failed to read the nmf file");
+                        for o in nmf.objects.iter_mut() {
+                            match transform {
+                                cfg::NmfTransform::Scale(factor) => o.scale(*factor).unwrap_or_else(|e| panic!("Scaling object {} failed: {}", o.name(), e)),
+                                cfg::NmfTransform::Mirror        => o.mirror_z(),
+                            }
+                        }
+
+                        fs::create_dir_all(out_path.parent().unwrap()).expect("Cannot create output directory");
+                        nmf.write_to_file(&out_path).expect("Failed to write the updated nmf");
+                        println!("{}: OK", out_path.display());
+                        done.push(path);
+                    }
                     println!("Done");
                 },
 
-                cfg::NmfCommand::Scale(cfg::ScaleCommand { input, factor, output }) => {
+                cfg::NmfCommand::RecalcNormals(cfg::FromToCommand { input, output }, mode) => {
                     let mut nmf = nmf::NmfBufFull::from_path(input).expect("Failed to read the nmf file");
                     for o in nmf.objects.iter_mut() {
-                        o.scale(*factor);
+                        let shared = o.recalc_normals(mode);
+                        if shared > 0 {
+                            println!("WARNING: object '{}': {} vertices are shared by faces with different normals, their normals are averaged", o.name(), shared);
+                        }
                     }
                     nmf.write_to_file(output).unwrap();
                     println!("Done");
                 },
 
-                cfg::NmfCommand::Mirror(cfg::FromToCommand { input, output }) => {
+                cfg::NmfCommand::Uv(cfg::FromToCommand { input, output }, (su, sv), (ou, ov)) => {
                     let mut nmf = nmf::NmfBufFull::from_path(input).expect("Failed to read the nmf file");
                     for o in nmf.objects.iter_mut() {
-                        o.mirror_z();
+                        o.transform_uv(*su, *sv, *ou, *ov);
                     }
                     nmf.write_to_file(output).unwrap();
                     println!("Done");
                 },
 
+                cfg::NmfCommand::GenMtl(cfg::FromToCommand { input, output }, used_only) => {
+                    let nmf = nmf::NmfInfo::from_path(input).expect("Failed to read the nmf file");
+                    let f_out = fs::OpenOptions::new().write(true).create_new(true).open(output).expect("Cannot create output file");
+                    let mut wr = io::BufWriter::new(f_out);
+
+                    let count = if *used_only {
+                        ini::material::write_stub(&mut wr, nmf.get_used_sumbaterials()).unwrap();
+                        nmf.get_used_sumbaterials().count()
+                    } else {
+                        ini::material::write_stub(&mut wr, nmf.submaterials.iter().map(|sm| sm.as_str())).unwrap();
+                        nmf.submaterials.len()
+                    };
+
+                    wr.flush().unwrap();
+                    println!("Done. Written {} submaterials to {}", count, output.display());
+                },
+
+                cfg::NmfCommand::NormalizeSubmaterials(cfg::FromToCommand { input, output }) => {
+                    let mut nmf = nmf::NmfBufFull::from_path(input).expect("Failed to read the nmf file");
+                    if let Err(e) = nmf.normalize_submaterials() {
+                        eprintln!("Cannot normalize submaterials: {}", e);
+                        std::process::exit(1);
+                    }
+                    nmf.write_to_file(output).unwrap();
+                    println!("Done");
+                },
+
+                cfg::NmfCommand::Repair(cfg::FromToCommand { input, output }) => {
+                    let bytes = fs::read(input).expect("Cannot read the nmf file");
+                    let (nmf, changes) = nmf::NmfBufFull::repair(&bytes).unwrap_or_else(|e| panic!("Cannot repair the nmf file: {}", e));
+                    for c in changes.iter() {
+                        println!("{}", c);
+                    }
+                    nmf.write_to_file(output).unwrap();
+                    nmf::NmfBufFull::from_path(output).expect("Repaired file still fails to load");
+                    println!("Done. {} changes, {} objects left", changes.len(), nmf.objects.len());
+                },
+
                 cfg::NmfCommand::Optimize(cfg::FromToCommand { input, output }) => {
                     let mut nmf = nmf::NmfBufFull::from_path(input).expect("Failed to read the nmf file");
                     for o in nmf.objects.iter_mut() {
-                        o.optimize_indices();
+                        if let Err(e) = o.optimize_indices() {
+                            eprintln!("Optimizing object {} failed: {}", o.name(), e);
+                            std::process::exit(1);
+                        }
                     }
                     nmf.write_to_file(output).unwrap();
                     println!("Done");
@@ -223,16 +491,28 @@ fn main() {
         cfg::AppCommand::ModBuilding(cmd) => {
             use building_def::ModBuildingDef;
 
-            fn check_and_copy_building(dir_input: &PathBuf, dir_output: &PathBuf) -> ModBuildingDef {
+            // dry_run: only checks, the returned definition points to the input files
+            fn check_and_copy_building(dir_input: &Path, dir_output: &Path, dry_run: bool) -> ModBuildingDef {
                 let render_ini = dir_input.join(RENDERCONFIG_INI);
                 let bld_ini = dir_input.join(BUILDING_INI);
-                let bld_def = ModBuildingDef::from_render_path(&bld_ini, &render_ini, ini::normalize_join, false)
+                let bld_def = ModBuildingDef::from_render_path(&bld_ini, &render_ini, &APP_SETTINGS.roots, ini::normalize_join, false)
                     .expect("Cannot parse building");
 
                 {
-                    let check_path = |path: &Path| assert!(path.starts_with(dir_input), 
+                    // resolved paths can go up and out with '..' (and shallow_copy_to would then copy a file onto itself)
+                    let lexical = |p: &Path| p.components().fold(PathBuf::new(), |mut acc, c| {
+                        match c {
+                            std::path::Component::ParentDir => { acc.pop(); },
+                            std::path::Component::CurDir => (),
+                            c => acc.push(c),
+                        }
+                        acc
+                    });
+                    let dir_input = lexical(dir_input);
+
+                    let check_path = |path: &Path| assert!(lexical(path).starts_with(&dir_input), 
                                           "To update the whole building in one operation, all potentially modified files (building.ini, \
-                                          renderconfig.ini, *.nmf) must be located in the input directory. Otherwise you should update \
+                                          renderconfig.ini, *.nmf, imagegui.png) must be located in the input directory. Otherwise you should update \
                                           files individually, one-by-one (using appropriate commands).");
 
                     let check_path_opt = |opt: &Option<PathBuf>| if let Some(p) = opt.as_ref() { check_path(p) };
@@ -243,6 +523,12 @@ fn main() {
                     check_path_opt(&bld_def.model_lod);
                     check_path_opt(&bld_def.model_lod2);
                     check_path_opt(&bld_def.model_e);
+                    check_path_opt(&bld_def.image_gui);
+                }
+
+                if dry_run {
+                    println!("Building parsed successfully. Dry run: no files are copied or written");
+                    return bld_def;
                 }
 
                 println!("Building parsed successfully. Copying files...");
@@ -252,27 +538,39 @@ fn main() {
             }
 
             macro_rules! modify_ini {
-                ($buf:ident, $path:expr, $name:expr, $parser:expr, $modifier:expr $(, $m_p:expr)*) => {{
+                ($buf:ident, $path:expr, $name:expr, $prec:expr, $dry_run:expr, $parser:expr, $modifier:expr $(, $m_p:expr)*) => {{
                     read_to_string_buf($path, &mut $buf).expect(concatcp!("Cannot read ", $name));
                     let mut ini = $parser(&mut $buf).expect(concatcp!("Cannot parse ", $name));
                     $modifier(&mut ini $(, $m_p)*);
-                    let mut out_writer = io::BufWriter::new(fs::OpenOptions::new().write(true).truncate(true).open($path).unwrap());
-                    ini.write_to(&mut out_writer).unwrap();
-                    out_writer.flush().unwrap();
-                    println!("{}: OK", $name);
+                    if $dry_run {
+                        let modified = ini.tokens_mut().filter(|t| matches!(t, ini::IniTokenState::Modified(_))).count();
+                        println!("{}: {} tokens would be modified", $name, modified);
+                    } else {
+                        ini.set_float_precision($prec);
+                        let mut out_writer = io::BufWriter::new(fs::OpenOptions::new().write(true).truncate(true).open($path).unwrap());
+                        ini.write_to(&mut out_writer).unwrap();
+                        out_writer.flush().unwrap();
+                        println!("{}: OK", $name);
+                    }
                 }};
             }
 
-            fn modify_models<F: Fn(&mut nmf::ObjectFull)>(bld_def: &ModBuildingDef, pfx: &Path, obj_modifier: F) {
+            // obj_modifier returns the number of vertices it has changed. In dry run models are only modified in memory
+            fn modify_models<F: Fn(&mut nmf::ObjectFull) -> usize>(bld_def: &ModBuildingDef, pfx: &Path, action: &str, dry_run: bool, obj_modifier: F) {
                 let modify_nmf = |path: Option<&PathBuf>| {
                     if let Some(path) = path {
-                        let mut nmf = nmf::NmfBufFull::from_path(path).expect("Failed to read the nmf file");
+                        let mut nmf = nmf::NmfBufFull::from_path_parallel(path).expect("Failed to read the nmf file");
+                        let mut vertices = 0_usize;
                         for o in nmf.objects.iter_mut() {
-                            obj_modifier(o);
+                            vertices += obj_modifier(o);
                         }
 
-                        nmf.write_to_file(path).expect("Failed to write the updated nmf");
-                        println!("{}: OK", path.strip_prefix(pfx).unwrap().display());
+                        if dry_run {
+                            println!("{}: would be {} ({} objects, {} vertices)", path.strip_prefix(pfx).unwrap().display(), action, nmf.objects.len(), vertices);
+                        } else {
+                            nmf.write_to_file(path).expect("Failed to write the updated nmf");
+                            println!("{}: OK ({} {} objects, {} vertices)", path.strip_prefix(pfx).unwrap().display(), action, nmf.objects.len(), vertices);
+                        }
                     }
                 };
 
@@ -284,12 +582,52 @@ fn main() {
 
 
             match cmd {
-                cfg::ModCommand::Validate(dir_input) => {
+                cfg::ModCommand::Validate(dir_input, strict, fix, node_refs) => {
                     let bld_ini = dir_input.join(BUILDING_INI);
                     let render_ini = dir_input.join(RENDERCONFIG_INI);
-                    match building_def::ModBuildingDef::from_render_path(&bld_ini, &render_ini, ini::normalize_join, true) {
-                        Ok(bld) => {
-                            println!("{}\nOK", bld);
+
+                    if *fix {
+                        let changes = ModBuildingDef::from_render_path(&bld_ini, &render_ini, &APP_SETTINGS.roots, ini::normalize_join, false)
+                            .and_then(|bld| bld.fix());
+
+                        match changes {
+                            Ok(changes) => {
+                                for c in changes.iter() {
+                                    println!("FIXED: {}", c);
+                                }
+                                println!("{} fixes applied", changes.len());
+                            },
+                            Err(e) => {
+                                eprintln!("Cannot fix the building:\n{}", e);
+                                std::process::exit(1);
+                            }
+                        }
+                    }
+
+                    let validated = ModBuildingDef::from_render_path(&bld_ini, &render_ini, &APP_SETTINGS.roots, ini::normalize_join, false)
+                        .and_then(|bld| bld.parse_and_validate(None).map(|warnings| (bld, warnings)));
+
+                    match validated {
+                        Ok((bld, warnings)) => {
+                            println!("{}", bld);
+                            for w in warnings.iter() {
+                                eprintln!("WARNING: {}", w);
+                            }
+
+                            if *node_refs {
+                                let lines = bld.node_refs_report().unwrap_or_else(|e| panic!("Cannot list node references: {}", e));
+                                println!("Node references:");
+                                for l in lines.iter() {
+                                    println!("    {}", l);
+                                }
+                            }
+
+                            if *strict && !warnings.is_empty() {
+                                eprintln!("Building has {} warnings (--strict)", warnings.len());
+                                std::process::exit(1);
+                            }
+
+                            println!("OK");
                         },
                         Err(e) => {
                             eprintln!("Building has errors:\n{}", e);
@@ -298,24 +636,75 @@ fn main() {
                     }
                 },
 
-                cfg::ModCommand::Scale(cfg::ScaleCommand { input: dir_input, factor, output: dir_output }) => {
+                cfg::ModCommand::Scale(cfg::ScaleCommand { input: dir_input, factor, output: dir_output }, parts, cost_scaling, prec, dry_run) => {
 
-                    let bld_def = check_and_copy_building(dir_input, dir_output);
+                    let bld_def = check_and_copy_building(dir_input, dir_output, *dry_run);
+                    let pfx = if *dry_run { dir_input } else { dir_output };
                     println!("Updating...");
 
-                    let mut buf = String::with_capacity(16 * 1024);
-                    modify_ini!(buf, &bld_def.building_ini, BUILDING_INI,     ini::parse_building_ini,     ini::transform::scale_building, *factor);
-                    modify_ini!(buf, &bld_def.render,       RENDERCONFIG_INI, ini::parse_renderconfig_ini, ini::transform::scale_render,   *factor);
-                    modify_models(&bld_def, dir_output, |o| o.scale(*factor));
+                    if parts.ini {
+                        let mut buf = String::with_capacity(16 * 1024);
+                        modify_ini!(buf, &bld_def.building_ini, BUILDING_INI,     *prec, *dry_run, ini::parse_building_ini,     ini::transform::scale_building, *factor, cost_scaling);
+                        modify_ini!(buf, &bld_def.render,       RENDERCONFIG_INI, *prec, *dry_run, ini::parse_renderconfig_ini, ini::transform::scale_render,   *factor);
+                    }
+
+                    if parts.models {
+                        modify_models(&bld_def, pfx, "scaled", *dry_run, |o| {
+                            o.scale(*factor).unwrap_or_else(|e| panic!("Scaling object {} failed: {}", o.name(), e));
+                            o.vertices().len()
+                        });
+                    }
                 },
-                cfg::ModCommand::Mirror(cfg::FromToCommand { input: dir_input, output: dir_output }) => {
-                    let bld_def = check_and_copy_building(dir_input, dir_output);
+                cfg::ModCommand::Mirror(cfg::FromToCommand { input: dir_input, output: dir_output }, prec, dry_run) => {
+                    let bld_def = check_and_copy_building(dir_input, dir_output, *dry_run);
+                    let pfx = if *dry_run { dir_input } else { dir_output };
                     println!("Updating...");
 
                     let mut buf = String::with_capacity(16 * 1024);
-                    modify_ini!(buf, &bld_def.building_ini, BUILDING_INI,     ini::parse_building_ini,     ini::transform::mirror_z_building);
-                    modify_ini!(buf, &bld_def.render,       RENDERCONFIG_INI, ini::parse_renderconfig_ini, ini::transform::mirror_z_render);
-                    modify_models(&bld_def, dir_output, |o| o.mirror_z());
+                    modify_ini!(buf, &bld_def.building_ini, BUILDING_INI,     *prec, *dry_run, ini::parse_building_ini,     ini::transform::mirror_z_building);
+                    modify_ini!(buf, &bld_def.render,       RENDERCONFIG_INI, *prec, *dry_run, ini::parse_renderconfig_ini, ini::transform::mirror_z_render);
+                    modify_models(&bld_def, pfx, "mirrored", *dry_run, |o| { o.mirror_z(); o.vertices().len() });
+                },
+                cfg::ModCommand::Pack(cfg::FromToCommand { input: dir_input, output: zip_output }) => {
+                    let bld_ini = dir_input.join(BUILDING_INI);
+                    let render_ini = dir_input.join(RENDERCONFIG_INI);
+                    let bld_def = match ModBuildingDef::from_render_path(&bld_ini, &render_ini, &APP_SETTINGS.roots, ini::normalize_join, true) {
+                        Ok(bld) => bld,
+                        Err(e) => {
+                            eprintln!("Building has errors:\n{}", e);
+                            std::process::exit(1);
+                        }
+                    };
+
+                    let zip_file = fs::OpenOptions::new().write(true).create_new(true).open(zip_output).expect("Cannot create zip file");
+                    let mut zip = zip::ZipWriter::new(io::BufWriter::new(zip_file));
+                    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+                    let mut packed = Vec::<&Path>::with_capacity(16);
+                    let mut buf = Vec::<u8>::with_capacity(1024 * 1024);
+
+                    for path in bld_def.all_asset_paths() {
+                        let path = path.as_path();
+                        if packed.contains(&path) {
+                            continue;
+                        }
+
+                        match path.strip_prefix(dir_input) {
+                            Ok(rel_path) => {
+                                let name = rel_path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+                                read_to_buf(path, &mut buf).expect("Cannot read building file");
+                                zip.start_file(name.as_str(), options).expect("Cannot add file to zip");
+                                zip.write_all(&buf).expect("Cannot write to zip");
+                                println!("{}: OK", name);
+                            },
+                            // stock assets and files from other mods are not packed
+                            Err(_) => println!("Skipped (outside of the building directory): {}", path.display())
+                        }
+
+                        packed.push(path);
+                    }
+
+                    zip.finish().expect("Cannot finish zip file").flush().unwrap();
+                    println!("Done. Building packed as {}", zip_output.display());
                 },
             }
         },
@@ -339,51 +728,155 @@ fn main() {
                 }
             }
 
-            fn save_ini_as<U: ini::IniToken>(path: &Path, ini: ini::IniFile<U>) {
+            fn warn_unknown_tokens(path: &Path, ini: &ini::BuildingIni) {
+                for chunk in ini.unknown_tokens() {
+                    eprintln!("WARNING: {}: unknown token passed through unchanged, its values are not transformed: ${}", path.display(), chunk);
+                }
+            }
+
+            // annotated: the tokens are printed first, '*' marks the ones rewritten in the saved file
+            fn save_ini_as<U: ini::IniToken + std::fmt::Display>(path: &Path, mut ini: ini::IniFile<U>, prec: Option<usize>, annotated: bool) {
+                if annotated {
+                    print!("{}", ini.display_tokens(true));
+                }
+
+                ini.set_float_precision(prec);
                 let out_writer = io::BufWriter::new(fs::OpenOptions::new().write(true).create_new(true).open(path).unwrap());
                 ini.write_to(out_writer).expect("Could not write modified file");
                 println!("Done. File saved as {}", path.display());
             }
 
             match cmd {
-                cfg::IniCommand::ParseBuilding(path) => {
+                cfg::IniCommand::ParseBuilding(path, summary, filter) => {
                     let buf = fs::read_to_string(path).expect("Cannot read the specified file");
-                    let tokens = ini::parse_building_tokens(&buf);
-                    process_tokens(tokens);
+                    if *summary {
+                        let tokens = ini::parse_building_tokens(&buf);
+                        let mut categories = std::collections::BTreeMap::<&str, usize>::new();
+                        let mut errors = 0_usize;
+                        for (_, t_val) in tokens.iter() {
+                            match t_val {
+                                Ok((t, _)) => *categories.entry(t.category()).or_insert(0) += 1,
+                                Err(_) => errors += 1,
+                            }
+                        }
+
+                        println!("Tokens: {}, errors: {}", tokens.len(), errors);
+                        for (c, n) in categories.iter() {
+                            println!("  {:<12} {}", c, n);
+                        }
+                    } else if let Some(filter) = filter {
+                        let mut tokens = ini::parse_building_tokens(&buf);
+                        tokens.retain(|(_, t_val)| match t_val {
+                            Ok((t, _)) => t.category() == filter,
+                            Err(_) => true,
+                        });
+                        process_tokens(tokens);
+                    } else {
+                        process_tokens(ini::parse_building_tokens(&buf));
+                    }
                 },
                 cfg::IniCommand::ParseRender(path) => {
                     let buf = fs::read_to_string(path).expect("Cannot read the specified file");
-                    let tokens = ini::parse_render_tokens(&buf);
-                    process_tokens(tokens);
+                    process_tokens(ini::parse_render_tokens(&buf));
                 },
-                cfg::IniCommand::ParseMtl(path) => {
+                cfg::IniCommand::ParseMtl(path, resolve) => {
                     let buf = fs::read_to_string(path).expect("Cannot read the specified file");
-                    let tokens = ini::parse_material_tokens(&buf);
-                    process_tokens(tokens);
-                },
-                cfg::IniCommand::ScaleBuilding(cfg::ScaleCommand { input, factor, output }) => {
-                    let file = fs::read_to_string(input).expect("Cannot read the specified file");
-                    let mut ini = ini::parse_building_ini(&file).expect("Cannot parse building.ini");
-                    ini::transform::scale_building(&mut ini, *factor);
-                    save_ini_as(output, ini);
-                },
-                cfg::IniCommand::ScaleRender(cfg::ScaleCommand { input, factor, output }) => {
-                    let file = fs::read_to_string(input).expect("Cannot read the specified file");
-                    let mut ini = ini::parse_renderconfig_ini(&file).expect("Cannot parse renderconfig");
-                    ini::transform::scale_render(&mut ini, *factor);
-                    save_ini_as(output, ini);
-                },
-                cfg::IniCommand::MirrorBuilding(cfg::FromToCommand { input, output }) => {
-                    let file = fs::read_to_string(input).expect("Cannot read the specified file");
-                    let mut ini = ini::parse_building_ini(&file).expect("Cannot parse building.ini");
-                    ini::transform::mirror_z_building(&mut ini);
-                    save_ini_as(output, ini);
-                },
-                cfg::IniCommand::MirrorRender(cfg::FromToCommand { input, output }) => {
-                    let file = fs::read_to_string(input).expect("Cannot read the specified file");
-                    let mut ini = ini::parse_renderconfig_ini(&file).expect("Cannot parse renderconfig");
-                    ini::transform::mirror_z_render(&mut ini);
-                    save_ini_as(output, ini);
+                    process_tokens(ini::parse_material_tokens(&buf));
+
+                    if *resolve {
+                        let mtl = ini::parse_mtl(&buf).expect("Cannot parse mtl");
+                        let mtl_root = path.parent().unwrap();
+                        println!("\nTextures:");
+                        for tx in mtl.get_texture_paths(&APP_SETTINGS.roots, |p| APP_SETTINGS.roots.resolve_source_path(mtl_root, p)) {
+                            println!("{:<8} {}", if tx.exists() { "OK" } else { "MISSING" }, tx.display());
+                        }
+                    }
+                },
+                cfg::IniCommand::ScaleBuilding(pairs, cost_scaling, prec, lenient, annotated) => {
+                    for cfg::ScaleCommand { input, factor, output } in pairs.iter() {
+                        let file = fs::read_to_string(input).unwrap_or_else(|e| panic!("Cannot read {}: {}", input.display(), e));
+                        let parse = if *lenient { ini::parse_building_ini_lenient } else { ini::parse_building_ini };
+                        let mut ini = parse(&file).unwrap_or_else(|e| panic!("Cannot parse building.ini {}: {:?}", input.display(), e));
+                        warn_unknown_tokens(input, &ini);
+                        ini::transform::scale_building(&mut ini, *factor, cost_scaling);
+                        save_ini_as(output, ini, *prec, *annotated);
+                    }
+                },
+                cfg::IniCommand::ScaleRender(pairs, prec, annotated) => {
+                    for cfg::ScaleCommand { input, factor, output } in pairs.iter() {
+                        let file = fs::read_to_string(input).unwrap_or_else(|e| panic!("Cannot read {}: {}", input.display(), e));
+                        let mut ini = ini::parse_renderconfig_ini(&file).unwrap_or_else(|e| panic!("Cannot parse renderconfig {}: {:?}", input.display(), e));
+                        ini::transform::scale_render(&mut ini, *factor);
+                        save_ini_as(output, ini, *prec, *annotated);
+                    }
+                },
+                cfg::IniCommand::MirrorBuilding(pairs, prec, lenient, annotated) => {
+                    for cfg::FromToCommand { input, output } in pairs.iter() {
+                        let file = fs::read_to_string(input).unwrap_or_else(|e| panic!("Cannot read {}: {}", input.display(), e));
+                        let parse = if *lenient { ini::parse_building_ini_lenient } else { ini::parse_building_ini };
+                        let mut ini = parse(&file).unwrap_or_else(|e| panic!("Cannot parse building.ini {}: {:?}", input.display(), e));
+                        warn_unknown_tokens(input, &ini);
+                        ini::transform::mirror_z_building(&mut ini);
+                        save_ini_as(output, ini, *prec, *annotated);
+                    }
+                },
+                cfg::IniCommand::MirrorRender(pairs, prec, annotated) => {
+                    for cfg::FromToCommand { input, output } in pairs.iter() {
+                        let file = fs::read_to_string(input).unwrap_or_else(|e| panic!("Cannot read {}: {}", input.display(), e));
+                        let mut ini = ini::parse_renderconfig_ini(&file).unwrap_or_else(|e| panic!("Cannot parse renderconfig {}: {:?}", input.display(), e));
+                        ini::transform::mirror_z_render(&mut ini);
+                        save_ini_as(output, ini, *prec, *annotated);
+                    }
+                },
+                cfg::IniCommand::MergeBuilding(cfg::MergeCommand { base, overlay, output }) => {
+                    let base_file = fs::read_to_string(base).expect("Cannot read the base file");
+                    let overlay_file = fs::read_to_string(overlay).expect("Cannot read the overlay file");
+                    let mut ini = ini::parse_building_ini(&base_file).expect("Cannot parse base building.ini");
+                    let overlay_ini = ini::parse_building_ini(&overlay_file).expect("Cannot parse overlay building.ini");
+                    ini::merge::merge_building(&mut ini, overlay_ini);
+                    save_ini_as(output, ini, None, false);
+                },
+                cfg::IniCommand::ListTypes => {
+                    for (category, keywords) in ini::building::KEYWORD_TABLES.iter() {
+                        println!("{}:", category);
+                        for k in keywords.iter() {
+                            println!("    {}", k);
+                        }
+                        println!();
+                    }
+                },
+                cfg::IniCommand::Coverage(path) => {
+                    let buf = fs::read_to_string(path).expect("Cannot read the specified file");
+                    let cov = ini::building::coverage(&buf);
+                    println!("Tokens: {}, parsed: {} ({:.1}%)", cov.tokens, cov.parsed, cov.percent());
+
+                    println!("Unknown token types: {}", cov.unknown.iter().map(|(_, n)| n).sum::<usize>());
+                    for (k, n) in cov.unknown.iter() {
+                        println!("    ${} (x{})", k, n);
+                    }
+
+                    println!("Failed tokens of known types: {}", cov.failed.len());
+                    for (t_str, e) in cov.failed.iter() {
+                        println!("    {}\n        chunk: [{}]", e, t_str.trim_end());
+                    }
+                },
+                cfg::IniCommand::GetModels(render_ini) => {
+                    // building.ini is only recorded, not read
+                    let bld_ini = render_ini.with_file_name(BUILDING_INI);
+                    let bld = building_def::ModBuildingDef::from_render_path(&bld_ini, render_ini, &APP_SETTINGS.roots, ini::normalize_join, false)
+                        .unwrap_or_else(|e| panic!("Cannot resolve paths from {}: {}", render_ini.display(), e));
+
+                    println!("model {}", bld.model.display());
+                    let optional = [("model_lod", &bld.model_lod), ("model_lod2", &bld.model_lod2), ("model_e", &bld.model_e)];
+                    for (k, p) in optional.iter() {
+                        if let Some(p) = p {
+                            println!("{} {}", k, p.display());
+                        }
+                    }
+                    println!("material {}", bld.material.display());
+                    if let Some(p) = &bld.material_e {
+                        println!("material_e {}", p.display());
+                    }
                 }
             }
 
@@ -395,37 +888,20 @@ fn main() {
 
 
 fn print_dirs() {
-    println!("Stock game files:   {}", APP_SETTINGS.path_stock.as_path().display());
-    assert!(APP_SETTINGS.path_stock.exists(), "Stock game files directory does not exist.");
-
-    println!("Workshop directory: {}", APP_SETTINGS.path_workshop.as_path().display());
-    assert!(APP_SETTINGS.path_workshop.exists(), "Workshop directory does not exist.");
-}
-
-
-pub fn read_to_buf(path: &Path, buf: &mut Vec<u8>) -> Result<(), std::io::Error> {
-    use std::io::Read;
-    use std::convert::TryInto;
-    buf.clear();
+    println!("Stock game files:   {}", APP_SETTINGS.roots.stock.as_path().display());
+    assert!(APP_SETTINGS.roots.stock.exists(), "Stock game files directory does not exist.");
 
-    let mut file = fs::File::open(path)?;
-    let meta = file.metadata()?;
-    let sz: usize = meta.len().try_into().expect("Cannot get file length");
-    buf.reserve(sz);
-    file.read_to_end(buf)?;
-    Ok(())
+    println!("Workshop directory: {}", APP_SETTINGS.roots.workshop.as_path().display());
+    assert!(APP_SETTINGS.roots.workshop.exists(), "Workshop directory does not exist.");
 }
 
 
-pub fn read_to_string_buf<P: AsRef<Path>>(path: P, buf: &mut String) -> Result<(), std::io::Error> {
-    use std::io::Read;
-    use std::convert::TryInto;
-    buf.clear();
+fn create_output(path: &Path) -> io::BufWriter<fs::File> {
+    let f_out = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(path)
+                    .expect("Cannot create output file");
 
-    let mut file = fs::File::open(path)?;
-    let meta = file.metadata()?;
-    let sz: usize = meta.len().try_into().expect("Cannot get file length");
-    buf.reserve(sz);
-    file.read_to_string(buf)?;
-    Ok(())
+    io::BufWriter::new(f_out)
 }
@@ -40,11 +40,13 @@ impl ObjectVerb {
 }
 
 
-pub fn read_actions(actions_path: &Path, buf: &mut String) -> Result<ModActions, Error> {
+// Ok(None): the file has no tokens (e.g. all actions are commented out), same as no file at all
+pub fn read_actions(actions_path: &Path, buf: &mut String) -> Result<Option<ModActions>, Error> {
     const RX_FLOAT: &str = r"(-?\d+(?:\.\d+)?)";
 
     lazy_static! {
-        static ref RX_TOKENS:  Regex = Regex::new(r"(?s)(^|(\s*\r?\n)+)\$").unwrap();
+        // '--' and '//' comment lines are skipped like in ini files (the file may also end with comments, or have nothing else)
+        static ref RX_TOKENS:  Regex = Regex::new(concatcp!("(?s)(", ini::common::RX_LEADING_COMMENTS, "|", ini::common::RX_LINE_BREAKS, r")(\$|$)")).unwrap();
 
         static ref RX_SCALE:    Regex = Regex::new(r"(?s)^SCALE\s+(\d+(?:\.\d+)?)\s*$").unwrap();
         static ref RX_OFFSET:   Regex = Regex::new(concatcp!(r"(?s)^OFFSET\s+", RX_FLOAT, r"\s+", RX_FLOAT, r"\s+", RX_FLOAT, r"\s*$")).unwrap();
@@ -65,12 +67,15 @@ pub fn read_actions(actions_path: &Path, buf: &mut String) -> Result<ModActions,
     let mut optimize = false;
     let mut objects = None;
     let mut rename_sm = Vec::with_capacity(0);
+    let mut has_tokens = false;
 
-    for token in RX_TOKENS.split(&buf) {
+    for token in RX_TOKENS.split(buf) {
         if token.is_empty() {
             continue;
         }
 
+        has_tokens = true;
+
         if let Some(cap) = RX_SCALE.captures(token) {
             let factor = f64::from_str(cap.get(1).unwrap().as_str())
                 .map_err(|e| Error::FileParse(format!("Could not parse SCALE as float: {:?}", e)))?;
@@ -102,7 +107,7 @@ pub fn read_actions(actions_path: &Path, buf: &mut String) -> Result<ModActions,
                 res
             };
                 
-            if names.len() == 0 {
+            if names.is_empty() {
                 return Err(Error::FileParse("Could not parse object action: no object names were specified".to_string()));
             }
 
@@ -124,7 +129,11 @@ pub fn read_actions(actions_path: &Path, buf: &mut String) -> Result<ModActions,
 
     }
 
-    Ok(ModActions { scale, offset, optimize, mirror, objects, rename_sm })
+    if !has_tokens {
+        return Ok(None);
+    }
+
+    Ok(Some(ModActions { scale, offset, optimize, mirror, objects, rename_sm }))
 }
 
 
@@ -150,13 +159,13 @@ impl ModActions {
         for (old_name, new_name) in self.rename_sm.iter() {
             for sm in nmf.submaterials.iter_mut() {
                 if sm.as_str() == old_name {
-                    sm.push_str(&new_name);
+                    sm.push_str(new_name);
                 }
             }
         }
     }
 
-    pub fn validate<'a>(&self, bld_ini: &Path, nmf_info: &nmf::NmfInfo, str_buf: &mut String) -> Result<(), Error> {
+    pub fn validate(&self, bld_ini: &Path, nmf_info: &nmf::NmfInfo, str_buf: &mut String) -> Result<(), Error> {
         if self.scale.is_none() && !self.mirror && self.objects.is_none() && self.rename_sm.is_empty() {
             return Err(Error::Validation(vec!["Empty ModActions".to_string()]));
         }
@@ -170,7 +179,7 @@ impl ModActions {
             //       Ideally this should be removed  when the ini can cleansed
             //       from removed nodes automatically. 
 
-            read_to_string_buf(&bld_ini, str_buf).map_err(Error::FileRead)?;
+            read_to_string_buf(bld_ini, str_buf).map_err(Error::FileRead)?;
             let bld_ini = ini::parse_building_ini(str_buf).unwrap();
             let model_refs = bld_ini.get_model_refs();
 
@@ -209,8 +218,8 @@ impl ModActions {
 
             if names.len() == nmf_info.objects.len() {
                 match verb {
-                    ObjectVerb::Remove => errors.push(format!("Possible attempt to remove all objects. Entries count equals nmf objects count.")),
-                    ObjectVerb::Keep   => errors.push(format!("Possible attempt to keep all objects. Entries count equals nmf objects count."))
+                    ObjectVerb::Remove => errors.push("Possible attempt to remove all objects. Entries count equals nmf objects count.".to_string()),
+                    ObjectVerb::Keep   => errors.push("Possible attempt to keep all objects. Entries count equals nmf objects count.".to_string())
                 }
             }
 
@@ -222,6 +231,13 @@ impl ModActions {
             }
         }
 
+        // checked here, before install starts writing anything
+        if self.optimize {
+            for o in nmf_info.objects.iter().filter(|o| o.vertices() as usize >= u16::MAX.into()) {
+                errors.push(format!("Cannot $OPTIMIZE object '{}': {}", o.name.as_str(), nmf::ObjectError::TooManyVertices(o.vertices() as usize)));
+            }
+        }
+
 
         if errors.is_empty() {
             Ok(())
@@ -258,3 +274,29 @@ impl fmt::Display for ObjectVerb {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScratchDir;
+
+    fn read(dir: &ScratchDir, src: &str) -> Option<ModActions> {
+        let path = dir.write("building.actions", src);
+        read_actions(&path, &mut String::new()).unwrap_or_else(|_| panic!("Cannot read actions: {:?}", src))
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let dir = ScratchDir::new("actions-comments");
+
+        let actions = read(&dir, "-- scaled copy of b1\r\n$SCALE 2.0\r\n\r\n// then\r\n$MIRROR\r\n$OBJECTS KEEP main door_1\r\n-- trailing").unwrap();
+        assert_eq!(actions.scale, Some(2.0));
+        assert!(actions.mirror && !actions.optimize);
+        let (verb, names) = actions.objects.unwrap();
+        assert!(matches!(verb, ObjectVerb::Keep));
+        assert_eq!(names, ["main", "door_1"]);
+
+        assert!(read(&dir, "-- $SCALE 2.0\r\n// $MIRROR\r\n").is_none());
+    }
+}
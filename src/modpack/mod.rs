@@ -2,20 +2,20 @@ use std::fs;
 use std::io::{Write, BufWriter, Error as IOErr};
 use std::path::{Path, PathBuf};
 use std::fmt::{self, Write as FmtWrite};
+use std::time::{Duration, Instant};
 
 //use const_format::concatcp;
 use regex::Regex;
 use normpath::{BasePathBuf, PathExt};
 use lazy_static::lazy_static;
 
-mod skins;
-mod actions;
+pub mod skins;
+pub mod actions;
 
-use crate::{read_to_buf, read_to_string_buf};
-use crate::cfg::{AppSettings, APP_SETTINGS, RENDERCONFIG_INI, BUILDING_INI};
+use crate::{read_to_buf, read_to_string_buf, RENDERCONFIG_INI, BUILDING_INI};
 use crate::building_def::{ModBuildingDef, BuildingError as DefError};
 use crate::nmf;
-use crate::ini::{self, resolve_source_path, resolve_stock_path};
+use crate::ini::{self, GameRoots};
 use crate::ini::common::IdStringParam;
 
 use skins::{Skins, Error as SkinsError};
@@ -33,16 +33,74 @@ pub struct BuildingSource {
 
 pub enum SourceError {
     NoRenderconfig,
-    MultiRenderconfig,
+    MultiRenderconfig(PathBuf, PathBuf),
     Def(DefError),
     RefRead(IOErr),
     RefParse,
+    RefCycle(Vec<PathBuf>),
     Skins(SkinsError),
     Actions(ActionsError),
     Nmf(nmf::Error),
 }
 
+// Validation result of a single building source (see modpack validate --report)
+pub struct BuildingReport {
+    pub dir: PathBuf,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+pub struct SourceStats {
+    pub buildings: usize,
+    pub skins: usize,
+    pub models: usize,
+    pub models_unique: usize,
+    pub textures: usize,
+    pub textures_unique: usize,
+    pub building_mods: usize,
+    pub skin_mods: usize,
+}
+
+// Settings of one modpack install
+pub struct InstallOptions<'a> {
+    pub visibility: u8,
+    // With max_buildings set, only the first max_buildings sources are installed
+    pub max_buildings: Option<usize>,
+    // Shared dds/nmf assets are linked to their sources instead of being copied (see link_asset)
+    pub link_assets: bool,
+    // Names of the shared (dds, nmf) directories in target; tokens are made relative to them
+    pub assets_dirs: (&'a str, &'a str),
+}
+
+// Shared asset directories of an install and the assets already written to them
+struct InstallAssets<'a> {
+    roots: &'a GameRoots,
+    dds_root: PathBuf,
+    nmf_root: PathBuf,
+    map: AssetsMap,
+    link: bool,
+    byte_buf: Vec<u8>,
+}
+
+// Time spent in each install phase (see modpack install --timings)
+#[derive(Default)]
+pub struct InstallTimings {
+    pub validation: Duration,
+    pub models: Duration,
+    pub textures: Duration,
+    pub ini: Duration,
+}
+
+
 pub const MODPACK_LOG:     &str = "modpack.log";
+// modpack.log line prefix for the shared asset directories of an install
+const LOG_ASSETS_DIR:      &str = "<ASSETS_DIR>";
+
+// mod folder is 7 digits and cannot start from zero.
+pub const MOD_IDS_START:        usize = 1_000_000;
+pub const MOD_IDS_END:          usize = 9_999_999;
+pub const MAX_BUILDINGS_IN_MOD: usize = 100; // [0..99]
+pub const MAX_SKINS_IN_MOD:     usize = 16;
 
 const RENDERCONFIG_SOURCE: &str = "renderconfig.source";
 const RENDERCONFIG_REF:    &str = "renderconfig.ref";
@@ -54,11 +112,39 @@ const MATERIAL_E_MTL:      &str = "material_e.mtl";
 const WORKSHOPCONFIG:      &str = "workshopconfig.ini";
 
 
-pub fn read_validate_sources(source_dir: &Path) -> Result<(Vec::<BuildingSource>, usize), usize> {
+// roots: stock and workshop directories for '~' and '#' path tokens
+// Directories which names start with '_' or '.' are skipped, as well as the ones matching any of 'exclude' glob patterns
+// only: if not empty, buildings are taken only from directories whose name or relative path (glob) matches one of the patterns
+// reports: gets one entry per building directory, with its errors and warnings
+// summary_only: only errors are printed, OK / Excluded / WARNING lines are not (warnings still go to reports)
+pub fn read_validate_sources(source_dir: &Path, roots: &GameRoots, exclude: &[String], only: &[String], reports: &mut Vec<BuildingReport>, summary_only: bool) -> Result<(Vec::<BuildingSource>, usize), usize> {
+    let rel_path = |dir: &Path| -> String {
+        dir.strip_prefix(source_dir).expect("Impossible: could not strip root prefix")
+            .components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+    };
+
+    let exclude = exclude.iter().map(|p| glob_to_regex(p)).collect::<Vec<_>>();
+    let is_excluded = |dir: &Path| -> bool {
+        !exclude.is_empty() && exclude.iter().any(|rx| rx.is_match(&rel_path(dir)))
+    };
+
+    let only_rx = only.iter().map(|p| glob_to_regex(p)).collect::<Vec<_>>();
+    let is_selected = |dir: &Path| -> bool {
+        if only.is_empty() {
+            return true;
+        }
+
+        let dir_name = dir.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        let rel_path = rel_path(dir);
+        only.iter().zip(only_rx.iter()).any(|(name, rx)| dir_name == name.as_str() || rx.is_match(&rel_path))
+    };
+
+    let shown_root = shown_root(source_dir);
     let mut result = Vec::<BuildingSource>::with_capacity(10000);
 
     let mut errors: usize = 0;
     let mut skins_count: usize = 0;
+    let mut names = ahash::AHashMap::<String, Vec<PathBuf>>::with_capacity(10000);
 
     let mut str_buf = String::with_capacity(1024 * 16);
     let mut rev_buf = Vec::<PathBuf>::with_capacity(100);
@@ -69,13 +155,19 @@ pub fn read_validate_sources(source_dir: &Path) -> Result<(Vec::<BuildingSource>
         macro_rules! log_err {
             ($err:expr $(, $v:expr)*) => {{
                 errors += 1;
-                eprintln!("ERROR: {}: {}", path.strip_prefix(source_dir).expect("Impossible: could not strip root prefix").display(), $err);
+                eprintln!("ERROR: {}: {}", path.strip_prefix(shown_root).expect("Impossible: could not strip root prefix").display(), $err);
                 $($v)*
             }};
         }
 
         path.push(BUILDING_INI);
         if path.exists() {
+            path.pop();
+            if !is_selected(&path) {
+                continue;
+            }
+            path.push(BUILDING_INI);
+
             // try to push this building source
             let bld_ini = path.clone();
 
@@ -87,11 +179,14 @@ pub fn read_validate_sources(source_dir: &Path) -> Result<(Vec::<BuildingSource>
             path.pop();
 
             let building_source_clean = match (render_src, render_ref) {
-                (Some(render_src), None) => ModBuildingDef::from_render_path(&bld_ini, &render_src, resolve_source_path, false)
+                (Some(render_src), None) => ModBuildingDef::from_render_path(&bld_ini, &render_src, roots, |r, p| roots.resolve_source_path(r, p), false)
                                             .map_err(SourceError::Def),
-                (None, Some(render_ref)) => get_source_type_from_ref(bld_ini, render_ref, &mut str_buf),
+                (None, Some(render_ref)) => get_source_type_from_ref(bld_ini, render_ref, roots, &mut str_buf),
                 (None, None)       => Err(SourceError::NoRenderconfig), 
-                (Some(_), Some(_)) => Err(SourceError::MultiRenderconfig),
+                (Some(_), Some(_)) => {
+                    let rel_dir = path.strip_prefix(shown_root).expect("Impossible: could not strip root prefix");
+                    Err(SourceError::MultiRenderconfig(rel_dir.join(RENDERCONFIG_SOURCE), rel_dir.join(RENDERCONFIG_REF)))
+                },
             };
 
             let building_source = building_source_clean.and_then(|def| {
@@ -100,7 +195,7 @@ pub fn read_validate_sources(source_dir: &Path) -> Result<(Vec::<BuildingSource>
 
                 path.push(BUILDING_SKINS);
                 let skins = if path.exists() {
-                    skins::read_skins(path.as_path(), &mut str_buf).map_err(SourceError::Skins)
+                    skins::read_skins(path.as_path(), roots, &mut str_buf).map_err(SourceError::Skins)
                 } else { 
                     Ok(Skins::with_capacity(0))
                 };
@@ -110,22 +205,18 @@ pub fn read_validate_sources(source_dir: &Path) -> Result<(Vec::<BuildingSource>
                     skins_count += skins.len();
                     path.push(BUILDING_ACTIONS);
                     let actions = if path.exists() {
-                        actions::read_actions(&mut path, &mut str_buf).map(Some).map_err(SourceError::Actions)
+                        actions::read_actions(&path, &mut str_buf).map_err(SourceError::Actions)
                     } else {
                         Ok(None)
                     };
                     path.pop();
 
-                    actions.and_then(|actions| {
-                        // NOTE: debug
-                        //println!("skins:\n{:#?}", bs.skins);
-                        //println!("actions:\n{:?}", actions);
-                        Ok(BuildingSource { source_dir: path.clone(), def, skins, actions })
-                    })
+                    actions.map(|actions| BuildingSource { source_dir: path.clone(), def, skins, actions })
                 })
             });
 
             // VALIDATIONS
+            let mut warnings = Vec::<String>::with_capacity(0);
             let building_source = building_source.and_then(|bs| {
                 let mut nmf_info = nmf::NmfInfo::from_path(bs.def.model.as_path()).map_err(SourceError::Nmf)?;
                 if let Some(act) = &bs.actions {
@@ -133,21 +224,38 @@ pub fn read_validate_sources(source_dir: &Path) -> Result<(Vec::<BuildingSource>
                     act.apply_to(&mut nmf_info);
                 }
 
-                bs.def.parse_and_validate(Some(&nmf_info)).map_err(SourceError::Def)?;
+                let mut push_warnings = |ws: Vec<String>| for w in ws {
+                    if !summary_only {
+                        eprintln!("WARNING: {}", w);
+                    }
+                    warnings.push(w);
+                };
+
+                push_warnings(bs.def.parse_and_validate(Some(&nmf_info)).map_err(SourceError::Def)?);
 
                 let sm_used = nmf_info.get_used_sumbaterials().collect::<Vec<_>>();
-                skins::validate(&bs.skins, &sm_used[..], &mut str_buf).map_err(SourceError::Skins)?;
+                push_warnings(skins::validate(&bs.skins, &sm_used[..], roots, &mut str_buf).map_err(SourceError::Skins)?);
 
                 Ok(bs)
             });
 
+            let mut report = BuildingReport { dir: path.clone(), errors: Vec::with_capacity(0), warnings };
             match building_source {
                 Ok(bs) => {
-                    println!("OK: {}", path.strip_prefix(source_dir).expect("Impossible: could not strip root prefix").display());
+                    if !summary_only {
+                        println!("OK: {}", path.strip_prefix(shown_root).expect("Impossible: could not strip root prefix").display());
+                    }
+                    if let Some(name) = get_building_name(&bs.def.building_ini, &mut str_buf) {
+                        names.entry(name).or_default().push(path.clone());
+                    }
                     result.push(bs)
                 },
-                Err(e) => log_err!(e)
+                Err(e) => {
+                    report.errors.push(e.to_string());
+                    log_err!(e)
+                }
             }
+            reports.push(report);
         } else {
             // try to push sub-dirs to backlog
             path.pop();
@@ -155,15 +263,24 @@ pub fn read_validate_sources(source_dir: &Path) -> Result<(Vec::<BuildingSource>
                 Ok(r_d) => {
                     for dir_entry in r_d {
                         if let Err(e) = dir_entry.and_then(|dir_entry| 
-                            dir_entry.file_type().and_then(|filetype| {
+                            dir_entry.file_type().map(|filetype| {
                                 if filetype.is_dir() && !dir_entry.file_name().to_string_lossy().starts_with(&['_', '.'][..]) {
-                                    rev_buf.push(dir_entry.path());
+                                    let dir = dir_entry.path();
+                                    if is_excluded(&dir) {
+                                        if !summary_only {
+                                            println!("Excluded: {}", dir.strip_prefix(shown_root).expect("Impossible: could not strip root prefix").display());
+                                        }
+                                    } else {
+                                        rev_buf.push(dir);
+                                    }
                                 }
-                                Ok(())
                             })
                         ) { log_err!(e) }
                     }
 
+                    // read_dir order depends on the file system: sorted by name, so that buildings
+                    // (and the mod ids assigned on install) come in the same order on every machine
+                    rev_buf.sort();
                     while let Some(x) = rev_buf.pop() {
                         backlog.push(x);
                     }
@@ -173,6 +290,26 @@ pub fn read_validate_sources(source_dir: &Path) -> Result<(Vec::<BuildingSource>
         }
     }
 
+    for (dir, w) in check_cross_references(shown_root, &result) {
+        if !summary_only {
+            eprintln!("WARNING: {}: {}", dir.strip_prefix(shown_root).expect("Impossible: could not strip root prefix").display(), w);
+        }
+        reports.iter_mut().filter(|r| r.dir == dir).for_each(|r| r.warnings.push(w.clone()));
+    }
+
+    for (name, paths) in names.iter().filter(|(_, paths)| paths.len() > 1) {
+        if !summary_only {
+            eprintln!("WARNING: {} buildings share the same name {}:", paths.len(), name);
+        }
+        for p in paths.iter() {
+            if !summary_only {
+                eprintln!("    {}", p.strip_prefix(shown_root).expect("Impossible: could not strip root prefix").display());
+            }
+            let w = format!("{} buildings share the same name {}", paths.len(), name);
+            reports.iter_mut().filter(|r| r.dir == *p).for_each(|r| r.warnings.push(w.clone()));
+        }
+    }
+
     if errors == 0 {
         Ok((result, skins_count))
     } else {
@@ -183,28 +320,225 @@ pub fn read_validate_sources(source_dir: &Path) -> Result<(Vec::<BuildingSource>
 
 
 
+// Paths are printed relative to this. The source dir itself can be a single building:
+// then its parent is used, so that the building is shown by its directory name instead of an empty path.
+fn shown_root(source_dir: &Path) -> &Path {
+    if source_dir.join(BUILDING_INI).exists() {
+        source_dir.parent().unwrap_or(source_dir)
+    } else {
+        source_dir
+    }
+}
+
+
+// JSON report of read_validate_sources. errors: total count, including the ones not tied to a building
+pub fn write_report<W: Write>(mut wr: W, source_dir: &Path, reports: &[BuildingReport], errors: usize) -> Result<(), IOErr> {
+    fn json_str(s: &str) -> String {
+        let mut res = String::with_capacity(s.len() + 2);
+        res.push('"');
+        for c in s.chars() {
+            match c {
+                '"'  => res.push_str("\\\""),
+                '\\' => res.push_str("\\\\"),
+                '\n' => res.push_str("\\n"),
+                '\r' => res.push_str("\\r"),
+                '\t' => res.push_str("\\t"),
+                c if (c as u32) < 0x20 => write!(res, "\\u{:04x}", c as u32).unwrap(),
+                c => res.push(c)
+            }
+        }
+        res.push('"');
+        res
+    }
+
+    let json_list = |items: &[String]| format!("[{}]", items.iter().map(|s| json_str(s)).collect::<Vec<_>>().join(", "));
+
+    writeln!(wr, "{{")?;
+    writeln!(wr, "  \"source\": {},", json_str(&source_dir.to_string_lossy()))?;
+    writeln!(wr, "  \"errors\": {},", errors)?;
+    writeln!(wr, "  \"buildings\": [")?;
+    for (i, r) in reports.iter().enumerate() {
+        let rel_dir = r.dir.strip_prefix(shown_root(source_dir)).expect("Impossible: could not strip root prefix");
+        writeln!(wr, "    {{ \"path\": {}, \"status\": \"{}\", \"errors\": {}, \"warnings\": {} }}{}",
+                 json_str(&rel_dir.to_string_lossy()),
+                 if r.errors.is_empty() { "ok" } else { "error" },
+                 json_list(&r.errors),
+                 json_list(&r.warnings),
+                 if i + 1 < reports.len() { "," } else { "" })?;
+    }
+    writeln!(wr, "  ]")?;
+    writeln!(wr, "}}")?;
+    wr.flush()
+}
+
+
+impl SourceStats {
+    // Unique models and textures are counted by content (md5), the way they are deduplicated on install
+    pub fn collect(sources: &[BuildingSource], skins_count: usize) -> Result<SourceStats, IOErr> {
+        fn count_unique<'p, I: Iterator<Item = &'p Path>>(paths: I, byte_buf: &mut Vec<u8>) -> Result<(usize, usize), IOErr> {
+            let paths = paths.collect::<ahash::AHashSet<&Path>>();
+            let mut hashes = ahash::AHashSet::<[u8; 16]>::with_capacity(paths.len());
+            for p in paths.iter() {
+                read_to_buf(p, byte_buf)?;
+                hashes.insert(md5::compute(byte_buf.as_slice()).0);
+            }
+
+            Ok((paths.len(), hashes.len()))
+        }
+
+        let mut byte_buf = Vec::<u8>::with_capacity(32 * 1024 * 1024);
+        let (models, models_unique) = count_unique(sources.iter().flat_map(|bs| {
+            std::iter::once(&bs.def.model)
+                .chain(bs.def.model_lod.iter())
+                .chain(bs.def.model_lod2.iter())
+                .chain(bs.def.model_e.iter())
+                .map(PathBuf::as_path)
+        }), &mut byte_buf)?;
+        let (textures, textures_unique) = count_unique(sources.iter().flat_map(|bs| bs.def.textures.iter().map(PathBuf::as_path)), &mut byte_buf)?;
+
+        Ok(SourceStats {
+            buildings: sources.len(),
+            skins: skins_count,
+            models,
+            models_unique,
+            textures,
+            textures_unique,
+            building_mods: (sources.len() + MAX_BUILDINGS_IN_MOD - 1) / MAX_BUILDINGS_IN_MOD,
+            skin_mods: (skins_count + MAX_SKINS_IN_MOD - 1) / MAX_SKINS_IN_MOD,
+        })
+    }
+}
+
+
+// Converts a glob pattern to a regex matching the whole relative path (with '/' separators):
+// '**' matches anything, '*' and '?' do not cross directory boundaries.
+fn glob_to_regex(pattern: &str) -> Regex {
+    let mut rx = String::with_capacity(pattern.len() * 2 + 2);
+    rx.push('^');
+
+    let mut chars = pattern.trim_matches(&['/', '\\'][..]).chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => { chars.next(); rx.push_str(".*"); },
+            '*'         => rx.push_str("[^/]*"),
+            '?'         => rx.push_str("[^/]"),
+            '/' | '\\'  => rx.push('/'),
+            c           => rx.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+
+    rx.push('$');
+    Regex::new(&rx).expect("Impossible: glob produced invalid regex")
+}
+
+
+// Canonicalizes model paths and reports buildings whose models resolve
+// into another building's source directory (e.g. through odd relative paths).
+// Models are copied to md5-named files on install, so this is not harmful, but likely unintended.
+// Sources with renderconfig.ref are skipped: reusing another building's models is what they are for.
+// Returns (building source dir, warning message) pairs
+// shown_root: other buildings' dirs are printed relative to it
+fn check_cross_references<'s>(shown_root: &Path, sources: &'s [BuildingSource]) -> Vec<(&'s Path, String)> {
+    let roots = sources.iter()
+        .filter_map(|bs| fs::canonicalize(&bs.source_dir).ok().map(|r| (r, &bs.source_dir)))
+        .collect::<Vec<_>>();
+
+    let mut warnings = Vec::<(&'s Path, String)>::with_capacity(0);
+    for bs in sources.iter().filter(|bs| !bs.source_dir.join(RENDERCONFIG_REF).exists()) {
+        let def = &bs.def;
+        let models = std::iter::once(&def.model)
+            .chain(def.model_lod.iter())
+            .chain(def.model_lod2.iter())
+            .chain(def.model_e.iter());
+
+        for asset in models {
+            let asset = match fs::canonicalize(asset) {
+                Ok(a) => a,
+                Err(_) => continue,
+            };
+
+            if let Some((_, other)) = roots.iter().find(|(r, dir)| *dir != &bs.source_dir && asset.starts_with(r)) {
+                warnings.push((bs.source_dir.as_path(), format!("model {} resolves into the source directory of another building ({})",
+                    asset.display(), other.strip_prefix(shown_root).expect("Impossible: could not strip root prefix").display())));
+            }
+        }
+    }
+
+    warnings
+}
+
+
 type AssetsMap = ahash::AHashMap::<PathBuf, PathBuf>;
+type AssetsSet = ahash::AHashSet::<PathBuf>;
+
+// Template of a building source in dir: building.ini and renderconfig.source stubs (with placeholder model and material),
+// an empty building.skins and a building.actions with all actions commented out. Returns the created files.
+// Nothing is written if any of these files already exists.
+pub fn new_building(dir: &Path) -> Result<Vec<PathBuf>, IOErr> {
+    const MODEL_STUB:    &str = "model.nmf";
+    const MATERIAL_STUB: &str = "model.mtl";
+
+    let files = [BUILDING_INI, RENDERCONFIG_SOURCE, BUILDING_SKINS, BUILDING_ACTIONS].iter().map(|f| dir.join(f)).collect::<Vec<_>>();
+    if let Some(f) = files.iter().find(|f| f.exists()) {
+        return Err(IOErr::new(std::io::ErrorKind::AlreadyExists, format!("{} already exists", f.display())));
+    }
+
+    fs::create_dir_all(dir)?;
+    let name = dir.normalize()?.as_path().file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| "New building".to_string());
+
+    for f in files.iter() {
+        let mut wr = BufWriter::new(fs::OpenOptions::new().write(true).create_new(true).open(f)?);
+        match f.file_name().and_then(|n| n.to_str()) {
+            Some(BUILDING_INI)        => ini::building::write_stub(&mut wr, &name)?,
+            Some(RENDERCONFIG_SOURCE) => ini::renderconfig::write_stub(&mut wr, MODEL_STUB, MATERIAL_STUB)?,
+            Some(BUILDING_ACTIONS)    => write!(wr, "-- Applied to the model on install, remove '-- ' to enable an action\r\n\
+                                                    -- $SCALE 1.0\r\n\
+                                                    -- $OFFSET 0.0 0.0 0.0\r\n\
+                                                    -- $MIRROR\r\n\
+                                                    -- $OPTIMIZE\r\n\
+                                                    -- $OBJECTS KEEP <object> <object> ...\r\n\
+                                                    -- $OBJECTS REMOVE <object> <object> ...\r\n\
+                                                    -- $SUBMATERIAL_RENAME <from> <to>\r\n")?,
+            _ => ()
+        }
+        wr.flush()?;
+    }
+
+    Ok(files)
+}
+
 
-pub fn install(sources: Vec<BuildingSource>, target: &Path, log_file: &mut BufWriter<fs::File>) {
+pub fn install(sources: Vec<BuildingSource>, target: &Path, roots: &GameRoots, options: &InstallOptions, log_file: &mut BufWriter<fs::File>, timings: &mut InstallTimings) {
     
-    let dds_root = target.join("dds");
+    let InstallOptions { visibility, max_buildings, link_assets, assets_dirs } = *options;
+    let dds_root = target.join(assets_dirs.0);
     fs::create_dir_all(&dds_root).unwrap();
-    let nmf_root = target.join("nmf");
+    let nmf_root = target.join(assets_dirs.1);
     fs::create_dir_all(&nmf_root).unwrap();
+    // uninstall removes only the asset dirs recorded here
+    writeln!(log_file, "{} {}", LOG_ASSETS_DIR, assets_dirs.0).unwrap();
+    writeln!(log_file, "{} {}", LOG_ASSETS_DIR, assets_dirs.1).unwrap();
 
     let mut pathbuf = target.to_path_buf();
-    let mut assets_map = AssetsMap::with_capacity(10000);
+    let mut assets = InstallAssets {
+        roots,
+        dds_root,
+        nmf_root,
+        map: AssetsMap::with_capacity(10000),
+        link: link_assets,
+        byte_buf: Vec::<u8>::with_capacity(32 * 1024 * 1024),
+    };
+    let mut used_assets = AssetsSet::with_capacity(10000);
     let mut str_buf = String::with_capacity(16 * 1024);
-    let mut byte_buf = Vec::<u8>::with_capacity(32 * 1024 * 1024);
-    let mut skins_buf = Vec::<(usize, usize, &PathBuf, Option<&PathBuf>)>::with_capacity(AppSettings::MAX_SKINS_IN_MOD);
+    let mut skins_buf = Vec::<(usize, usize, &PathBuf, Option<&PathBuf>)>::with_capacity(MAX_SKINS_IN_MOD);
 
-    let mut src_iter = sources.iter();
-    let mut mod_id_iter = (AppSettings::MOD_IDS_START .. AppSettings::MOD_IDS_END).into_iter();
-    while let Some(mod_id) = mod_id_iter.next() {
+    let mut src_iter = sources.iter().take(max_buildings.unwrap_or(usize::MAX));
+    let mut mod_id_iter = MOD_IDS_START .. MOD_IDS_END;
+    'mods: while let Some(mod_id) = mod_id_iter.next() {
         str_buf.clear();
         write!(str_buf, "{}", mod_id).unwrap();
         pathbuf.push(&str_buf);
-        for bld_id in 0 .. AppSettings::MAX_BUILDINGS_IN_MOD {
+        for bld_id in 0 .. MAX_BUILDINGS_IN_MOD {
             if let Some(src) = src_iter.next() {
                 str_buf.clear();
                 write!(str_buf, "{:0>2}", bld_id).unwrap();
@@ -213,11 +547,17 @@ pub fn install(sources: Vec<BuildingSource>, target: &Path, log_file: &mut BufWr
 
                 fs::create_dir_all(&pathbuf).unwrap();
 
-                install_building(&src.def, &src.actions, &pathbuf, &dds_root, &nmf_root, &mut assets_map, &mut str_buf, &mut byte_buf).unwrap();
+                let new_def = install_building(&src.def, &src.actions, &pathbuf, &mut assets, &mut str_buf, timings)
+                    .unwrap_or_else(|e| panic!("Cannot install {}: {}", src.source_dir.display(), e));
+                used_assets.insert(new_def.model);
+                used_assets.extend(new_def.model_lod);
+                used_assets.extend(new_def.model_lod2);
+                used_assets.extend(new_def.model_e);
+
                 for (skin, skin_e) in src.skins.iter() {
                     skins_buf.push((mod_id, bld_id, skin, skin_e.as_ref()));
-                    if skins_buf.len() == AppSettings::MAX_SKINS_IN_MOD {
-                        let skin_mod_id = write_skins_mod(target, &mut mod_id_iter, &skins_buf[..], &dds_root, &mut assets_map, &mut str_buf, &mut byte_buf);
+                    if skins_buf.len() == MAX_SKINS_IN_MOD {
+                        let skin_mod_id = write_skins_mod(target, &mut mod_id_iter, visibility, &skins_buf[..], &mut assets, &mut str_buf, timings);
                         skins_buf.clear();
                         writeln!(log_file, "{} <SKINS>", skin_mod_id).unwrap();
                     }
@@ -226,30 +566,103 @@ pub fn install(sources: Vec<BuildingSource>, target: &Path, log_file: &mut BufWr
                 pathbuf.pop();
             } else {
                 pathbuf.push(WORKSHOPCONFIG);
-                write_workshop_ini_buildings(pathbuf.as_path(), mod_id, bld_id, &mut str_buf);
+                write_workshop_ini_buildings(pathbuf.as_path(), mod_id, bld_id, visibility, &mut str_buf);
                 if !skins_buf.is_empty() {
-                    let skin_mod_id = write_skins_mod(target, &mut mod_id_iter, &skins_buf[..], &dds_root, &mut assets_map, &mut str_buf, &mut byte_buf);
+                    let skin_mod_id = write_skins_mod(target, &mut mod_id_iter, visibility, &skins_buf[..], &mut assets, &mut str_buf, timings);
                     writeln!(log_file, "{} <SKINS>", skin_mod_id).unwrap();
                 }
-                return;
+                break 'mods;
             }
         }
 
         pathbuf.push(WORKSHOPCONFIG);
-        write_workshop_ini_buildings(pathbuf.as_path(), mod_id, AppSettings::MAX_BUILDINGS_IN_MOD, &mut str_buf);
+        write_workshop_ini_buildings(pathbuf.as_path(), mod_id, MAX_BUILDINGS_IN_MOD, visibility, &mut str_buf);
         pathbuf.pop();
         pathbuf.pop();
     }
+
+    // Anything in the shared asset dirs that was not written (or reused) by this install is an orphan.
+    // This install only writes referenced assets, so orphans were already there: they are reported, never deleted.
+    used_assets.extend(assets.map.drain().map(|(_, v)| v));
+    let orphans = report_orphan_assets(&assets.dds_root, &used_assets, log_file).unwrap()
+                + report_orphan_assets(&assets.nmf_root, &used_assets, log_file).unwrap();
+    if orphans > 0 {
+        println!("Found {} unreferenced asset(s) not written by this install, left in place. See <ORPHAN> lines in {}", orphans, MODPACK_LOG);
+    }
+}
+
+
+// Removes a previous install: mod directories and shared asset dirs listed in modpack.log, and the log itself.
+// Nothing else in target is touched. Returns the number of removed mod directories.
+pub fn uninstall(target: &Path) -> Result<usize, IOErr> {
+    let log_path = target.join(MODPACK_LOG);
+    let log = fs::read_to_string(&log_path)?;
+
+    let mut mod_ids = Vec::<&str>::with_capacity(100);
+    let mut assets_dirs = Vec::<&str>::with_capacity(2);
+    for line in log.lines() {
+        if let Some(name) = line.strip_prefix(LOG_ASSETS_DIR).and_then(|l| l.strip_prefix(' ')) {
+            // never follow a tampered log outside of target
+            let mut cs = Path::new(name).components();
+            if !matches!((cs.next(), cs.next()), (Some(std::path::Component::Normal(_)), None)) {
+                return Err(IOErr::new(std::io::ErrorKind::InvalidData, format!("{}: bad asset directory name '{}'", MODPACK_LOG, name)));
+            }
+            assets_dirs.push(name);
+            continue;
+        }
+
+        // "<mod_id>/<bld_id> <source>" or "<mod_id> <SKINS>"; other tagged lines start with '<'
+        let mod_id = line.split(['/', ' ']).next().unwrap_or_default();
+        if !mod_id.is_empty() && mod_id.bytes().all(|b| b.is_ascii_digit()) && !mod_ids.contains(&mod_id) {
+            mod_ids.push(mod_id);
+        }
+    }
+
+    let mut count = 0;
+    for mod_id in mod_ids.iter() {
+        let mod_dir = target.join(mod_id);
+        if mod_dir.is_dir() {
+            fs::remove_dir_all(mod_dir)?;
+            count += 1;
+        }
+    }
+
+    for assets_dir in assets_dirs {
+        let assets_dir = target.join(assets_dir);
+        if assets_dir.is_dir() {
+            fs::remove_dir_all(assets_dir)?;
+        }
+    }
+
+    fs::remove_file(log_path)?;
+    Ok(count)
+}
+
+
+// Logs files of assets_root that are not in used as <ORPHAN>. Returns their number.
+fn report_orphan_assets<W: Write>(assets_root: &Path, used: &AssetsSet, log_file: &mut W) -> Result<usize, IOErr> {
+    let mut count = 0;
+    for dir_entry in fs::read_dir(assets_root)? {
+        let dir_entry = dir_entry?;
+        let path = dir_entry.path();
+        let file_type = dir_entry.file_type()?;
+        if (file_type.is_file() || file_type.is_symlink()) && !used.contains(&path) {
+            writeln!(log_file, "<ORPHAN> {}", path.display())?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
 }
 
 #[must_use]
 fn write_skins_mod(target: &Path, 
                    mod_id_iter: &mut impl Iterator<Item = usize>, 
+                   visibility: u8,
                    skins: &[(usize, usize, &PathBuf, Option<&PathBuf>)], 
-                   dds_root: &Path,
-                   assets_map: &mut AssetsMap,
+                   assets: &mut InstallAssets,
                    str_buf: &mut String,
-                   byte_buf: &mut Vec<u8>
+                   timings: &mut InstallTimings
                    ) -> usize 
 {
     let mod_id = mod_id_iter.next().expect("Too many mods");
@@ -265,17 +678,18 @@ fn write_skins_mod(target: &Path,
         "$ITEM_ID {}\n\
          $OWNER_ID 12345678901234567\n\
          $ITEM_TYPE WORKSHOP_ITEMTYPE_BUILDINGSKIN\n\
-         $VISIBILITY 2\n", 
-        mod_id).unwrap();
+         $VISIBILITY {}\n", 
+        mod_id, visibility).unwrap();
 
+    let t_start = Instant::now();
     for ((m, b, mtl, mtl_e), i) in skins.iter().zip(1..) {
         str_buf.clear();
         write!(str_buf, "{:0>2}.mtl", i).unwrap();
         write!(config_buf, "\n$TARGET_BUILDING_SKIN {}/{:0>2} {}", m, b, str_buf).unwrap();
 
         pathbuf.push(&str_buf);
-        fs::copy(&mtl, &pathbuf).expect("Could not copy skin's mtl file");
-        update_mtl(&pathbuf, &mtl, dds_root, assets_map, str_buf, byte_buf).unwrap();
+        fs::copy(mtl, &pathbuf).expect("Could not copy skin's mtl file");
+        update_mtl(&pathbuf, mtl, assets, str_buf).unwrap();
         pathbuf.pop();
 
         if let Some(mtl) = mtl_e {
@@ -285,10 +699,11 @@ fn write_skins_mod(target: &Path,
 
             pathbuf.push(&str_buf);
             fs::copy(mtl, &pathbuf).expect("Could not copy skin's mtl_e file");
-            update_mtl(&pathbuf, &mtl, dds_root, assets_map, str_buf, byte_buf).unwrap();
+            update_mtl(&pathbuf, mtl, assets, str_buf).unwrap();
             pathbuf.pop();
         }
     }
+    timings.textures += t_start.elapsed();
 
     writeln!(config_buf, "\n\n$ITEM_NAME \"Automatically generated by wrsr-mt modpack installer\"\
                             \n$ITEM_DESC \"Automatically generated by wrsr-mt modpack installer\"\
@@ -300,7 +715,7 @@ fn write_skins_mod(target: &Path,
     mod_id
 }
 
-fn write_workshop_ini_buildings(path: &Path, mod_id: usize, count: usize, buf: &mut String) {
+fn write_workshop_ini_buildings(path: &Path, mod_id: usize, count: usize, visibility: u8, buf: &mut String) {
     if count == 0 {
         return;
     }
@@ -310,8 +725,8 @@ fn write_workshop_ini_buildings(path: &Path, mod_id: usize, count: usize, buf: &
         "$ITEM_ID {}\n\
          $OWNER_ID 12345678901234567\n\
          $ITEM_TYPE WORKSHOP_ITEMTYPE_BUILDING\n\
-         $VISIBILITY 2\n", 
-        mod_id).unwrap();
+         $VISIBILITY {}\n", 
+        mod_id, visibility).unwrap();
 
     for i in 0 .. count {
         writeln!(buf, "$OBJECT_BUILDING {:0>2}", i).unwrap();
@@ -327,14 +742,12 @@ fn write_workshop_ini_buildings(path: &Path, mod_id: usize, count: usize, buf: &
 fn install_building(src_def: &ModBuildingDef,
                     actions: &Option<actions::ModActions>,
                     destination: &Path, 
-                    dds_root: &Path,
-                    nmf_root: &Path,
-                    assets_map: &mut AssetsMap, 
+                    assets: &mut InstallAssets,
                     str_buf: &mut String,
-                    byte_buf: &mut Vec<u8>) -> Result<(), IOErr> {
+                    timings: &mut InstallTimings) -> Result<ModBuildingDef, IOErr> {
 
     str_buf.clear();
-    byte_buf.clear();
+    assets.byte_buf.clear();
 
     let new_render_path = destination.join(RENDERCONFIG_INI);
     fs::copy(&src_def.render, &new_render_path)?;
@@ -363,8 +776,8 @@ fn install_building(src_def: &ModBuildingDef,
         ($nmf_path:expr) => {{
             let nmf_path = $nmf_path;
             match actions {
-                None          => nmf_path.push(copy_asset_md5(nmf_path, nmf_root, byte_buf, assets_map)?),
-                Some(actions) => nmf_path.push(copy_nmf_with_actions(nmf_path, nmf_root, byte_buf, actions)?)
+                None          => nmf_path.push(copy_asset_md5(nmf_path, &assets.nmf_root, &mut assets.byte_buf, &mut assets.map, assets.link)?),
+                Some(actions) => nmf_path.push(copy_nmf_with_actions(nmf_path, &assets.nmf_root, &mut assets.byte_buf, actions)?)
             };
 
             Result::<String, IOErr>::Ok(make_relative_token(&new_render_path, nmf_path).expect("Could not construct relative nmf token"))
@@ -381,12 +794,13 @@ fn install_building(src_def: &ModBuildingDef,
 
     macro_rules! update_mtl {
         ($mtl_path:expr, $old_mtl_path:expr) => {
-            update_mtl($mtl_path, $old_mtl_path, &dds_root, assets_map, str_buf, byte_buf)
+            update_mtl($mtl_path, $old_mtl_path, assets, str_buf)
         }
     }
 
     //-----------------------------------------------------------------
 
+    let t_start = Instant::now();
     copy_fld!(src_def.building_ini, new_def.building_ini, BUILDING_INI);
     copy_fld!(src_def.material,     new_def.material,     MATERIAL_MTL);
     copy_fld_opt!(material_e, MATERIAL_E_MTL); 
@@ -394,11 +808,17 @@ fn install_building(src_def: &ModBuildingDef,
     
     // Update config files
     {   
+        timings.ini += t_start.elapsed();
+        let t_start = Instant::now();
+
         let model_token:      String         = copy_nmf_token!(&mut new_def.model)?;
         let model_lod_token:  Option<String> = copy_nmf_token_opt!(new_def.model_lod)?;
         let model_lod2_token: Option<String> = copy_nmf_token_opt!(new_def.model_lod2)?;
         let model_e_token:    Option<String> = copy_nmf_token_opt!(new_def.model_e)?;
 
+        timings.models += t_start.elapsed();
+        let t_start = Instant::now();
+
         // Update renderconfig.ini
 
         read_to_string_buf(&new_render_path, str_buf)?;
@@ -441,7 +861,7 @@ fn install_building(src_def: &ModBuildingDef,
         let mut bld_ini = ini::parse_building_ini(str_buf).expect("Invalid building ini");
         if let Some(actions) = actions {
             if let Some(factor) = actions.scale {
-                ini::transform::scale_building(&mut bld_ini, factor);
+                ini::transform::scale_building(&mut bld_ini, factor, &ini::transform::CostScaling::None);
             }
 
             if let Some((dx, dy, dz)) = actions.offset {
@@ -453,15 +873,26 @@ fn install_building(src_def: &ModBuildingDef,
             }
         }
         bld_ini.write_file(&new_def.building_ini)?;
+
+        timings.ini += t_start.elapsed();
     }
 
     // Copy textures and update *.mtl files
+    let t_start = Instant::now();
     update_mtl!(&new_def.material, &src_def.material)?;
     if let (Some(material_e), Some(src_mtl_e)) = (&new_def.material_e, &src_def.material_e) {
         update_mtl!(material_e, src_mtl_e)?;
     }
+    timings.textures += t_start.elapsed();
+
+    Ok(new_def)
+}
+
 
-    Ok(())
+fn get_building_name(building_ini: &Path, buf: &mut String) -> Option<String> {
+    read_to_string_buf(building_ini, buf).ok()?;
+    let bld_ini = ini::parse_building_ini(buf).ok()?;
+    bld_ini.get_name().map(|t| t.to_string())
 }
 
 
@@ -470,32 +901,50 @@ lazy_static! {
 }
 
 
-fn get_source_type_from_ref(bld_ini: PathBuf, mut render_ref: BasePathBuf, buf: &mut String) -> Result<ModBuildingDef, SourceError> {
-    read_to_string_buf(&render_ref, buf).map_err(SourceError::RefRead)?;
-    let caps = RX_REF.captures(buf).ok_or(SourceError::RefParse)?;
-    let mut root: BasePathBuf = if let Some(c) = caps.get(2) {
-        // workshop
-        Ok(APP_SETTINGS.path_workshop.join(c.as_str()))
-    } else if let Some(c) = caps.get(3) {
-        // relative path
-        render_ref.pop().unwrap();
-        Ok(render_ref.join(c.as_str()))
-    } else {
-        Err(SourceError::RefParse)
-    }?;
+// If the referenced directory has no renderconfig.ini but has its own renderconfig.ref, that one is followed too
+fn get_source_type_from_ref(bld_ini: PathBuf, mut render_ref: BasePathBuf, roots: &GameRoots, buf: &mut String) -> Result<ModBuildingDef, SourceError> {
+    let mut visited = Vec::<PathBuf>::with_capacity(2);
+    loop {
+        // canonical paths, so that different relative routes to the same ref are recognized
+        let ref_path = fs::canonicalize(&render_ref).map_err(SourceError::RefRead)?;
+        let is_cycle = visited.contains(&ref_path);
+        visited.push(ref_path);
+        if is_cycle {
+            return Err(SourceError::RefCycle(visited));
+        }
+
+        read_to_string_buf(&render_ref, buf).map_err(SourceError::RefRead)?;
+        let caps = RX_REF.captures(buf).ok_or(SourceError::RefParse)?;
+        let root: BasePathBuf = if let Some(c) = caps.get(2) {
+            // workshop
+            Ok(roots.workshop.join(c.as_str()))
+        } else if let Some(c) = caps.get(3) {
+            // relative path
+            render_ref.pop().unwrap();
+            Ok(render_ref.join(c.as_str()))
+        } else {
+            Err(SourceError::RefParse)
+        }?;
+
+        let render = root.join(RENDERCONFIG_INI);
+        let next_ref = root.join(RENDERCONFIG_REF);
+        if !render.exists() && next_ref.exists() {
+            render_ref = next_ref;
+            continue;
+        }
 
-    root.push(RENDERCONFIG_INI);
-    ModBuildingDef::from_render_path(&bld_ini, root.as_path(), resolve_source_path, true)
-        .map_err(SourceError::Def)
+        return ModBuildingDef::from_render_path(&bld_ini, render.as_path(), roots, |r, p| roots.resolve_source_path(r, p), true)
+            .map_err(SourceError::Def);
+    }
 }
 
 
-fn copy_asset_md5<'map>(asset_path: &Path, assets_root: &Path, byte_buf: &mut Vec<u8>, assets_map: &'map mut AssetsMap) -> Result<&'map Path, IOErr> {
+fn copy_asset_md5<'map>(asset_path: &Path, assets_root: &Path, byte_buf: &mut Vec<u8>, assets_map: &'map mut AssetsMap, link: bool) -> Result<&'map Path, IOErr> {
 
     // TODO: update this when borrowchecker is made less stupid
     if !assets_map.contains_key(asset_path) {
         let file_ext = asset_path.extension()
-            .ok_or_else(|| IOErr::new(std::io::ErrorKind::Other, "Asset has no extension"))?
+            .ok_or_else(|| IOErr::new(std::io::ErrorKind::InvalidInput, "Asset has no extension"))?
             .to_string_lossy();
 
         read_to_buf(asset_path, byte_buf)?;
@@ -505,7 +954,11 @@ fn copy_asset_md5<'map>(asset_path: &Path, assets_root: &Path, byte_buf: &mut Ve
         let new_val = assets_root.join(&asset_md5name);
 
         if !new_val.exists() {
-            fs::write(&new_val, byte_buf.as_slice())?;
+            // not existing, but may still be a dangling link left from a previous linked install
+            let _ = fs::remove_file(&new_val);
+            if !link || link_asset(asset_path, &new_val).is_err() {
+                fs::write(&new_val, byte_buf.as_slice())?;
+            }
         }
 
         assets_map.insert(new_key, new_val);
@@ -516,8 +969,18 @@ fn copy_asset_md5<'map>(asset_path: &Path, assets_root: &Path, byte_buf: &mut Ve
 }
 
 
+// Symlinks on windows need elevated privileges, hard links do not
+fn link_asset(src: &Path, dest: &Path) -> Result<(), IOErr> {
+    #[cfg(unix)]
+    { std::os::unix::fs::symlink(fs::canonicalize(src)?, dest) }
+
+    #[cfg(not(unix))]
+    { fs::hard_link(src, dest) }
+}
+
+
 fn copy_nmf_with_actions(asset_path: &Path, assets_root: &Path, byte_buf: &mut Vec<u8>, actions: &ModActions) -> Result<PathBuf, IOErr> {
-    let mut model = nmf::NmfBufFull::from_path(asset_path).expect(&format!("Could not read NMF at {}", asset_path.display()));
+    let mut model = nmf::NmfBufFull::from_path(asset_path).unwrap_or_else(|_| panic!("Could not read NMF at {}", asset_path.display()));
 
     if let Some(obj_act) = &actions.objects {
         let mut tmp_objects = Vec::<nmf::ObjectFull>::with_capacity(model.objects.len());
@@ -543,11 +1006,12 @@ fn copy_nmf_with_actions(asset_path: &Path, assets_root: &Path, byte_buf: &mut V
 
     for obj in model.objects.iter_mut() {
         if actions.optimize {
-            obj.optimize_indices();
+            obj.optimize_indices().map_err(|e| IOErr::new(std::io::ErrorKind::InvalidData,
+                format!("Optimizing object {} of {} failed: {}", obj.name(), asset_path.display(), e)))?;
         }
 
         if let Some(factor) = actions.scale {
-            obj.scale(factor);
+            obj.scale(factor).unwrap_or_else(|e| panic!("Scaling object {} of {} failed: {}", obj.name(), asset_path.display(), e));
         }
 
         if let Some((dx, dy, dz)) = actions.offset {
@@ -588,10 +1052,8 @@ fn copy_nmf_with_actions(asset_path: &Path, assets_root: &Path, byte_buf: &mut V
 // panics on invalid mtl
 fn update_mtl(mtl_path: &Path, 
               old_mtl_path: &Path, 
-              dds_root: &Path, 
-              assets_map: &mut AssetsMap,
-              str_buf: &mut String, 
-              byte_buf: &mut Vec<u8>
+              assets: &mut InstallAssets,
+              str_buf: &mut String
               ) -> Result<(), IOErr> {
     let old_mtl_root = old_mtl_path.parent().unwrap();
     read_to_string_buf(mtl_path, str_buf)?;
@@ -600,7 +1062,7 @@ fn update_mtl(mtl_path: &Path,
     macro_rules! update_tx_token {
         ($token:ident, $path_resolver:expr) => {{
             let src_tx_path = $path_resolver($token);
-            let new_tx_path = copy_asset_md5(&src_tx_path, dds_root, byte_buf, assets_map).expect("Could not copy texture when updating mtl");
+            let new_tx_path = copy_asset_md5(&src_tx_path, &assets.dds_root, &mut assets.byte_buf, &mut assets.map, assets.link).expect("Could not copy texture when updating mtl");
             let tx_token = make_relative_token(mtl_path, &new_tx_path).expect("Could not construct relative texture token");
             ini::common::IdStringParam::new_owned(tx_token)
         }}
@@ -612,10 +1074,10 @@ fn update_mtl(mtl_path: &Path,
             use ini::material::Token as MT;
             
             match t {
-                MT::Texture(        (i, p)) => Some(MT::TextureMtl(     (*i, update_tx_token!(p, resolve_stock_path)) )),
-                MT::TextureNoMip(   (i, p)) => Some(MT::TextureNoMipMtl((*i, update_tx_token!(p, resolve_stock_path)) )),
-                MT::TextureMtl(     (i, p)) => Some(MT::TextureMtl(     (*i, update_tx_token!(p, |p| resolve_source_path(&old_mtl_root, p)) ))),
-                MT::TextureNoMipMtl((i, p)) => Some(MT::TextureNoMipMtl((*i, update_tx_token!(p, |p| resolve_source_path(&old_mtl_root, p)) ))), 
+                MT::Texture(        (i, p)) => Some(MT::TextureMtl(     (*i, update_tx_token!(p, |p| assets.roots.resolve_stock_path(p))) )),
+                MT::TextureNoMip(   (i, p)) => Some(MT::TextureNoMipMtl((*i, update_tx_token!(p, |p| assets.roots.resolve_stock_path(p))) )),
+                MT::TextureMtl(     (i, p)) => Some(MT::TextureMtl(     (*i, update_tx_token!(p, |p| assets.roots.resolve_source_path(old_mtl_root, p)) ))),
+                MT::TextureNoMipMtl((i, p)) => Some(MT::TextureNoMipMtl((*i, update_tx_token!(p, |p| assets.roots.resolve_source_path(old_mtl_root, p)) ))), 
                 _ => None
             }
         });
@@ -638,10 +1100,10 @@ pub fn make_relative_token(path_from: &Path, path_to: &Path) -> Option<String> {
             }
 
             new_token.push_str(&c_to.as_os_str().to_string_lossy());
-            new_token.push_str("/");
+            new_token.push('/');
             for c in iter_to {
                 new_token.push_str(&c.as_os_str().to_string_lossy());
-                new_token.push_str("/");
+                new_token.push('/');
             }
 
             new_token.push_str(&path_to.file_name()?.to_string_lossy());
@@ -661,18 +1123,398 @@ impl fmt::Display for BuildingSource {
     }
 }
 
+impl fmt::Display for SourceStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        writeln!(f, "Buildings:          {}", self.buildings)?;
+        writeln!(f, "Skins:              {}", self.skins)?;
+        writeln!(f, "Models:             {} ({} unique)", self.models, self.models_unique)?;
+        writeln!(f, "Building textures:  {} ({} unique)", self.textures, self.textures_unique)?;
+        write!(f,   "Mod ids needed:     {} ({} for buildings, {} for skins)", self.building_mods + self.skin_mods, self.building_mods, self.skin_mods)
+    }
+}
+
+impl fmt::Display for InstallTimings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.validation + self.models + self.textures + self.ini;
+        writeln!(f, "Timings:")?;
+        writeln!(f, "  validation   {:>10.3}s", self.validation.as_secs_f64())?;
+        writeln!(f, "  models       {:>10.3}s", self.models.as_secs_f64())?;
+        writeln!(f, "  textures     {:>10.3}s", self.textures.as_secs_f64())?;
+        writeln!(f, "  ini files    {:>10.3}s", self.ini.as_secs_f64())?;
+        write!(f,   "  total        {:>10.3}s", total.as_secs_f64())
+    }
+}
+
+
 impl fmt::Display for SourceError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         use SourceError as E;
         match self {
             E::NoRenderconfig    => write!(f, "Building source is missing one of renderconfig.source or renderconfig.ref"),
-            E::MultiRenderconfig => write!(f, "Building source has both renderconfig.source and renderconfig.ref. Only one is required."),
+            E::MultiRenderconfig(src, rf) => write!(f, "Building source has both {} and {}. Only one is required: \
+                                                        delete the first one to use the referenced building, or the second one to use the local renderconfig",
+                                                    src.display(), rf.display()),
             E::Def(e)            => write!(f, "BuildingDef error: {}", e),
             E::RefRead(e)        => write!(f, "Error reading building reference: {}", e),
             E::RefParse          => write!(f, "Cannot parse building reference"),
-            E::Skins(e)          => write!(f, "Skins error: {:#?}", e),
+            E::RefCycle(chain)   => {
+                write!(f, "Building references form a cycle: ")?;
+                for (i, p) in chain.iter().enumerate() {
+                    write!(f, "{}{}", if i > 0 { " -> " } else { "" }, p.display())?;
+                }
+                Ok(())
+            },
+            E::Skins(e)          => write!(f, "Skins error: {}", e),
             E::Actions(e)        => write!(f, "Actions error: {}", e),
-            E::Nmf(e)            => write!(f, "Nmf error: {:#?}", e),
+            E::Nmf(e)            => write!(f, "Nmf error: {}", e),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, ScratchDir};
+
+    // Installs the sources found in dir/src into dir/dst, with modpack.log. Returns dst
+    fn install_src(dir: &ScratchDir, roots: &GameRoots, visibility: u8, max_buildings: Option<usize>, assets_dirs: (&str, &str)) -> PathBuf {
+        let (sources, _) = read_validate_sources(&dir.path().join("src"), roots, &[], &[], &mut Vec::new(), true).unwrap();
+        let target = dir.path().join("dst");
+        fs::create_dir_all(&target).unwrap();
+        let mut log_file = BufWriter::new(fs::File::create(target.join(MODPACK_LOG)).unwrap());
+        install(sources, &target, roots, &InstallOptions { visibility, max_buildings, link_assets: false, assets_dirs }, &mut log_file, &mut InstallTimings::default());
+        log_file.flush().unwrap();
+        target
+    }
+
+    #[test]
+    fn install_reports_orphan_assets_and_keeps_them() {
+        let dir = ScratchDir::new("orphans");
+        let roots = test_util::game_roots(&dir);
+        test_util::write_building(&dir, "src/b1", "b1");
+        let orphan = dir.write("dst/dds/0123456789abcdef0123456789abcdef.dds", "DDS not written by the install");
+
+        let target = install_src(&dir, &roots, 0, None, ("dds", "nmf"));
+
+        assert!(orphan.exists());
+        let log = fs::read_to_string(target.join(MODPACK_LOG)).unwrap();
+        let orphan_lines = log.lines().filter(|l| l.starts_with("<ORPHAN>")).collect::<Vec<_>>();
+        assert_eq!(orphan_lines, vec![format!("<ORPHAN> {}", orphan.display())]);
+    }
+
+    #[test]
+    fn model_in_another_building_dir_is_flagged() {
+        let dir = ScratchDir::new("crossref");
+        let roots = test_util::game_roots(&dir);
+        let src = dir.path().join("src");
+        test_util::write_building(&dir, "src/b1", "b1");
+        // a relative path that goes through its own directory into b1
+        test_util::write_building(&dir, "src/b2", "b2");
+        dir.write("src/b2/renderconfig.source", "MODEL ../b2/../b1/model.nmf\r\nMATERIAL model.mtl\r\n");
+        // reusing b1 through renderconfig.ref is not flagged (a ref target needs renderconfig.ini)
+        dir.write("src/b1/renderconfig.ini", "MODEL model.nmf\r\nMATERIAL model.mtl\r\n");
+        let mut bld_ini = Vec::new();
+        ini::building::write_stub(&mut bld_ini, "b3").unwrap();
+        dir.write("src/b3/building.ini", bld_ini);
+        dir.write("src/b3/renderconfig.ref", "../b1");
+
+        let mut reports = Vec::new();
+        let (sources, _) = read_validate_sources(&src, &roots, &[], &[], &mut reports, true).unwrap();
+        assert_eq!(sources.len(), 3);
+
+        let warnings = |name: &str| &reports.iter().find(|r| r.dir == src.join(name)).unwrap().warnings;
+        assert!(warnings("b1").is_empty());
+        assert!(warnings("b3").is_empty());
+        let b2 = warnings("b2");
+        assert_eq!(b2.len(), 1);
+        assert!(b2[0].contains("resolves into the source directory of another building (b1)"), "{}", b2[0]);
+    }
+
+    // uninstall goes by the asset dirs recorded at install time, whatever the current defaults are
+    #[test]
+    fn uninstall_removes_only_logged_dirs() {
+        let dir = ScratchDir::new("uninstall");
+        let roots = test_util::game_roots(&dir);
+        test_util::write_building(&dir, "src/b1", "b1");
+        let unrelated = dir.write("dst/dds/keep.txt", "not part of the modpack");
+
+        let target = install_src(&dir, &roots, 0, None, ("tex", "models"));
+        let mod_dir = target.join(MOD_IDS_START.to_string());
+        assert!(mod_dir.is_dir() && target.join("tex").is_dir() && target.join("models").is_dir());
+
+        assert_eq!(uninstall(&target).unwrap(), 1);
+        assert!(!mod_dir.exists());
+        assert!(!target.join("tex").exists());
+        assert!(!target.join("models").exists());
+        assert!(!target.join(MODPACK_LOG).exists());
+        assert!(unrelated.exists());
+    }
+
+    #[test]
+    fn uninstall_rejects_asset_dir_outside_target() {
+        let dir = ScratchDir::new("uninstall-bad");
+        let outside = dir.write("outside/file.txt", "must survive");
+        dir.write(&format!("dst/{}", MODPACK_LOG), format!("{} ../outside\n", LOG_ASSETS_DIR));
+
+        assert!(uninstall(&dir.path().join("dst")).is_err());
+        assert!(outside.exists());
+    }
+
+    #[test]
+    fn install_writes_custom_visibility() {
+        let dir = ScratchDir::new("visibility");
+        let roots = test_util::game_roots(&dir);
+        test_util::write_building(&dir, "src/b1", "b1");
+        let target = install_src(&dir, &roots, 2, None, ("dds", "nmf"));
+
+        let config = fs::read_to_string(target.join(MOD_IDS_START.to_string()).join(WORKSHOPCONFIG)).unwrap();
+        assert!(config.lines().any(|l| l.trim() == "$VISIBILITY 2"), "{}", config);
+    }
+
+    #[test]
+    fn shared_building_name_is_reported() {
+        let dir = ScratchDir::new("names");
+        let roots = test_util::game_roots(&dir);
+        let src = dir.path().join("src");
+        test_util::write_building(&dir, "src/b1", "Same name");
+        test_util::write_building(&dir, "src/b2", "Same name");
+        test_util::write_building(&dir, "src/b3", "Other name");
+
+        let mut reports = Vec::new();
+        let (sources, _) = read_validate_sources(&src, &roots, &[], &[], &mut reports, true).unwrap();
+        assert_eq!(sources.len(), 3);
+
+        let warnings = |name: &str| &reports.iter().find(|r| r.dir == src.join(name)).unwrap().warnings;
+        for b in ["b1", "b2"] {
+            assert_eq!(warnings(b).len(), 1);
+            assert!(warnings(b)[0].starts_with("2 buildings share the same name") && warnings(b)[0].contains("Same name"), "{}", warnings(b)[0]);
+        }
+        assert!(warnings("b3").is_empty());
+    }
+
+    #[test]
+    fn excluded_buildings_are_not_read() {
+        let dir = ScratchDir::new("exclude");
+        let roots = test_util::game_roots(&dir);
+        let src = dir.path().join("src");
+        test_util::write_building(&dir, "src/b1", "b1");
+        test_util::write_building(&dir, "src/old/b2", "b2");
+        test_util::write_building(&dir, "src/new/old_b3", "b3");
+
+        let exclude = ["o*".to_string(), "**/old_*".to_string()];
+        let mut reports = Vec::new();
+        let (sources, _) = read_validate_sources(&src, &roots, &exclude, &[], &mut reports, true).unwrap();
+
+        assert_eq!(sources.iter().map(|s| s.source_dir.clone()).collect::<Vec<_>>(), vec![src.join("b1")]);
+        assert_eq!(reports.len(), 1);
+    }
+
+    #[test]
+    fn max_buildings_limits_install() {
+        let dir = ScratchDir::new("max-buildings");
+        let roots = test_util::game_roots(&dir);
+        for name in ["b1", "b2", "b3"] {
+            test_util::write_building(&dir, &format!("src/{}", name), name);
+        }
+
+        let target = install_src(&dir, &roots, 0, Some(1), ("dds", "nmf"));
+
+        let mod_dir = target.join(MOD_IDS_START.to_string());
+        assert!(mod_dir.join("00").join("building.ini").exists());
+        assert!(!mod_dir.join("01").exists());
+        assert!(!target.join((MOD_IDS_START + 1).to_string()).exists());
+
+        let log = fs::read_to_string(target.join(MODPACK_LOG)).unwrap();
+        let installed = log.lines().filter(|l| !l.starts_with(LOG_ASSETS_DIR)).collect::<Vec<_>>();
+        assert_eq!(installed, vec![format!("{}/00 {}", MOD_IDS_START, dir.path().join("src").join("b1").display())]);
+    }
+
+    #[test]
+    fn stats_count_buildings_skins_and_unique_assets() {
+        let dir = ScratchDir::new("stats");
+        let roots = test_util::game_roots(&dir);
+        for name in ["b1", "b2", "b3"] {
+            test_util::write_building(&dir, &format!("src/{}", name), name);
+        }
+        // same texture content in b1 and b3, and all three models are the same
+        dir.write("src/b3/tex.dds", "DDS texture of b1");
+        let mtl = fs::read(dir.path().join("src/b1/model.mtl")).unwrap();
+        dir.write("src/b1/skin_1.mtl", &mtl);
+        dir.write("src/b1/skin_2.mtl", &mtl);
+        dir.write("src/b1/building.skins", "skin_1.mtl\r\nskin_2.mtl\r\n");
+
+        let (sources, skins_count) = read_validate_sources(&dir.path().join("src"), &roots, &[], &[], &mut Vec::new(), true).unwrap();
+        let stats = SourceStats::collect(&sources, skins_count).unwrap();
+
+        assert_eq!((stats.buildings, stats.skins), (3, 2));
+        assert_eq!((stats.models, stats.models_unique), (3, 1));
+        assert_eq!((stats.textures, stats.textures_unique), (3, 2));
+        assert_eq!((stats.building_mods, stats.skin_mods), (1, 1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linked_assets_point_to_sources() {
+        let dir = ScratchDir::new("link-assets");
+        let roots = test_util::game_roots(&dir);
+        let src = test_util::write_building(&dir, "src/b1", "b1");
+
+        let (sources, _) = read_validate_sources(&dir.path().join("src"), &roots, &[], &[], &mut Vec::new(), true).unwrap();
+        let target = dir.path().join("dst");
+        fs::create_dir_all(&target).unwrap();
+        let mut log_file = BufWriter::new(fs::File::create(target.join(MODPACK_LOG)).unwrap());
+        install(sources, &target, &roots, &InstallOptions { visibility: 0, max_buildings: None, link_assets: true, assets_dirs: ("dds", "nmf") }, &mut log_file, &mut InstallTimings::default());
+
+        for (assets_dir, source) in [("dds", "tex.dds"), ("nmf", "model.nmf")] {
+            let entries = fs::read_dir(target.join(assets_dir)).unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+            assert_eq!(entries.len(), 1, "{:?}", entries);
+            let installed = &entries[0];
+
+            assert!(fs::symlink_metadata(installed).unwrap().file_type().is_symlink(), "{}", installed.display());
+            assert_eq!(fs::read_link(installed).unwrap(), fs::canonicalize(src.join(source)).unwrap());
+            assert_eq!(fs::read(installed).unwrap(), fs::read(src.join(source)).unwrap());
+        }
+    }
+
+    #[test]
+    fn only_installs_selected_buildings() {
+        let dir = ScratchDir::new("only");
+        let roots = test_util::game_roots(&dir);
+        let src = dir.path().join("src");
+        for rel in ["a/b1", "a/b2", "c/b3"] {
+            test_util::write_building(&dir, &format!("src/{}", rel), rel);
+        }
+
+        let selected = |only: &[&str]| {
+            let only = only.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+            read_validate_sources(&src, &roots, &[], &only, &mut Vec::new(), true).unwrap().0
+        };
+        // by directory name, and by relative path glob
+        assert_eq!(selected(&["b2"]).iter().map(|s| s.source_dir.clone()).collect::<Vec<_>>(), vec![src.join("a/b2")]);
+        assert_eq!(selected(&["c/*"]).iter().map(|s| s.source_dir.clone()).collect::<Vec<_>>(), vec![src.join("c/b3")]);
+
+        let target = dir.path().join("dst");
+        fs::create_dir_all(&target).unwrap();
+        let mut log_file = BufWriter::new(fs::File::create(target.join(MODPACK_LOG)).unwrap());
+        install(selected(&["b2"]), &target, &roots, &InstallOptions { visibility: 0, max_buildings: None, link_assets: false, assets_dirs: ("dds", "nmf") }, &mut log_file, &mut InstallTimings::default());
+        log_file.flush().unwrap();
+
+        let log = fs::read_to_string(target.join(MODPACK_LOG)).unwrap();
+        let installed = log.lines().filter(|l| !l.starts_with(LOG_ASSETS_DIR)).collect::<Vec<_>>();
+        assert_eq!(installed, vec![format!("{}/00 {}", MOD_IDS_START, src.join("a/b2").display())]);
+        assert!(!target.join(MOD_IDS_START.to_string()).join("01").exists());
+    }
+
+    #[test]
+    fn source_and_ref_together_name_both_files() {
+        let dir = ScratchDir::new("multi-render");
+        let roots = test_util::game_roots(&dir);
+        test_util::write_building(&dir, "src/a/b1", "b1");
+        dir.write("src/a/b1/renderconfig.ref", "#other");
+
+        let mut reports = Vec::new();
+        assert_eq!(read_validate_sources(&dir.path().join("src"), &roots, &[], &[], &mut reports, true).err(), Some(1));
+
+        let errors = reports[0].errors.join("\n");
+        let rel = Path::new("a").join("b1");
+        assert!(errors.contains(&rel.join(RENDERCONFIG_SOURCE).display().to_string()), "{}", errors);
+        assert!(errors.contains(&rel.join(RENDERCONFIG_REF).display().to_string()), "{}", errors);
+    }
+
+    #[test]
+    fn custom_asset_dirs_are_used_in_tokens() {
+        let dir = ScratchDir::new("asset-dir-names");
+        let roots = test_util::game_roots(&dir);
+        test_util::write_building(&dir, "src/b1", "b1");
+
+        let target = install_src(&dir, &roots, 0, None, ("tex", "mdl"));
+        assert!(!target.join("dds").exists() && !target.join("nmf").exists());
+
+        let bld_dir = target.join(MOD_IDS_START.to_string()).join("00");
+        for (assets_dir, file, ext) in [("tex", MATERIAL_MTL, "dds"), ("mdl", RENDERCONFIG_INI, "nmf")] {
+            let entries = fs::read_dir(target.join(assets_dir)).unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+            assert_eq!(entries.len(), 1, "{:?}", entries);
+            assert_eq!(entries[0].extension().unwrap(), ext);
+
+            let asset_name = entries[0].file_name().unwrap().to_str().unwrap().to_string();
+            let content = fs::read_to_string(bld_dir.join(file)).unwrap();
+            assert!(content.contains(&format!("../../{}/{}", assets_dir, asset_name)), "{}", content);
+        }
+    }
+
+    #[test]
+    fn ref_chains_are_followed_and_cycles_reported() {
+        let dir = ScratchDir::new("ref-cycle");
+        let roots = test_util::game_roots(&dir);
+        let real = test_util::write_building(&dir, "lib/real", "real");
+        dir.write("lib/real/renderconfig.ini", "MODEL model.nmf\r\nMATERIAL model.mtl\r\n");
+        dir.write("lib/hop/renderconfig.ref", "../real");
+        let bld_ini = fs::read(real.join(BUILDING_INI)).unwrap();
+
+        // a -> b -> a, and c -> lib/hop -> lib/real
+        for (name, target) in [("a", "../b"), ("b", "../a"), ("c", "../../lib/hop")] {
+            dir.write(&format!("src/{}/{}", name, BUILDING_INI), &bld_ini);
+            dir.write(&format!("src/{}/{}", name, RENDERCONFIG_REF), target);
+        }
+
+        let mut reports = Vec::new();
+        assert_eq!(read_validate_sources(&dir.path().join("src"), &roots, &[], &[], &mut reports, true).err(), Some(2));
+
+        let src = fs::canonicalize(dir.path().join("src")).unwrap();
+        let report = |name: &str| reports.iter().find(|r| r.dir.ends_with(name)).unwrap();
+        let (a_ref, b_ref) = (src.join("a").join(RENDERCONFIG_REF), src.join("b").join(RENDERCONFIG_REF));
+        assert_eq!(report("a").errors.join("\n"), format!("Building references form a cycle: {} -> {} -> {}",
+                                                          a_ref.display(), b_ref.display(), a_ref.display()));
+        assert_eq!(report("b").errors.len(), 1);
+        assert!(report("c").errors.is_empty(), "{:?}", report("c").errors);
+    }
+
+    #[test]
+    fn new_building_scaffold_validates_once_assets_are_added() {
+        let dir = ScratchDir::new("new-building");
+        let roots = test_util::game_roots(&dir);
+        let src = dir.path().join("src");
+        let b1 = src.join("b1");
+
+        let files = new_building(&b1).unwrap();
+        assert_eq!(files.len(), 4);
+        assert!(files.iter().all(|f| f.exists()));
+        assert_eq!(new_building(&b1).unwrap_err().kind(), std::io::ErrorKind::AlreadyExists);
+
+        // the stubs parse, the only error is a missing placeholder file
+        let mut reports = Vec::new();
+        assert_eq!(read_validate_sources(&src, &roots, &[], &[], &mut reports, true).err(), Some(1));
+        assert_eq!(reports[0].errors.len(), 1);
+        assert!(reports[0].errors[0].contains(&b1.join("model.mtl").display().to_string()), "{:?}", reports[0].errors);
+
+        let assets = test_util::write_building(&dir, "assets", "a");
+        for f in ["model.nmf", "model.mtl", "tex.dds"] {
+            fs::copy(assets.join(f), b1.join(f)).unwrap();
         }
+
+        let mut reports = Vec::new();
+        let (sources, skins_count) = read_validate_sources(&src, &roots, &[], &[], &mut reports, true).unwrap();
+        assert_eq!((sources.len(), skins_count), (1, 0));
+        assert!(sources[0].actions.is_none());
+        assert!(reports[0].errors.is_empty() && reports[0].warnings.is_empty(), "{:?}", reports[0].warnings);
+    }
+
+    #[test]
+    fn sources_are_read_in_name_order() {
+        let dir = ScratchDir::new("sorted-sources");
+        let roots = test_util::game_roots(&dir);
+        for name in ["zeta", "alpha", "Mid", "group/b2", "beta_2", "group/b1", "beta_10"] {
+            test_util::write_building(&dir, &format!("src/{}", name), name);
+        }
+
+        let src = dir.path().join("src");
+        let order = || read_validate_sources(&src, &roots, &[], &[], &mut Vec::new(), true).unwrap().0
+            .into_iter().map(|s| s.source_dir.strip_prefix(&src).unwrap().to_path_buf()).collect::<Vec<_>>();
+
+        let first = order();
+        let expected = ["Mid", "alpha", "beta_10", "beta_2", "group/b1", "group/b2", "zeta"].iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(first, expected);
+        assert_eq!(order(), first);
     }
 }
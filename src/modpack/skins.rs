@@ -2,10 +2,11 @@ use std::io::Error as IOErr;
 use std::path::{Path, PathBuf};
 
 use lazy_static::lazy_static;
+use const_format::concatcp;
 use regex::Regex;
 
 use crate::{read_to_string_buf};
-use crate::ini::{self, resolve_source_path};
+use crate::ini::{self, GameRoots};
 use crate::building_def;
 
 
@@ -24,24 +25,25 @@ pub enum Error {
 pub type Skins = Vec<(PathBuf, Option<PathBuf>)>;
 
 
-pub fn read_skins(path: &Path, buf: &mut String) -> Result<Skins, Error> {
+pub fn read_skins(path: &Path, roots: &GameRoots, buf: &mut String) -> Result<Skins, Error> {
     use ini::common::IdStringParam;
     lazy_static! {
         static ref RX_SKIN: Regex = Regex::new(r"(?s)^([^\s]+)(\s+([^\s]+))?$").unwrap();
-        static ref RX_LINES: Regex = Regex::new(r"(?s)(\s*\r?\n)+").unwrap();
+        // '--' and '//' comment lines are skipped like in ini files
+        static ref RX_LINES: Regex = Regex::new(concatcp!("(?s)(", ini::common::RX_LEADING_COMMENTS, "|", ini::common::RX_LINE_BREAKS, ")")).unwrap();
     }
 
     buf.clear();
     read_to_string_buf(path, buf).map_err(Error::SkinsFileRead)?;
     let mut result = Skins::with_capacity(16);
 
-    for line in RX_LINES.split(&buf) {
+    for line in RX_LINES.split(buf) {
         if !line.is_empty() {
             match RX_SKIN.captures(line) {
                 Some(cap) => {
                     let root = path.parent().unwrap();
-                    let mtl = resolve_source_path(root, &IdStringParam::new_borrowed(cap.get(1).unwrap().as_str()));
-                    let mtl_e = cap.get(3).map(|x| resolve_source_path(root, &IdStringParam::new_borrowed(x.as_str())));
+                    let mtl = roots.resolve_source_path(root, &IdStringParam::new_borrowed(cap.get(1).unwrap().as_str()));
+                    let mtl_e = cap.get(3).map(|x| roots.resolve_source_path(root, &IdStringParam::new_borrowed(x.as_str())));
                     result.push((mtl, mtl_e));
                 },
                 None => return Err(Error::SkinsFileParse(line.to_string()))
@@ -53,8 +55,10 @@ pub fn read_skins(path: &Path, buf: &mut String) -> Result<Skins, Error> {
 }
 
 
-pub fn validate(skins: &Skins, used_submaterials: &[&str], buf: &mut String) -> Result<(), Error> {
+// Ok: warnings
+pub fn validate(skins: &Skins, used_submaterials: &[&str], roots: &GameRoots, buf: &mut String) -> Result<Vec<String>, Error> {
     let mut validation_errors = Vec::with_capacity(0);
+    let mut warnings = Vec::with_capacity(0);
 
     macro_rules! check_mtl {
         ($mtl_path:ident) => {
@@ -65,10 +69,10 @@ pub fn validate(skins: &Skins, used_submaterials: &[&str], buf: &mut String) ->
                 e.into_iter().map(|(_, e)|  e).collect())
                 )?;
 
-            building_def::push_mtl_errors(&mtl, used_submaterials.iter(), &mut validation_errors, $mtl_path.display());
+            building_def::push_mtl_errors(&mtl, used_submaterials.iter(), &mut validation_errors, &mut warnings, $mtl_path.display());
             let mtl_root = $mtl_path.parent().unwrap();
 
-            for tx in mtl.get_texture_paths(|p| resolve_source_path(mtl_root, p)) {
+            for tx in mtl.get_texture_paths(roots, |p| roots.resolve_source_path(mtl_root, p)) {
                 if !tx.exists() {
                     return Err(Error::TexturePathInvalid(tx));
                 }
@@ -84,8 +88,54 @@ pub fn validate(skins: &Skins, used_submaterials: &[&str], buf: &mut String) ->
     }
 
     if validation_errors.is_empty() {
-        Ok(())
+        Ok(warnings)
     } else {
         Err(Error::SkinValidation(validation_errors))
     }
 }
+
+
+use std::fmt;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::SkinsFileRead(e)      => write!(f, "Could not read skins file: {}", e),
+            Error::SkinsFileParse(line)  => write!(f, "Could not parse skins file line: [{}]", line),
+            Error::MtlRead(e)            => write!(f, "Could not read skin mtl: {}", e),
+            Error::MtlParse(path, e)     => {
+                writeln!(f, "Could not parse skin mtl {}:", path.display())?;
+                for i in e.iter() {
+                    writeln!(f, "    {}", i)?;
+                }
+                Ok(())
+            },
+            Error::SkinValidation(e)     => {
+                writeln!(f, "Validation failed: ")?;
+                for i in e.iter() {
+                    writeln!(f, "    {}", i)?;
+                }
+                Ok(())
+            },
+            Error::TexturePathInvalid(p) => write!(f, "Texture does not exist: {}", p.display()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, ScratchDir};
+
+    #[test]
+    fn comments_are_skipped() {
+        let dir = ScratchDir::new("skins-comments");
+        let roots = test_util::game_roots(&dir);
+        let path = dir.write("b1/building.skins", "-- winter skins\r\nskin_1.mtl\r\n\r\n// emissive too\r\nskin_2.mtl skin_2_e.mtl\r\n-- trailing");
+
+        let skins = read_skins(&path, &roots, &mut String::new()).unwrap_or_else(|_| panic!("Cannot read skins"));
+        let b1 = dir.path().join("b1");
+        assert_eq!(skins, vec![(b1.join("skin_1.mtl"), None), (b1.join("skin_2.mtl"), Some(b1.join("skin_2_e.mtl")))]);
+    }
+}
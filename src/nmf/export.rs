@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+use std::convert::TryInto;
+
+use super::{ObjectFull, NameBuf};
+use super::object_full::{RawVertex, RawPoint};
+
+
+pub enum ObjIndexBase {
+    // 1-based, running over the whole file
+    File,
+    // negative, relative to the end of the object's own v/vt/vn lists
+    Object,
+}
+
+
+// submaterials: if set, faces are split into groups by submaterial
+pub fn write_obj<'a, I, W>(objects: I, mut wr: W, submaterials: Option<&[NameBuf]>, index_base: &ObjIndexBase) -> io::Result<()>
+    where I: Iterator<Item = &'a ObjectFull>,
+          W: Write
+{
+    let mut vx_map = ahash::AHashMap::<&RawVertex, usize>::with_capacity(0);
+    let mut n1_map = ahash::AHashMap::<&RawVertex, usize>::with_capacity(0);
+    let mut uv_map = ahash::AHashMap::<&RawPoint, usize>::with_capacity(0);
+    let mut vx_vec = Vec::<usize>::with_capacity(0);
+    let mut n1_vec = Vec::<usize>::with_capacity(0);
+    let mut uv_vec = Vec::<usize>::with_capacity(0);
+
+    let mut d_vx = 0_usize;
+    let mut d_n1 = 0_usize;
+    let mut d_uv = 0_usize;
+
+    for obj in objects {
+        let verts = obj.vertices();
+
+        vx_map.clear();
+        n1_map.clear();
+        uv_map.clear();
+
+        vx_vec.clear();
+        n1_vec.clear();
+        uv_vec.clear();
+
+        vx_map.reserve(verts.len());
+        n1_map.reserve(verts.len());
+        uv_map.reserve(verts.len());
+
+        vx_vec.reserve(verts.len());
+        n1_vec.reserve(verts.len());
+        uv_vec.reserve(verts.len());
+
+        writeln!(wr, "o {}", obj.name())?;
+
+        for v in verts {
+            if !vx_map.contains_key(v) {
+                d_vx += 1;
+                vx_map.insert(v, d_vx);
+                vx_vec.push(d_vx);
+                writeln!(wr, "v {:.6} {:.6} {:.6}", v.x, v.y, v.z)?;
+            } else {
+                vx_vec.push(vx_map[v]);
+            }
+        }
+
+        let uvs = obj.uv_map();
+        for uv in uvs {
+            if !uv_map.contains_key(uv) {
+                d_uv += 1;
+                uv_map.insert(uv, d_uv);
+                uv_vec.push(d_uv);
+                writeln!(wr, "vt {:.6} {:.6}", uv.x, 1f32 - uv.y)?;
+            } else {
+                uv_vec.push(uv_map[uv]);
+            }
+        }
+
+        let ns = obj.normals_1();
+        for n in ns {
+            if !n1_map.contains_key(n) {
+                d_n1 += 1;
+                n1_map.insert(n, d_n1);
+                n1_vec.push(d_n1);
+                writeln!(wr, "vn {:.6} {:.6} {:.6}", n.x, n.y, n.z)?;
+            } else {
+                n1_vec.push(n1_map[n]);
+            }
+        }
+
+        writeln!(wr, "s off")?;
+
+        // faces only reference this object's lines, so a relative index is (absolute - count written so far - 1)
+        let (b_vx, b_uv, b_n1) = match index_base {
+            ObjIndexBase::File   => (0, 0, 0),
+            ObjIndexBase::Object => (d_vx as isize + 1, d_uv as isize + 1, d_n1 as isize + 1),
+        };
+
+        let faces = obj.faces();
+        let groups = match submaterials.map(|sms| (sms, obj.submaterial_faces())) {
+            Some((sms, Some(ranges))) => ranges.into_iter().map(|(sm, r)| (Some(sms[sm as usize].as_str()), r)).collect(),
+            Some((_, None)) => {
+                eprintln!("WARNING: submaterial ranges of object '{}' do not match its faces, writing it as a single group", obj.name());
+                vec![(None, 0 .. faces.len())]
+            },
+            None => vec![(None, 0 .. faces.len())],
+        };
+
+        for (sm_name, range) in groups {
+            if let Some(sm_name) = sm_name {
+                writeln!(wr, "g {}_{}", obj.name(), sm_name)?;
+                writeln!(wr, "usemtl {}", sm_name)?;
+            }
+
+            for f in &faces[range] {
+                let v1  = vx_vec[f.v1 as usize] as isize - b_vx;
+                let n1  = n1_vec[f.v1 as usize] as isize - b_n1;
+                let uv1 = uv_vec[f.v1 as usize] as isize - b_uv;
+
+                let v2  = vx_vec[f.v2 as usize] as isize - b_vx;
+                let n2  = n1_vec[f.v2 as usize] as isize - b_n1;
+                let uv2 = uv_vec[f.v2 as usize] as isize - b_uv;
+
+                let v3  = vx_vec[f.v3 as usize] as isize - b_vx;
+                let n3  = n1_vec[f.v3 as usize] as isize - b_n1;
+                let uv3 = uv_vec[f.v3 as usize] as isize - b_uv;
+
+                write!(wr, "f {}/{}/{}",   v1, uv1, n1)?;
+                write!(wr, "  {}/{}/{}",   v2, uv2, n2)?;
+                writeln!(wr, "  {}/{}/{}", v3, uv3, n3)?;
+            }
+        }
+    }
+
+    wr.flush()
+}
+
+
+// Binary STL: 80 bytes header, u32 triangles count, then per triangle: normal, 3 vertices (f32 x3 each), u16 attributes
+pub fn write_stl<'a, I, W>(objects: I, mut wr: W) -> io::Result<()>
+    where I: Iterator<Item = &'a ObjectFull> + Clone,
+          W: Write
+{
+    let mut header = [0u8; 80];
+    let title = b"wrsr-mt nmf export";
+    header[.. title.len()].copy_from_slice(title);
+    wr.write_all(&header)?;
+
+    let triangles: usize = objects.clone().map(|o| o.faces().len()).sum();
+    let triangles: u32 = triangles.try_into().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many triangles for stl"))?;
+    wr.write_all(&triangles.to_le_bytes())?;
+
+    let mut record = [0u8; 50];
+    for obj in objects {
+        let verts = obj.vertices();
+        for f in obj.faces() {
+            let (v1, v2, v3) = (&verts[f.v1 as usize], &verts[f.v2 as usize], &verts[f.v3 as usize]);
+            let (a, b) = ([v2.x - v1.x, v2.y - v1.y, v2.z - v1.z], [v3.x - v1.x, v3.y - v1.y, v3.z - v1.z]);
+            let n = [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+            let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+            let n = if len > 0f32 { [n[0] / len, n[1] / len, n[2] / len] } else { n };
+
+            let floats = [n[0], n[1], n[2], v1.x, v1.y, v1.z, v2.x, v2.y, v2.z, v3.x, v3.y, v3.z];
+            for (i, x) in floats.iter().enumerate() {
+                record[i * 4 .. i * 4 + 4].copy_from_slice(&x.to_le_bytes());
+            }
+            // last 2 bytes (attributes) stay zero
+            wr.write_all(&record)?;
+        }
+    }
+
+    wr.flush()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn object_index_base_makes_face_indices_relative() {
+        let tri = [(0., 0., 0.), (1., 0., 0.), (0., 0., 1.)];
+        let a = test_util::object("a", &tri, &[(0, 1, 2)], &[(0, 1)]);
+        let b = test_util::object("b", &tri, &[(0, 1, 2)], &[(0, 1)]);
+
+        let faces = |index_base| {
+            let mut out = Vec::new();
+            write_obj([&a, &b].iter().copied(), &mut out, None, &index_base).unwrap();
+            String::from_utf8(out).unwrap().lines().filter(|l| l.starts_with("f ")).map(String::from).collect::<Vec<_>>()
+        };
+
+        assert_eq!(faces(ObjIndexBase::File)[1], "f 4/4/2  5/5/2  6/6/2");
+        assert_eq!(faces(ObjIndexBase::Object), vec!["f -3/-3/-1  -2/-2/-1  -1/-1/-1"; 2]);
+    }
+
+    #[test]
+    fn stl_has_one_record_per_face() {
+        let nmf = test_util::sample_nmf();
+        let faces: usize = nmf.objects.iter().map(|o| o.faces().len()).sum();
+
+        let mut out = Vec::new();
+        write_stl(nmf.objects.iter(), &mut out).unwrap();
+
+        assert_eq!(out.len(), 84 + faces * 50);
+        assert_eq!(u32::from_le_bytes([out[80], out[81], out[82], out[83]]) as usize, faces);
+    }
+}
@@ -5,8 +5,9 @@ use std::io::{self, Read, Seek, Write};
 use std::convert::TryInto;
 
 pub mod object_full;
+pub mod export;
 
-pub use object_full::ObjectFull;
+pub use object_full::{ObjectFull, ObjectParts, NormalsMode};
 
 
 #[derive(Debug)]
@@ -15,8 +16,10 @@ pub enum Error {
     HeaderEOF(ChopEOF),
     UnknownNmfType,
     FileLengthMismatch(usize, u64),
+    CountsExceedLength(usize, usize, u64),
     ZeroSubmaterials,
     Submaterial(usize, io::Error),
+    SubmaterialName(usize, usize),
     ZeroObjects,
     Object(usize, ObjectError),
     U32Conversion(std::num::TryFromIntError),
@@ -32,6 +35,14 @@ pub enum ObjectError {
     ZeroSubmaterials,
     SubmaterialIdxTooBig(u32),
     Allocation(String),
+    NonFiniteVertex(usize),
+    FaceIndexOutOfRange(usize, u16),
+    TooManyVertices(usize),
+    SizeMismatch(usize, usize),
+    ExceedsData(u64, u64),
+    NameTooLong(usize),
+    EmptyName,
+    CountMismatch(&'static str, usize, usize),
 }
 
 
@@ -52,6 +63,7 @@ pub struct NmfBuf<T> {
 }
 
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum NmfType {
     FromObj,
     B3dmh10
@@ -79,38 +91,51 @@ pub type NmfBufFull = NmfBuf<ObjectFull>;
 
 
 pub trait ObjectReader<R: Read> {
-    fn from_reader(rdr: &mut R, max_sm_idx: usize) -> Result<Self, ObjectError> where Self: Sized;
+    // data_left: bytes of NMF data from the current reader position. Counts in the object header
+    // must be checked against it before anything is allocated.
+    fn from_reader(rdr: &mut R, max_sm_idx: usize, data_left: u64) -> Result<Self, ObjectError> where Self: Sized;
 }
 
 
 //----------------------------------------------------------------------------------
 
 
-impl<T: ObjectReader<fs::File>> NmfBuf<T> {
+impl<T> NmfBuf<T> {
 
-    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<NmfBuf<T>, Error> {
-        let path: &Path = path.as_ref();
-        
-        let mut buf = [0; 512];
+    // Header signature: "fromObj" or "B3DMH 10"
+    pub fn nmf_type(&self) -> NmfType {
+        self.nmf_type
+    }
+
+    // Bytes left after the last object (not part of the parsed structure)
+    pub fn remainder(&self) -> u64 {
+        self.remainder
+    }
 
-        let mut file = fs::File::open(path).map_err(Error::FileIO)?;
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<NmfBuf<T>, Error> 
+    where T: ObjectReader<fs::File>
+    {
+        let mut file = fs::File::open(path.as_ref()).map_err(Error::FileIO)?;
         let file_len = file.metadata().map_err(Error::FileIO).map(|md| md.len())?;
+        Self::from_reader(&mut file, file_len)
+    }
 
-        let (nmf_type, submat_count, obj_count, nmf_len) = {
-            let slice = &mut buf[0 .. 20];
-            file.read_exact(slice).map_err(Error::FileIO)?;
-            let mut chop = SliceChopper::from(slice);
-            
-            let nmf_type  = chop.chop_subslice(8).map_err(Error::HeaderEOF).and_then(|s| NmfType::from_slice(s).ok_or(Error::UnknownNmfType))?;
-            let sm_count  = chop.chop_u32size().map_err(Error::HeaderEOF)?;
-            let obj_count = chop.chop_u32size().map_err(Error::HeaderEOF)?;
-            let nmf_len   = chop.chop_u32size().map_err(Error::HeaderEOF)?;
+    pub fn from_bytes(bytes: &[u8]) -> Result<NmfBuf<T>, Error> 
+    where T: for<'b> ObjectReader<io::Cursor<&'b [u8]>>
+    {
+        Self::from_reader(&mut io::Cursor::new(bytes), bytes.len() as u64)
+    }
 
-            (nmf_type, sm_count, obj_count, nmf_len)
-        };
+    // data_len: total length of the NMF data, starting from the current reader position
+    pub fn from_reader<R: Read + Seek>(rdr: &mut R, data_len: u64) -> Result<NmfBuf<T>, Error> 
+    where T: ObjectReader<R>
+    {
+        let start = rdr.stream_position().map_err(Error::FileIO)?;
+
+        let (nmf_type, submat_count, obj_count, nmf_len) = read_header(rdr)?;
 
-        if nmf_len as u64 != file_len {
-            return Err(Error::FileLengthMismatch(nmf_len, file_len));
+        if nmf_len as u64 != data_len {
+            return Err(Error::FileLengthMismatch(nmf_len, data_len));
         }
         if submat_count == 0 {
             return Err(Error::ZeroSubmaterials);
@@ -118,29 +143,74 @@ impl<T: ObjectReader<fs::File>> NmfBuf<T> {
         if obj_count == 0 {
             return Err(Error::ZeroObjects);
         }
+        // counts come from an untrusted header: they must at least fit into the file before anything is allocated
+        if 20 + (submat_count as u64) * (NameBuf::BUF_LENGTH as u64) + (obj_count as u64) * 260 > data_len {
+            return Err(Error::CountsExceedLength(submat_count, obj_count, data_len));
+        }
 
         let mut submaterials = Vec::<NameBuf>::with_capacity(submat_count);
         for i in 0 .. submat_count {
-            submaterials.push(NameBuf::from_reader(&mut file).map_err(|e| Error::Submaterial(i, e))?);
+            submaterials.push(NameBuf::from_reader(rdr).map_err(|e| Error::Submaterial(i, e))?);
         }
         
         let mut objects = Vec::<T>::with_capacity(obj_count);
         for i in 0 .. obj_count {
-            objects.push(T::from_reader(&mut file, submat_count - 1).map_err(|e| Error::Object(i, e))?);
+            let data_left = data_len - (rdr.stream_position().map_err(Error::FileIO)? - start);
+            objects.push(T::from_reader(rdr, submat_count - 1, data_left).map_err(|e| Error::Object(i, e))?);
         }
 
-        let remainder = file_len - file.stream_position().map_err(Error::FileIO)?;
+        let remainder = data_len - (rdr.stream_position().map_err(Error::FileIO)? - start);
 
         Ok(NmfBuf { nmf_type, submaterials, objects, remainder })
     }
 }
 
 
+// Type, submaterials count, objects count, total length
+fn read_header<R: Read>(rdr: &mut R) -> Result<(NmfType, usize, usize, usize), Error> {
+    let mut buf = [0; 20];
+    rdr.read_exact(&mut buf[..]).map_err(Error::FileIO)?;
+    let mut chop = SliceChopper::from(&mut buf[..]);
+
+    let nmf_type  = chop.chop_subslice(8).map_err(Error::HeaderEOF).and_then(|s| NmfType::from_slice(s).ok_or(Error::UnknownNmfType))?;
+    let sm_count  = chop.chop_u32size().map_err(Error::HeaderEOF)?;
+    let obj_count = chop.chop_u32size().map_err(Error::HeaderEOF)?;
+    let nmf_len   = chop.chop_u32size().map_err(Error::HeaderEOF)?;
+
+    Ok((nmf_type, sm_count, obj_count, nmf_len))
+}
+
+
+// Total length stored in the header, and the actual length of the file. Only the header is read.
+pub fn declared_length<P: AsRef<Path>>(path: P) -> Result<(usize, u64), Error> {
+    let mut file = fs::File::open(path.as_ref()).map_err(Error::FileIO)?;
+    let file_len = file.metadata().map_err(Error::FileIO)?.len();
+    let (_, _, _, nmf_len) = read_header(&mut file)?;
+    Ok((nmf_len, file_len))
+}
+
+
+impl ObjectInfo {
+    pub fn vertices(&self) -> u32 {
+        self.vertices
+    }
+
+    pub fn faces(&self) -> u32 {
+        self.faces
+    }
+
+    // Position of the object's bytes in the source data
+    pub fn byte_range(&self) -> std::ops::Range<u64> {
+        self.range.clone()
+    }
+}
+
+
 impl<R: Read + Seek> ObjectReader<R> for ObjectInfo {
-    fn from_reader(rdr: &mut R, max_sm_idx: usize) -> Result<ObjectInfo, ObjectError> {
+    fn from_reader(rdr: &mut R, max_sm_idx: usize, data_left: u64) -> Result<ObjectInfo, ObjectError> {
 
         #[inline]
-        fn skip<R: Seek>(reader: &mut R, n: u32) -> Result<u64, ObjectError> {
+        fn skip<R: Seek>(reader: &mut R, n: u64) -> Result<u64, ObjectError> {
             reader.seek(io::SeekFrom::Current(n as i64)).map_err(ObjectError::FileIO)
         }
 
@@ -165,10 +235,15 @@ impl<R: Read + Seek> ObjectReader<R> for ObjectInfo {
             return Err(ObjectError::ZeroSubmaterials)
         }
 
-        let mut submat_rest = Vec::with_capacity(submats as usize - 1);
-
         let faces = get_faces_count(indices)?;
         let skip_len = indices_len_bytes(indices) + geometry_len_bytes(vertices, faces);
+
+        let obj_len = object_len(vertices, indices, submats)?;
+        if obj_len > data_left {
+            return Err(ObjectError::ExceedsData(obj_len, data_left));
+        }
+
+        let mut submat_rest = Vec::with_capacity(submats as usize - 1);
         // 12 (pre-indices magic bytes) + 8 (primary material indices)
         skip(rdr, 20 + skip_len)?;
 
@@ -223,11 +298,256 @@ impl NmfInfo {
     pub fn object_names(&self) -> impl Iterator<Item = &str> + Clone {
         self.objects.iter().map(|o| o.name.as_str())
     }
+
+    pub fn vertices_count(&self) -> u64 {
+        self.objects.iter().map(|o| o.vertices as u64).sum()
+    }
+
+    // Objects without any geometry (zero vertices or zero faces)
+    pub fn empty_objects(&self) -> impl Iterator<Item = &ObjectInfo> {
+        self.objects.iter().filter(|o| o.vertices == 0 || o.faces == 0)
+    }
+
+    // Structural differences between two nmfs (objects are matched by name). Empty if they are identical.
+    pub fn compare(&self, other: &NmfInfo) -> Vec<String> {
+        let mut diffs = Vec::<String>::with_capacity(0);
+
+        if self.nmf_type != other.nmf_type {
+            diffs.push(format!("Type: {} vs {}", self.nmf_type, other.nmf_type));
+        }
+
+        let sm_names = |nmf: &NmfInfo| nmf.submaterials.iter().map(|sm| sm.as_str().to_string()).collect::<Vec<_>>();
+        let (sm_a, sm_b) = (sm_names(self), sm_names(other));
+        if sm_a != sm_b {
+            diffs.push(format!("Submaterials: {:?} vs {:?}", sm_a, sm_b));
+        }
+
+        if self.objects.len() != other.objects.len() {
+            diffs.push(format!("Objects count: {} vs {}", self.objects.len(), other.objects.len()));
+        }
+
+        let obj_submaterials = |nmf: &NmfInfo, o: &ObjectInfo| {
+            std::iter::once(o.submat_main).chain(o.submat_rest.iter().copied())
+                .map(|i| nmf.submaterials[i as usize].as_str().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        for (i, o_a) in self.objects.iter().enumerate() {
+            let name = o_a.name.as_str();
+            match other.objects.iter().position(|o| o.name.as_str() == name) {
+                None => diffs.push(format!("Object '{}': missing in the second nmf", name)),
+                Some(j) => {
+                    let o_b = &other.objects[j];
+                    if i != j {
+                        diffs.push(format!("Object '{}': position {} vs {}", name, i, j));
+                    }
+                    if o_a.vertices != o_b.vertices {
+                        diffs.push(format!("Object '{}': vertices {} vs {}", name, o_a.vertices, o_b.vertices));
+                    }
+                    if o_a.faces != o_b.faces {
+                        diffs.push(format!("Object '{}': faces {} vs {}", name, o_a.faces, o_b.faces));
+                    }
+
+                    let (osm_a, osm_b) = (obj_submaterials(self, o_a), obj_submaterials(other, o_b));
+                    if osm_a != osm_b {
+                        diffs.push(format!("Object '{}': submaterials {:?} vs {:?}", name, osm_a, osm_b));
+                    }
+                }
+            }
+        }
+
+        for o_b in other.objects.iter().filter(|o| self.objects.iter().all(|o_a| o_a.name.as_str() != o.name.as_str())) {
+            diffs.push(format!("Object '{}': missing in the first nmf", o_b.name.as_str()));
+        }
+
+        diffs
+    }
 }
 
 
 impl NmfBuf<ObjectFull> {
 
+    // Reads the whole file into memory, finds object ranges with a cheap ObjectInfo pass,
+    // then builds ObjectFulls on several threads. Produces the same result as from_path.
+    pub fn from_path_parallel<P: AsRef<Path>>(path: P) -> Result<NmfBufFull, Error> {
+        let bytes = fs::read(path.as_ref()).map_err(Error::FileIO)?;
+        let NmfBuf { nmf_type, submaterials, objects: infos, remainder } = NmfInfo::from_bytes(&bytes)?;
+
+        let max_sm_idx = submaterials.len() - 1;
+        let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let chunk_len = (infos.len() + threads - 1) / threads;
+
+        let objects = std::thread::scope(|scope| {
+            let handles = infos.chunks(chunk_len).enumerate().map(|(chunk_idx, chunk)| {
+                let bytes = &bytes;
+                scope.spawn(move || {
+                    chunk.iter().enumerate().map(|(i, info)| {
+                        let mut rdr = io::Cursor::new(&bytes[info.range.start as usize .. info.range.end as usize]);
+                        ObjectFull::from_reader(&mut rdr, max_sm_idx, info.range.end - info.range.start).map_err(|e| Error::Object(chunk_idx * chunk_len + i, e))
+                    }).collect::<Result<Vec<_>, _>>()
+                })
+            }).collect::<Vec<_>>();
+
+            let mut objects = Vec::<ObjectFull>::with_capacity(infos.len());
+            for h in handles {
+                objects.append(&mut h.join().expect("Object reader thread panicked")?);
+            }
+
+            Ok(objects)
+        })?;
+
+        Ok(NmfBuf { nmf_type, submaterials, objects, remainder })
+    }
+
+    // Best-effort rescue of a damaged file. Objects are read one by one from the bytes actually present,
+    // the length declared in the header is ignored (write_to stores the right one).
+    // An object that fails to parse is dropped; if even its extent is unknown, all the following objects are dropped too.
+    // Bboxes are recalculated, bytes after the last object are dropped. Returns descriptions of the changes made.
+    pub fn repair(bytes: &[u8]) -> Result<(NmfBufFull, Vec<String>), Error> {
+        let data_len = bytes.len() as u64;
+        let mut rdr = io::Cursor::new(bytes);
+        let (nmf_type, submat_count, obj_count, nmf_len) = read_header(&mut rdr)?;
+        let mut changes = Vec::<String>::with_capacity(0);
+
+        if nmf_len as u64 != data_len {
+            changes.push(format!("header length {} does not match the file length {}", nmf_len, data_len));
+        }
+        if submat_count == 0 {
+            return Err(Error::ZeroSubmaterials);
+        }
+        if 20 + (submat_count as u64) * (NameBuf::BUF_LENGTH as u64) > data_len {
+            return Err(Error::CountsExceedLength(submat_count, obj_count, data_len));
+        }
+
+        let mut submaterials = Vec::<NameBuf>::with_capacity(submat_count);
+        for i in 0 .. submat_count {
+            submaterials.push(NameBuf::from_reader(&mut rdr).map_err(|e| Error::Submaterial(i, e))?);
+        }
+
+        let mut objects = Vec::<ObjectFull>::with_capacity(obj_count.min(bytes.len() / 260));
+        let mut pos = rdr.position();
+        for i in 0 .. obj_count {
+            let head = &bytes[pos as usize ..];
+            let obj_len = if head.len() < 260 {
+                Err(ObjectError::ExceedsData(260, head.len() as u64))
+            } else {
+                let count = |at: usize| u32::from_le_bytes([head[at], head[at + 1], head[at + 2], head[at + 3]]);
+                object_len(count(236), count(240), count(244))
+                    .and_then(|len| if len > head.len() as u64 { Err(ObjectError::ExceedsData(len, head.len() as u64)) } else { Ok(len) })
+            };
+
+            let obj_len = match obj_len {
+                Ok(len) => len,
+                Err(e) => {
+                    let dropped = if i + 1 == obj_count { format!("object {}", i) } else { format!("objects {} .. {}", i, obj_count - 1) };
+                    changes.push(format!("dropped {}: extent of object {} is unknown ({})", dropped, i, e));
+                    break;
+                }
+            };
+
+            let obj_bytes = &bytes[pos as usize .. (pos + obj_len) as usize];
+            let read_obj = ObjectInfo::from_reader(&mut io::Cursor::new(obj_bytes), submat_count - 1, obj_len)
+                .and_then(|_| ObjectFull::from_reader(&mut io::Cursor::new(obj_bytes), submat_count - 1, obj_len));
+            match read_obj {
+                Ok(o) => objects.push(o),
+                Err(e) => changes.push(format!("dropped object {} '{}': {}", i, NameBuf::from_reader(&mut &obj_bytes[8 ..]).map(|n| n.to_string()).unwrap_or_default(), e)),
+            }
+
+            pos += obj_len;
+        }
+
+        if objects.is_empty() {
+            return Err(Error::ZeroObjects);
+        }
+
+        if pos < data_len {
+            changes.push(format!("removed {} leftover bytes after the last object", data_len - pos));
+        }
+
+        for o in objects.iter_mut() {
+            if o.recalc_bboxes() {
+                changes.push(format!("recalculated bounding boxes of object '{}'", o.name()));
+            }
+        }
+
+        Ok((NmfBuf { nmf_type, submaterials, objects, remainder: 0 }, changes))
+    }
+
+    // Problems that do not prevent loading, but most likely mean a broken mesh:
+    //   - UV coordinates beyond ±uv_bound (or not finite). Tiling is legitimate, so the bound should be generous.
+    pub fn check(&self, uv_bound: f32) -> Vec<String> {
+        let mut warnings = Vec::<String>::with_capacity(0);
+
+        for o in self.objects.iter() {
+            let uv_map = o.uv_map();
+            let outside = uv_map.iter().filter(|p| !(p.x.abs() <= uv_bound && p.y.abs() <= uv_bound)).count();
+            if outside > 0 {
+                let furthest = uv_map.iter().map(|p| p.x.abs().max(p.y.abs())).fold(0f32, f32::max);
+                warnings.push(format!("object '{}': {} of {} UV points are outside ±{} (furthest: {})", o.name(), outside, uv_map.len(), uv_bound, furthest));
+            }
+        }
+
+        warnings
+    }
+
+    // Reorders the submaterial table by first use (objects in file order),
+    // unused submaterials go last in their original order. Object indices are remapped.
+    // Nothing is changed if an object refers to a submaterial that does not exist.
+    pub fn normalize_submaterials(&mut self) -> Result<(), Error> {
+        check_submaterial_indices(&self.objects, self.submaterials.len())?;
+
+        let mut order = Vec::<usize>::with_capacity(self.submaterials.len());
+        for o in self.objects.iter() {
+            for sm in o.submaterials() {
+                let idx = sm.index as usize;
+                if !order.contains(&idx) {
+                    order.push(idx);
+                }
+            }
+        }
+
+        for idx in 0 .. self.submaterials.len() {
+            if !order.contains(&idx) {
+                order.push(idx);
+            }
+        }
+
+        let mut remap = vec![0u32; order.len()];
+        for (new_idx, old_idx) in order.iter().enumerate() {
+            remap[*old_idx] = new_idx as u32;
+        }
+
+        let mut old_names: Vec<Option<NameBuf>> = self.submaterials.drain(..).map(Some).collect();
+        self.submaterials.extend(order.iter().map(|i| old_names[*i].take().unwrap()));
+
+        for o in self.objects.iter_mut() {
+            for sm in o.submaterials_mut() {
+                sm.index = remap[sm.index as usize];
+            }
+        }
+
+        Ok(())
+    }
+
+    // Assembles an NMF from submaterial names and objects built with ObjectFull::build
+    pub fn new(nmf_type: NmfType, submaterials: &[&str], objects: Vec<ObjectFull>) -> Result<NmfBufFull, Error> {
+        if submaterials.is_empty() {
+            return Err(Error::ZeroSubmaterials);
+        }
+
+        if objects.is_empty() {
+            return Err(Error::ZeroObjects);
+        }
+
+        let submaterials = submaterials.iter().enumerate()
+            .map(|(i, sm)| NameBuf::new(sm).ok_or(Error::SubmaterialName(i, sm.len())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        check_submaterial_indices(&objects, submaterials.len())?;
+
+        Ok(NmfBuf { nmf_type, submaterials, objects, remainder: 0 })
+    }
+
     pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
         let f_out = fs::OpenOptions::new()
                         .write(true)
@@ -263,9 +583,21 @@ impl NmfBuf<ObjectFull> {
 }
 
 
+// Every submaterial index of every object must be below sm_count
+fn check_submaterial_indices(objects: &[ObjectFull], sm_count: usize) -> Result<(), Error> {
+    for (i, o) in objects.iter().enumerate() {
+        if let Some(sm) = o.submaterials().iter().find(|sm| sm.index as usize >= sm_count) {
+            return Err(Error::Object(i, ObjectError::SubmaterialIdxTooBig(sm.index)));
+        }
+    }
+
+    Ok(())
+}
+
+
 impl NmfType {
     const FROM_OBJ: &'static [u8] = b"fromObj\0";
-    const B3DMH_10: &'static [u8] = b"B3DMH\010";
+    const B3DMH_10: &'static [u8] = b"B3DMH\x0010";
 
     fn from_slice(bytes: &[u8]) -> Option<NmfType> {
         match bytes {
@@ -290,6 +622,12 @@ impl NameBuf {
 
     const BUF_LENGTH: usize = 64;
 
+    // None if the name does not fit
+    pub fn new(name: &str) -> Option<NameBuf> {
+        let mut buf = NameBuf { bytes: [0; Self::BUF_LENGTH], displayed: 0 };
+        if buf.push_str(name) { Some(buf) } else { None }
+    }
+
     fn from_reader<R: Read>(rdr: &mut R) -> Result<NameBuf, io::Error> {
         let mut name = NameBuf {
             bytes: [0; Self::BUF_LENGTH],
@@ -309,17 +647,18 @@ impl NameBuf {
         } else {
             (&mut self.bytes[..]).write_all(new_bytes).unwrap();
             self.displayed = len;
-            (&mut self.bytes[len..]).fill(0u8);
+            self.bytes[len..].fill(0u8);
             true
         }
     }
 
-    pub fn as_str<'a>(&'a self) -> &'a str {
+    pub fn as_str(&self) -> &str {
+        debug_assert!(self.displayed <= Self::BUF_LENGTH);
         if self.displayed > 0 {
             let s = unsafe { std::str::from_utf8_unchecked(self.bytes.get_unchecked(0 .. self.displayed)) };
-            &s
+            s
         } else {
-            &"<not displayable>"
+            "<not displayable>"
         }
     }
 
@@ -343,20 +682,28 @@ impl AsRef<str> for NameBuf {
 
 
 #[inline]
-const fn indices_len_bytes(indices: u32) -> u32 {
-    indices * 2
+const fn indices_len_bytes(indices: u32) -> u64 {
+    indices as u64 * 2
 }
 
+// u64: counts read from a corrupt file must not overflow
 #[inline]
-const fn geometry_len_words(vertices: u32, faces: u32) -> u32 {
-    vertices * (3 + 9 + 2) + faces * (4 + 6)
+const fn geometry_len_words(vertices: u32, faces: u32) -> u64 {
+    vertices as u64 * (3 + 9 + 2) + faces as u64 * (4 + 6)
 }
 
 #[inline]
-const fn geometry_len_bytes(vertices: u32, faces: u32) -> u32 {
+const fn geometry_len_bytes(vertices: u32, faces: u32) -> u64 {
     geometry_len_words(vertices, faces) * 4
 }
 
+// Length of an object in the file, from the counts in its header:
+// 260 (header) + geometry + 12 per submaterial entry
+fn object_len(vertices: u32, indices: u32, submats: u32) -> Result<u64, ObjectError> {
+    let faces = get_faces_count(indices)?;
+    Ok(260 + indices_len_bytes(indices) + geometry_len_bytes(vertices, faces) + submats as u64 * 12)
+}
+
 #[inline]
 fn get_faces_count(indices: u32) -> Result<u32, ObjectError> {
     let (c, rm) = num::integer::div_rem(indices, 3);
@@ -502,6 +849,55 @@ impl fmt::Display for NameBuf {
 }
 
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::FileIO(e)                 => write!(f, "File IO error: {}", e),
+            Error::HeaderEOF(e)              => write!(f, "Unexpected end of header: {}", e),
+            Error::UnknownNmfType            => write!(f, "Unknown NMF type"),
+            Error::FileLengthMismatch(h, a)  => write!(f, "File length mismatch: header says {} bytes, actual length is {}", h, a),
+            Error::CountsExceedLength(s, o, l) => write!(f, "Header declares {} submaterials and {} objects, which cannot fit into {} bytes", s, o, l),
+            Error::ZeroSubmaterials          => write!(f, "NMF has zero submaterials"),
+            Error::Submaterial(i, e)         => write!(f, "Cannot read submaterial {}: {}", i, e),
+            Error::SubmaterialName(i, l)     => write!(f, "Submaterial {} name is {} bytes long, at most {} fit", i, l, NameBuf::BUF_LENGTH),
+            Error::ZeroObjects               => write!(f, "NMF has zero objects"),
+            Error::Object(i, e)              => write!(f, "Cannot read object {}: {}", i, e),
+            Error::U32Conversion(e)          => write!(f, "Value does not fit into u32: {}", e),
+            Error::WriteObject(i, e)         => write!(f, "Cannot write object {}: {}", i, e),
+        }
+    }
+}
+
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            ObjectError::FileIO(e)               => write!(f, "File IO error: {}", e),
+            ObjectError::SliceReadU32            => write!(f, "Cannot read u32 from slice"),
+            ObjectError::WrongIndicesCount(i)    => write!(f, "Indices count ({}) is not a multiple of 3", i),
+            ObjectError::ZeroSubmaterials        => write!(f, "Object has zero submaterials"),
+            ObjectError::SubmaterialIdxTooBig(i) => write!(f, "Submaterial index {} is out of range", i),
+            ObjectError::Allocation(e)           => write!(f, "Allocation failed: {}", e),
+            ObjectError::NonFiniteVertex(i)      => write!(f, "Vertex {} is not finite (out of f32 range)", i),
+            ObjectError::FaceIndexOutOfRange(f_idx, v) => write!(f, "Face {} references vertex {}, which is out of range", f_idx, v),
+            ObjectError::SizeMismatch(d, c)      => write!(f, "Declared object size {} is smaller than its vertex data ({} bytes)", d, c),
+            ObjectError::ExceedsData(n, l)       => write!(f, "Object counts need {} bytes, but only {} bytes of data are left", n, l),
+            ObjectError::NameTooLong(l)          => write!(f, "Object name is {} bytes long, at most {} fit", l, NameBuf::BUF_LENGTH),
+            ObjectError::EmptyName               => write!(f, "Object name is empty"),
+            ObjectError::CountMismatch(what, n, exp) => write!(f, "Object has {} {}, expected {}", n, what, exp),
+            ObjectError::TooManyVertices(n)      => write!(f, "Object has {} vertices, but u16 face indices can address at most {}. Split the object into smaller parts", n, u16::MAX),
+        }
+    }
+}
+
+
+impl fmt::Display for ChopEOF {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "need {} bytes, have {}", self.need, self.have)
+    }
+}
+
+
 impl fmt::Display for NmfBuf<ObjectFull> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
 
@@ -524,3 +920,231 @@ impl fmt::Display for NmfBuf<ObjectFull> {
         Ok(())
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn names<T>(nmf: &NmfBuf<T>) -> Vec<&str> {
+        nmf.submaterials.iter().map(|sm| sm.as_str()).collect()
+    }
+
+    #[test]
+    fn normalize_submaterials_orders_by_first_use_and_is_idempotent() {
+        let quad = [(0., 0., 0.), (1., 0., 0.), (1., 0., 1.), (0., 0., 1.)];
+        let a = test_util::object("a", &quad, &[(0, 2, 1), (0, 3, 2)], &[(2, 1), (1, 1)]);
+        let b = test_util::object("b", &quad, &[(0, 2, 1)], &[(1, 1)]);
+        let mut nmf = NmfBufFull::new(NmfType::B3dmh10, &["unused", "second", "first"], vec![a, b]).unwrap();
+
+        nmf.normalize_submaterials().unwrap();
+        assert_eq!(names(&nmf), vec!["first", "second", "unused"]);
+        assert_eq!(nmf.objects[0].submaterials().iter().map(|sm| sm.index).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(nmf.objects[1].submaterials()[0].index, 1);
+
+        let once = test_util::nmf_bytes(&nmf);
+        nmf.normalize_submaterials().unwrap();
+        assert_eq!(test_util::nmf_bytes(&nmf), once);
+    }
+
+    #[test]
+    fn normalize_submaterials_rejects_bad_index() {
+        let mut nmf = test_util::sample_nmf();
+        let bad = test_util::object("bad", &[(0., 0., 0.), (1., 0., 0.), (0., 0., 1.)], &[(0, 2, 1)], &[(7, 1)]);
+        nmf.objects.push(bad);
+        let before = names(&nmf).into_iter().map(String::from).collect::<Vec<_>>();
+
+        assert!(matches!(nmf.normalize_submaterials(), Err(Error::Object(2, ObjectError::SubmaterialIdxTooBig(7)))));
+        assert_eq!(names(&nmf), before);
+    }
+
+    #[test]
+    fn from_bytes_matches_from_path() {
+        let dir = test_util::ScratchDir::new("nmf-from-bytes");
+        let bytes = test_util::nmf_bytes(&test_util::sample_nmf());
+        let path = dir.write("model.nmf", &bytes);
+
+        let info_path = NmfInfo::from_path(&path).unwrap();
+        let info_bytes = NmfInfo::from_bytes(&bytes).unwrap();
+        assert_eq!(info_bytes.to_string(), info_path.to_string());
+        assert_eq!(info_bytes.objects.iter().map(|o| o.vertices()).collect::<Vec<_>>(), vec![4, 3]);
+
+        let full_path = NmfBufFull::from_path(&path).unwrap();
+        let full_bytes = NmfBufFull::from_bytes(&bytes).unwrap();
+        assert_eq!(test_util::nmf_bytes(&full_bytes), test_util::nmf_bytes(&full_path));
+        assert_eq!(test_util::nmf_bytes(&full_bytes), bytes);
+    }
+
+    #[test]
+    fn empty_objects_lists_objects_without_geometry() {
+        let mut nmf = test_util::sample_nmf();
+        nmf.objects.insert(1, test_util::object("empty", &[], &[], &[(0, 0)]));
+        let info = NmfInfo::from_bytes(&test_util::nmf_bytes(&nmf)).unwrap();
+
+        assert_eq!(info.empty_objects().map(|o| o.name.as_str()).collect::<Vec<_>>(), vec!["empty"]);
+    }
+
+    #[test]
+    fn compare_mirrored_twice_is_identical() {
+        let info = |nmf: &NmfBufFull| NmfInfo::from_bytes(&test_util::nmf_bytes(nmf)).unwrap();
+        let original = info(&test_util::sample_nmf());
+
+        let mut nmf = test_util::sample_nmf();
+        for _ in 0..2 {
+            nmf.objects.iter_mut().for_each(|o| o.mirror_z());
+        }
+        assert!(original.compare(&info(&nmf)).is_empty());
+
+        nmf.objects.pop();
+        assert_eq!(original.compare(&info(&nmf)), vec!["Objects count: 2 vs 1", "Object 'door_1': missing in the second nmf"]);
+    }
+
+    // Corrupt files must be reported as errors: no panics, no huge allocations
+    #[test]
+    fn truncated_and_garbage_headers_are_errors() {
+        let bytes = test_util::nmf_bytes(&test_util::sample_nmf());
+
+        for len in 0 .. bytes.len() {
+            let mut cut = bytes[.. len].to_vec();
+            assert!(NmfInfo::from_bytes(&cut).is_err(), "length {}", len);
+            // same, with the header length patched to match, so that parsing goes past the header
+            if len >= 20 {
+                cut[16 .. 20].copy_from_slice(&(len as u32).to_le_bytes());
+                assert!(NmfInfo::from_bytes(&cut).is_err(), "patched length {}", len);
+                assert!(NmfBufFull::from_bytes(&cut).is_err(), "patched length {}", len);
+            }
+        }
+
+        assert!(matches!(NmfInfo::from_bytes(&[0xAB; 64]), Err(Error::UnknownNmfType)));
+
+        let mut huge = bytes.clone();
+        huge[8 .. 16].copy_from_slice(&[0xFF; 8]);
+        assert!(matches!(NmfInfo::from_bytes(&huge), Err(Error::CountsExceedLength(..))));
+        assert!(matches!(NmfBufFull::from_bytes(&huge), Err(Error::CountsExceedLength(..))));
+
+        let mut nmf = test_util::sample_nmf();
+        nmf.objects[1].faces_mut()[0].v3 = 3;
+        assert!(matches!(NmfBufFull::from_bytes(&test_util::nmf_bytes(&nmf)), Err(Error::Object(1, ObjectError::FaceIndexOutOfRange(0, 3)))));
+    }
+
+    #[test]
+    fn parallel_load_matches_sequential() {
+        let dir = test_util::ScratchDir::new("nmf-parallel");
+        let quad = [(0., 0., 0.), (1., 0., 0.), (1., 0., 1.), (0., 0., 1.)];
+        // more objects than threads, so that every thread gets a chunk of several objects
+        let objects = (0 .. 64).map(|i| {
+            let verts = quad.iter().map(|&(x, y, z)| (x + i as f32, y, z)).collect::<Vec<_>>();
+            test_util::object(&format!("obj_{}", i), &verts, &[(0, 2, 1), (0, 3, 2)], &[(i % 2, 2)])
+        }).collect();
+        let nmf = NmfBufFull::new(NmfType::B3dmh10, &["mat_a", "mat_b"], objects).unwrap();
+        let path = dir.write("model.nmf", test_util::nmf_bytes(&nmf));
+
+        let sequential = test_util::nmf_bytes(&NmfBufFull::from_path(&path).unwrap());
+        let parallel = test_util::nmf_bytes(&NmfBufFull::from_path_parallel(&path).unwrap());
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn nmf_type_matches_header() {
+        for &t in [NmfType::FromObj, NmfType::B3dmh10].iter() {
+            let sample = test_util::sample_nmf();
+            let nmf = NmfBufFull::new(t, &["mat_a", "mat_b"], sample.objects).unwrap();
+            let bytes = test_util::nmf_bytes(&nmf);
+
+            assert_eq!(NmfBufFull::from_bytes(&bytes).unwrap().nmf_type(), t);
+            assert_eq!(NmfInfo::from_bytes(&bytes).unwrap().nmf_type(), t);
+        }
+    }
+
+    // The header-only reader (ObjectInfo) and the full reader (ObjectFull) must see the same structure
+    #[test]
+    fn info_and_full_readers_agree() {
+        let mut with_empty = test_util::sample_nmf();
+        with_empty.objects.push(test_util::object("empty", &[], &[], &[(0, 0)]));
+        let quad = [(0., 0., 0.), (1., 0., 0.), (1., 0., 1.), (0., 0., 1.)];
+        let from_obj = NmfBufFull::new(NmfType::FromObj, &["x", "y", "z"], vec![
+            test_util::object("a", &quad, &[(0, 2, 1), (0, 3, 2)], &[(2, 1), (0, 1)]),
+            test_util::object("b", &quad[.. 3], &[(0, 2, 1)], &[(1, 1)]),
+        ]).unwrap();
+
+        for nmf in [test_util::sample_nmf(), with_empty, from_obj].iter() {
+            let bytes = test_util::nmf_bytes(nmf);
+            let info = NmfInfo::from_bytes(&bytes).unwrap();
+            let full = NmfBufFull::from_bytes(&bytes).unwrap();
+
+            assert_eq!(info.nmf_type(), full.nmf_type());
+            assert_eq!(names(&info), names(&full));
+            assert_eq!(info.remainder(), full.remainder());
+            assert_eq!(info.objects.len(), full.objects.len());
+            for (i, f) in info.objects.iter().zip(full.objects.iter()) {
+                assert_eq!(i.name.as_str(), f.name());
+                assert_eq!(i.vertices as usize, f.vertices().len());
+                assert_eq!(i.faces as usize, f.faces().len());
+                let sms = f.submaterials().iter().map(|sm| sm.index).collect::<Vec<_>>();
+                assert_eq!(std::iter::once(i.submat_main).chain(i.submat_rest.iter().copied()).collect::<Vec<_>>(), sms);
+            }
+        }
+    }
+
+    // Counts in an object header are checked against the data left before anything is allocated
+    #[test]
+    fn huge_object_counts_are_errors() {
+        let bytes = test_util::nmf_bytes(&test_util::sample_nmf());
+        let door = NmfInfo::from_bytes(&bytes).unwrap().objects[1].byte_range().start as usize;
+        let patched = |fields: &[(usize, u32)]| {
+            let mut b = bytes.clone();
+            for &(offset, value) in fields {
+                b[door + offset .. door + offset + 4].copy_from_slice(&value.to_le_bytes());
+            }
+            b
+        };
+
+        // 64M vertices (with both size fields raised to cover them), 60M indices, 4G submaterials
+        let cases = [patched(&[(236, 0x0400_0000), (4, u32::MAX), (232, u32::MAX)]),
+                     patched(&[(240, 60_000_000)]),
+                     patched(&[(244, u32::MAX)])];
+        for (i, b) in cases.iter().enumerate() {
+            assert!(matches!(NmfInfo::from_bytes(b), Err(Error::Object(1, ObjectError::ExceedsData(..)))), "case {}", i);
+            assert!(matches!(NmfBufFull::from_bytes(b), Err(Error::Object(1, ObjectError::ExceedsData(..)))), "case {}", i);
+        }
+    }
+
+    // Wrong header length, an object with an out-of-range face, a stale bbox and junk after the last object
+    #[test]
+    fn repair_salvages_damaged_file() {
+        let mut nmf = test_util::sample_nmf();
+        let mut bad = test_util::object("bad", &[(0., 0., 0.), (1., 0., 0.), (0., 1., 0.)], &[(0, 1, 2)], &[(0, 1)]);
+        bad.faces_mut()[0].v3 = 7;
+        nmf.objects.insert(1, bad);
+        nmf.objects[2].face_bboxes_mut()[0].v_max.y = 100.;
+        let mut bytes = test_util::nmf_bytes(&nmf);
+        bytes.extend_from_slice(&[0xAB; 12]);
+        assert!(NmfBufFull::from_bytes(&bytes).is_err());
+
+        let (repaired, changes) = NmfBufFull::repair(&bytes).unwrap();
+        assert_eq!(repaired.objects.iter().map(|o| o.name()).collect::<Vec<_>>(), ["main", "door_1"]);
+        assert_eq!(changes.len(), 4, "{:?}", changes);
+        assert!(changes[0].starts_with("header length"), "{:?}", changes);
+        assert!(changes.iter().any(|c| c.starts_with("dropped object 1 'bad'")), "{:?}", changes);
+        assert!(changes.contains(&"removed 12 leftover bytes after the last object".to_string()), "{:?}", changes);
+        assert!(changes.contains(&"recalculated bounding boxes of object 'door_1'".to_string()), "{:?}", changes);
+
+        let written = test_util::nmf_bytes(&repaired);
+        assert_eq!(written, test_util::nmf_bytes(&test_util::sample_nmf()));
+        assert!(NmfBufFull::from_bytes(&written).is_ok());
+    }
+
+    #[test]
+    fn check_warns_about_huge_uv() {
+        let mut nmf = test_util::sample_nmf();
+        // tiling a few times over is fine
+        nmf.objects[0].uv_map_mut()[2].x = 8.;
+        assert!(nmf.check(16.).is_empty());
+
+        nmf.objects[1].uv_map_mut()[1].y = -1000.;
+        nmf.objects[1].uv_map_mut()[2].x = f32::NAN;
+        assert_eq!(nmf.check(16.), ["object 'door_1': 2 of 3 UV points are outside ±16 (furthest: 1000)"]);
+        assert_eq!(nmf.check(4.).len(), 2);
+    }
+}
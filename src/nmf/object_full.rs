@@ -1,11 +1,10 @@
 use std::mem::size_of;
-use std::alloc;
-use std::io::{Write, Read, Seek};
+use std::io::{Write, Read, Seek, Cursor};
 use std::convert::TryInto;
 use core::ops::Range;
 
 
-use super::{ObjectError, ObjectReader, NameBuf};
+use super::{ObjectError, ObjectReader, NameBuf, geometry_len_bytes};
 
 
 
@@ -14,8 +13,11 @@ pub struct ObjectFull {
     head_buf: [u8; 260],
     range_name: Option<Range<usize>>,
 
+    // owns the object data; buf_ptr is its first 4-aligned byte, followed by buf_len bytes.
+    // buf is never touched again after reading, so buf_ptr stays valid when the object moves
+    buf: Vec<u8>,
     buf_ptr: *mut u8,
-    buf_layout: alloc::Layout,
+    buf_len: usize,
 
     vertices_count: usize,
     indices_count:  usize,
@@ -33,6 +35,12 @@ pub struct ObjectFull {
 }
 
 
+pub enum NormalsMode {
+    Flat,
+    Smooth
+}
+
+
 #[repr(C)]
 pub struct RawFace {
     pub v1: u16,
@@ -65,12 +73,35 @@ pub struct RawFaceExtra {
 }
 
 
+// Faces drawn with a submaterial: a run of the index buffer
+#[repr(C)]
+pub struct RawSubmaterial {
+    pub first_index: u32,
+    pub indices_count: u32,
+    pub index: u32
+}
+
+
 #[repr(C)]
+#[derive(Clone, PartialEq)]
 pub struct RawBBox {
     pub v_min: RawVertex,
     pub v_max: RawVertex,
 }
 
+
+// Geometry of an object built from scratch with ObjectFull::build.
+// normals and uv_map have one entry per vertex. submaterials are consecutive runs of faces,
+// in face order: (submaterial index, faces count).
+pub struct ObjectParts<'a> {
+    pub name: &'a str,
+    pub vertices: &'a [RawVertex],
+    pub normals: &'a [RawVertex],
+    pub uv_map: &'a [RawPoint],
+    pub faces: &'a [RawFace],
+    pub submaterials: &'a [(u32, usize)],
+}
+
 /*
 #[repr(C)]
 struct SubmaterialUsage {
@@ -84,7 +115,7 @@ struct SubmaterialUsage {
 
 
 fn read_u32(bytes: &[u8]) -> Result<u32, ObjectError> {
-    let ar: [u8; 4] = bytes[0..4].try_into().map_err(|_| ObjectError::SliceReadU32)?;
+    let ar: [u8; 4] = bytes.get(0..4).and_then(|b| b.try_into().ok()).ok_or(ObjectError::SliceReadU32)?;
     Ok(u32::from_le_bytes(ar))
 }
 
@@ -95,7 +126,7 @@ fn read_u32size(bytes: &[u8]) -> Result<usize, ObjectError> {
 
 
 impl<R: Read + Seek> ObjectReader<R> for ObjectFull {
-    fn from_reader(rdr: &mut R, _max_sm_idx: usize) -> Result<ObjectFull, ObjectError> {
+    fn from_reader(rdr: &mut R, _max_sm_idx: usize, data_left: u64) -> Result<ObjectFull, ObjectError> {
 
         let mut head_buf = [0u8; 260];
         rdr.read_exact(&mut head_buf[..]).map_err(ObjectError::FileIO)?;
@@ -116,6 +147,15 @@ impl<R: Read + Seek> ObjectReader<R> for ObjectFull {
         let submat_count   = read_u32size(&head_buf[244..])?;
         let faces_count    = get_faces_count(indices_count)?;
 
+        // The exact meaning of the two size fields is unknown, but both include the vertex data
+        // (optimize_indices shrinks them by the bytes of removed vertices), so that is a lower bound
+        let vertex_bytes = vertices_count * (4 * size_of::<RawVertex>() + size_of::<RawPoint>());
+        for declared in [read_u32size(&head_buf[4..])?, read_u32size(&head_buf[232..])?] {
+            if declared < vertex_bytes {
+                return Err(ObjectError::SizeMismatch(declared, vertex_bytes));
+            }
+        }
+
         let indices_bytes = indices_count * size_of::<u16>();
 
         // possible round-up to 4 byte alignment for the following elements
@@ -129,60 +169,194 @@ impl<R: Read + Seek> ObjectReader<R> for ObjectFull {
         let submat_start      = face_bboxes_start + faces_count    * 24;
         let obj_end           = submat_start      + submat_count   * 12;
 
-        unsafe {
-            let buf_layout = alloc::Layout::from_size_align(obj_end, 4_usize).map_err(|e| ObjectError::Allocation(format!("{:?}", e)))?;
-            // TODO: without zeroed (MaybeUninit etc)
-            let buf_ptr = alloc::alloc_zeroed(buf_layout);
-            if buf_ptr.is_null() {
-                return Err(ObjectError::Allocation(String::from("Allocated zero pointer")));
+        // the padding after indices is not stored in the file
+        let obj_len = (260 + indices_bytes + obj_end - vertices_start) as u64;
+        if obj_len > data_left {
+            return Err(ObjectError::ExceedsData(obj_len, data_left));
+        }
+
+        // A Vec<u8> is only byte aligned, the typed slices need 4: the object starts at the first aligned byte.
+        // The spare capacity beyond the worst case padding keeps read_to_end from ever reallocating.
+        let mut buf = Vec::<u8>::new();
+        buf.try_reserve_exact(obj_end + 4).map_err(|e| ObjectError::Allocation(format!("{:?}", e)))?;
+        let buf_start = buf.as_ptr().align_offset(4);
+        if buf_start >= 4 {
+            return Err(ObjectError::Allocation(String::from("Cannot align object buffer")));
+        }
+        buf.resize(buf_start, 0);
+
+        // read_to_end fills the spare capacity without zeroing it first
+        let mut read_exact = |buf: &mut Vec<u8>, len: usize| {
+            match rdr.by_ref().take(len as u64).read_to_end(buf) {
+                Ok(n) if n == len => Ok(()),
+                Ok(_)  => Err(ObjectError::FileIO(std::io::ErrorKind::UnexpectedEof.into())),
+                Err(e) => Err(ObjectError::FileIO(e)),
             }
+        };
 
-            let s1 = std::slice::from_raw_parts_mut(buf_ptr, obj_end);
-            rdr.read_exact(&mut s1[0 .. indices_bytes]).map_err(ObjectError::FileIO)
-               .and_then(|_| rdr.read_exact(&mut s1[vertices_start .. obj_end]).map_err(ObjectError::FileIO))
-               .map_err(|e| {
-                    alloc::dealloc(buf_ptr, buf_layout);
-                    e
-               })?;
-
-            Ok(ObjectFull { head_buf,
-                            range_name,
-
-                            buf_ptr,
-                            buf_layout,
-
-                            vertices_count,
-                            indices_count,
-                            faces_count,
-                            submat_count,
-
-                            vertices_start,
-                            normals1_start,
-                            normals2_start,
-                            normals3_start,
-                            uv_map_start,
-                            face_ext_start,
-                            face_bboxes_start,
-                            submat_start,
-            })
+        read_exact(&mut buf, indices_bytes)?;
+        buf.resize(buf_start + vertices_start, 0);
+        read_exact(&mut buf, obj_end - vertices_start)?;
 
+        if buf.as_ptr().align_offset(4) != buf_start {
+            return Err(ObjectError::Allocation(String::from("Object buffer moved while reading")));
         }
 
-    }
-}
+        let buf_ptr = buf[buf_start ..].as_mut_ptr();
+
+        let obj = ObjectFull { head_buf,
+                        range_name,
+
+                        buf,
+                        buf_ptr,
+                        buf_len: obj_end,
 
+                        vertices_count,
+                        indices_count,
+                        faces_count,
+                        submat_count,
 
-impl Drop for ObjectFull {
-    fn drop(&mut self) {
-        unsafe { 
-            alloc::dealloc(self.buf_ptr, self.buf_layout);
+                        vertices_start,
+                        normals1_start,
+                        normals2_start,
+                        normals3_start,
+                        uv_map_start,
+                        face_ext_start,
+                        face_bboxes_start,
+                        submat_start,
+        };
+
+        // the rest of the code indexes vertices by face indices without further checks
+        if let Some((i, v)) = obj.faces().iter().enumerate().find_map(|(i, f)| {
+            [f.v1, f.v2, f.v3].iter().copied().find(|&v| v as usize >= vertices_count).map(|v| (i, v))
+        }) {
+            return Err(ObjectError::FaceIndexOutOfRange(i, v));
         }
+
+        Ok(obj)
+
     }
 }
 
 
+// buf_ptr points into buf, which is owned exclusively by this object
+unsafe impl Send for ObjectFull {}
+
+
 impl ObjectFull {
 
+    // Builds an object from its geometry. Header counts and sizes, face normals, bboxes and tangents
+    // are derived from it, the opaque header bytes are left zeroed. The result goes through
+    // the same checks as an object read from a file.
+    pub fn build(parts: &ObjectParts) -> Result<ObjectFull, ObjectError> {
+        let v_count = parts.vertices.len();
+        if v_count >= u16::MAX.into() {
+            return Err(ObjectError::TooManyVertices(v_count));
+        }
+
+        if parts.normals.len() != v_count {
+            return Err(ObjectError::CountMismatch("normals", parts.normals.len(), v_count));
+        }
+
+        if parts.uv_map.len() != v_count {
+            return Err(ObjectError::CountMismatch("UV points", parts.uv_map.len(), v_count));
+        }
+
+        if parts.submaterials.is_empty() {
+            return Err(ObjectError::ZeroSubmaterials);
+        }
+
+        let f_count = parts.faces.len();
+        let run_faces: usize = parts.submaterials.iter().map(|(_, n)| n).sum();
+        if run_faces != f_count {
+            return Err(ObjectError::CountMismatch("faces in submaterial runs", run_faces, f_count));
+        }
+
+        let name = parts.name.as_bytes();
+        if name.is_empty() {
+            return Err(ObjectError::EmptyName);
+        }
+
+        if name.len() > NameBuf::BUF_LENGTH {
+            return Err(ObjectError::NameTooLong(name.len()));
+        }
+
+        // Laid out as in the file. Tangents, face extras and face bboxes are zeroed here
+        // and calculated once the buffer is parsed.
+        let mut body = Vec::<u8>::new();
+        let put_f32s = |body: &mut Vec<u8>, xs: &[f32]| xs.iter().for_each(|x| body.extend_from_slice(&x.to_le_bytes()));
+
+        for f in parts.faces {
+            for i in [f.v1, f.v2, f.v3] {
+                body.extend_from_slice(&i.to_le_bytes());
+            }
+        }
+
+        for v in parts.vertices.iter().chain(parts.normals) {
+            put_f32s(&mut body, &[v.x, v.y, v.z]);
+        }
+
+        body.resize(body.len() + v_count * 2 * size_of::<RawVertex>(), 0u8);
+
+        for p in parts.uv_map {
+            put_f32s(&mut body, &[p.x, p.y]);
+        }
+
+        body.resize(body.len() + f_count * (size_of::<RawFaceExtra>() + size_of::<RawBBox>()), 0u8);
+
+        let mut first_face = 0;
+        for (sm_idx, n) in parts.submaterials {
+            for x in [first_face * 3, n * 3] {
+                body.extend_from_slice(&(x as u32).to_le_bytes());
+            }
+            body.extend_from_slice(&sm_idx.to_le_bytes());
+            first_face += n;
+        }
+
+        let mut head_buf = [0u8; 260];
+        head_buf[8 .. 8 + name.len()].copy_from_slice(name);
+        let header_u32s = [(4,   260 + body.len()),
+                           (232, geometry_len_bytes(v_count as u32, f_count as u32) as usize),
+                           (236, v_count),
+                           (240, f_count * 3),
+                           (244, parts.submaterials.len())];
+        for (offset, x) in header_u32s {
+            head_buf[offset .. offset + 4].copy_from_slice(&(x as u32).to_le_bytes());
+        }
+
+        let mut bytes = head_buf.to_vec();
+        bytes.append(&mut body);
+        let len = bytes.len() as u64;
+        let mut obj = ObjectFull::from_reader(&mut Cursor::new(bytes), 0, len)?;
+
+        obj.recalc_bboxes();
+
+        let extras = {
+            let verts = obj.vertices();
+            obj.faces().iter().map(|f| {
+                let p1 = &verts[f.v1 as usize];
+                let n = p1.to(&verts[f.v2 as usize]).cross(&p1.to(&verts[f.v3 as usize])).normalized();
+                let factor = -n.dot(p1);
+                RawFaceExtra { auto_normal: n, factor }
+            }).collect::<Vec<_>>()
+        };
+
+        for (dst, ext) in obj.face_extras_mut().iter_mut().zip(extras) {
+            *dst = ext;
+        }
+
+        let zero = RawVertex { x: 0f32, y: 0f32, z: 0f32 };
+        let tangents = obj.normals_1().iter().map(|n| tangent_frame(n, n, &zero, &zero)).collect::<Vec<_>>();
+        for (dst, (t, _)) in obj.normals_2_mut().iter_mut().zip(tangents.iter()) {
+            *dst = t.clone();
+        }
+        for (dst, (_, b)) in obj.normals_3_mut().iter_mut().zip(tangents.iter()) {
+            *dst = b.clone();
+        }
+
+        Ok(obj)
+    }
+
     pub fn write_bytes<W: Write>(&self, mut wr: W) -> Result<(), std::io::Error> {
         wr.write_all(&self.head_buf)?;
 
@@ -200,92 +374,214 @@ impl ObjectFull {
         let slice = self.get_slice::<u8>(self.uv_map_start,    self.vertices_count * size_of::<RawPoint>());
         wr.write_all(slice)?;
 
-        let slice = self.get_slice::<u8>(self.face_ext_start, self.buf_layout.size() - self.face_ext_start);
+        let slice = self.get_slice::<u8>(self.face_ext_start, self.buf_len - self.face_ext_start);
         wr.write_all(slice)
     }
 
     pub fn name(&self) -> &str {
         match &self.range_name {
             Some(rng) => unsafe { std::str::from_utf8_unchecked(self.head_buf.get_unchecked(rng.clone())) },
-            None => &"<not displayable>"
+            None => "<not displayable>"
         }
     }
 
-    fn bbox_mut<'a>(&'a mut self) -> &'a mut RawBBox {
-        unsafe {
-            let ptr = self.head_buf.as_mut_ptr().add(204).cast::<RawBBox>();
-            ptr.as_mut().unwrap()
+    // 132 header bytes between the name and the bbox. Their meaning is unknown, may hold a per-object transform.
+    pub fn header_opaque(&self) -> &[u8] {
+        &self.head_buf[72 .. 204]
+    }
+
+    // Returns false (nothing changed) if the name is empty, has a NUL byte or does not fit into the name field
+    pub fn set_name(&mut self, new_name: &str) -> bool {
+        let bytes = new_name.as_bytes();
+        if bytes.is_empty() || bytes.len() > NameBuf::BUF_LENGTH || bytes.contains(&0) {
+            return false;
         }
+
+        let name_start = 8;
+        let field = &mut self.head_buf[name_start .. name_start + NameBuf::BUF_LENGTH];
+        field[.. bytes.len()].copy_from_slice(bytes);
+        field[bytes.len() ..].fill(0);
+        self.range_name = Some(name_start .. name_start + bytes.len());
+        true
+    }
+
+    // Object bbox is stored in head_buf at offset 204. head_buf is a plain byte array with no alignment
+    // guarantee, so the floats are decoded and encoded byte-wise instead of casting the pointer.
+    fn bbox(&self) -> RawBBox {
+        let f = |i: usize| f32::from_le_bytes(self.head_buf[204 + i * 4 .. 208 + i * 4].try_into().unwrap());
+        RawBBox { v_min: RawVertex { x: f(0), y: f(1), z: f(2) },
+                  v_max: RawVertex { x: f(3), y: f(4), z: f(5) } }
     }
 
-    fn get_slice<'a, T>(&'a self, offset: usize, count: usize) -> &'a [T] {
+    fn set_bbox(&mut self, bbox: &RawBBox) {
+        let RawBBox { v_min, v_max } = bbox;
+        for (i, x) in [v_min.x, v_min.y, v_min.z, v_max.x, v_max.y, v_max.z].iter().enumerate() {
+            self.head_buf[204 + i * 4 .. 208 + i * 4].copy_from_slice(&x.to_le_bytes());
+        }
+    }
+
+    fn update_bbox<F: FnOnce(&mut RawBBox)>(&mut self, f: F) {
+        let mut bbox = self.bbox();
+        f(&mut bbox);
+        self.set_bbox(&bbox);
+    }
+
+    fn get_slice<T>(&self, offset: usize, count: usize) -> &[T] {
+        debug_assert!(offset + count * size_of::<T>() <= self.buf_len);
         unsafe {
             let ptr = (self.buf_ptr as *const u8).add(offset).cast::<T>();
             std::slice::from_raw_parts(ptr, count)
         }
     }
 
-    fn get_slice_mut<'a, T>(&'a mut self, offset: usize, count: usize) -> &'a mut [T] {
+    fn get_slice_mut<T>(&mut self, offset: usize, count: usize) -> &mut [T] {
+        debug_assert!(offset + count * size_of::<T>() <= self.buf_len);
         unsafe {
             let ptr = self.buf_ptr.add(offset).cast::<T>();
             std::slice::from_raw_parts_mut(ptr, count)
         }
     }
 
-    pub fn faces<'a>(&'a self) -> &'a [RawFace] {
+    pub fn faces(&self) -> &[RawFace] {
         self.get_slice::<RawFace>(0, self.faces_count)
     }
 
-    pub fn faces_mut<'a>(&'a mut self) -> &'a mut [RawFace] {
+    pub fn faces_mut(&mut self) -> &mut [RawFace] {
         self.get_slice_mut::<RawFace>(0, self.faces_count)
     }
 
-    pub fn vertices<'a>(&'a self) -> &'a [RawVertex] {
+    pub fn vertices(&self) -> &[RawVertex] {
         self.get_slice::<RawVertex>(self.vertices_start, self.vertices_count)
     }
 
-    pub fn vertices_mut<'a>(&'a mut self) -> &'a mut [RawVertex] {
+    pub fn vertices_mut(&mut self) -> &mut [RawVertex] {
         self.get_slice_mut::<RawVertex>(self.vertices_start, self.vertices_count)
     }
 
-    pub fn normals_1<'a>(&'a self) -> &'a [RawVertex] {
+    pub fn normals_1(&self) -> &[RawVertex] {
         self.get_slice::<RawVertex>(self.normals1_start, self.vertices_count)
     }
 
-    pub fn normals_1_mut<'a>(&'a mut self) -> &'a mut [RawVertex] {
+    pub fn normals_1_mut(&mut self) -> &mut [RawVertex] {
         self.get_slice_mut::<RawVertex>(self.normals1_start, self.vertices_count)
     }
 
-    //pub fn normals_2<'a>(&'a self) -> &'a [RawVertex] {
-        //self.get_slice::<RawVertex>(self.normals2_start, self.vertices_count)
-    //}
+    pub fn normals_2(&self) -> &[RawVertex] {
+        self.get_slice::<RawVertex>(self.normals2_start, self.vertices_count)
+    }
   
-    pub fn normals_2_mut<'a>(&'a mut self) -> &'a mut [RawVertex] {
+    pub fn normals_2_mut(&mut self) -> &mut [RawVertex] {
         self.get_slice_mut::<RawVertex>(self.normals2_start, self.vertices_count)
     }
 
-    //pub fn normals_3<'a>(&'a self) -> &'a [RawVertex] {
-        //self.get_slice::<RawVertex>(self.normals3_start, self.vertices_count)
-    //}
+    pub fn normals_3(&self) -> &[RawVertex] {
+        self.get_slice::<RawVertex>(self.normals3_start, self.vertices_count)
+    }
 
-    pub fn normals_3_mut<'a>(&'a mut self) -> &'a mut [RawVertex] {
+    pub fn normals_3_mut(&mut self) -> &mut [RawVertex] {
         self.get_slice_mut::<RawVertex>(self.normals3_start, self.vertices_count)
     }
 
-    pub fn uv_map<'a>(&'a self) -> &'a [RawPoint] {
+    pub fn uv_map(&self) -> &[RawPoint] {
         self.get_slice::<RawPoint>(self.uv_map_start, self.vertices_count)
     }
 
-    pub fn face_extras_mut<'a>(&'a mut self) -> &'a mut [RawFaceExtra] {
+    pub fn uv_map_mut(&mut self) -> &mut [RawPoint] {
+        self.get_slice_mut::<RawPoint>(self.uv_map_start, self.vertices_count)
+    }
+
+    pub fn face_extras(&self) -> &[RawFaceExtra] {
+        self.get_slice::<RawFaceExtra>(self.face_ext_start, self.faces_count)
+    }
+
+    pub fn face_extras_mut(&mut self) -> &mut [RawFaceExtra] {
         self.get_slice_mut::<RawFaceExtra>(self.face_ext_start, self.faces_count)
     }
 
-    pub fn face_bboxes_mut<'a>(&'a mut self) -> &'a mut [RawBBox] {
+    pub fn face_bboxes_mut(&mut self) -> &mut [RawBBox] {
         self.get_slice_mut::<RawBBox>(self.face_bboxes_start, self.faces_count)
     }
 
-    pub fn scale(&mut self, scale_factor: f64) {
-        self.bbox_mut().scale(scale_factor);
+    pub fn submaterials(&self) -> &[RawSubmaterial] {
+        self.get_slice::<RawSubmaterial>(self.submat_start, self.submat_count)
+    }
+
+    pub fn submaterials_mut(&mut self) -> &mut [RawSubmaterial] {
+        self.get_slice_mut::<RawSubmaterial>(self.submat_start, self.submat_count)
+    }
+
+    // (submaterial index, faces range) for every submaterial entry.
+    // None if the entries do not describe whole faces within the object.
+    pub fn submaterial_faces(&self) -> Option<Vec<(u32, Range<usize>)>> {
+        self.submaterials().iter().map(|sm| {
+            let (first, count) = (sm.first_index as usize, sm.indices_count as usize);
+            if first % 3 != 0 || count % 3 != 0 || first + count > self.indices_count {
+                None
+            } else {
+                Some((sm.index, first / 3 .. (first + count) / 3))
+            }
+        }).collect()
+    }
+
+    // Recomputes the object bbox and per-face bboxes from vertices. Returns true if any of them changed.
+    // Objects without vertices are left as is.
+    pub fn recalc_bboxes(&mut self) -> bool {
+        if self.vertices_count == 0 {
+            return false;
+        }
+
+        let bbox_of = |pts: &mut dyn Iterator<Item = &RawVertex>| -> RawBBox {
+            let first = pts.next().unwrap().clone();
+            pts.fold(RawBBox { v_min: first.clone(), v_max: first }, |mut b, p| {
+                b.v_min = RawVertex { x: b.v_min.x.min(p.x), y: b.v_min.y.min(p.y), z: b.v_min.z.min(p.z) };
+                b.v_max = RawVertex { x: b.v_max.x.max(p.x), y: b.v_max.y.max(p.y), z: b.v_max.z.max(p.z) };
+                b
+            })
+        };
+
+        let verts = self.vertices();
+        let obj_bbox = bbox_of(&mut verts.iter());
+        let face_bboxes: Vec<RawBBox> = self.faces().iter()
+            .map(|f| bbox_of(&mut [f.v1, f.v2, f.v3].iter().map(|i| &verts[*i as usize])))
+            .collect();
+
+        let mut changed = false;
+        if self.bbox() != obj_bbox {
+            self.set_bbox(&obj_bbox);
+            changed = true;
+        }
+
+        for (dst, b) in self.face_bboxes_mut().iter_mut().zip(face_bboxes) {
+            if *dst != b {
+                *dst = b;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    // Area of every face, in file order
+    pub fn face_areas(&self) -> Vec<f32> {
+        let verts = self.vertices();
+        self.faces().iter().map(|f| {
+            let p1 = &verts[f.v1 as usize];
+            let n = p1.to(&verts[f.v2 as usize]).cross(&p1.to(&verts[f.v3 as usize]));
+            n.dot(&n).sqrt() / 2f32
+        }).collect()
+    }
+
+    // uv = uv * scale + offset. Geometry is not touched
+    pub fn transform_uv(&mut self, scale_u: f32, scale_v: f32, offset_u: f32, offset_v: f32) {
+        for RawPoint { x: u, y: v } in self.uv_map_mut() {
+            *u = *u * scale_u + offset_u;
+            *v = *v * scale_v + offset_v;
+        }
+    }
+
+    // Fails if any scaled vertex does not fit into f32 (the object is left modified anyway)
+    pub fn scale(&mut self, scale_factor: f64) -> Result<(), ObjectError> {
+        self.update_bbox(|b| b.scale(scale_factor));
 
         for v in self.vertices_mut() {
             v.scale(scale_factor);
@@ -298,10 +594,15 @@ impl ObjectFull {
         for bbox in self.face_bboxes_mut() {
             bbox.scale(scale_factor); 
         }
+
+        match self.vertices().iter().position(|v| !(v.x.is_finite() && v.y.is_finite() && v.z.is_finite())) {
+            Some(i) => Err(ObjectError::NonFiniteVertex(i)),
+            None => Ok(())
+        }
     }
 
     pub fn offset(&mut self, dx: f32, dy: f32, dz: f32) {
-        self.bbox_mut().offset(dx, dy, dz);
+        self.update_bbox(|b| b.offset(dx, dy, dz));
 
         for v in self.vertices_mut() {
             v.offset(dx, dy, dz);
@@ -317,7 +618,7 @@ impl ObjectFull {
     }
 
     pub fn mirror_z(&mut self) {
-        self.bbox_mut().mirror_z();
+        self.update_bbox(RawBBox::mirror_z);
 
         for f in self.faces_mut() {
             f.reverse();
@@ -349,9 +650,72 @@ impl ObjectFull {
     }
 
 
-    pub fn optimize_indices(&mut self) {
+    // Recalculates normals (normals_1) from the faces geometry. Tangents (normals_2, normals_3) are
+    // re-orthogonalized to the new normals, keeping their direction and handedness as much as possible.
+    // Vertices are not split: in flat mode a vertex shared by faces with different normals gets
+    // the average of them. Returns the number of such vertices.
+    pub fn recalc_normals(&mut self, mode: &NormalsMode) -> usize {
+        let verts = self.vertices();
+
+        // face normals (not normalized, so that bigger faces have more weight when averaging);
+        // winding is checked against the face normals stored in the file
+        let face_normals = self.faces().iter().zip(self.face_extras()).map(|(f, ext)| {
+            let p1 = &verts[f.v1 as usize];
+            let n = p1.to(&verts[f.v2 as usize]).cross(&p1.to(&verts[f.v3 as usize]));
+            if n.dot(&ext.auto_normal) < 0f32 { n.scaled(-1f32) } else { n }
+        }).collect::<Vec<_>>();
+
+        let mut sums = vec![RawVertex { x: 0f32, y: 0f32, z: 0f32 }; self.vertices_count];
+        let mut firsts: Vec<Option<RawVertex>> = vec![None; self.vertices_count];
+        let mut shared = vec![false; self.vertices_count];
+
+        for (f, n) in self.faces().iter().zip(face_normals.iter()) {
+            for &v in [f.v1, f.v2, f.v3].iter() {
+                let v = v as usize;
+                sums[v] = sums[v].plus(n);
+                match &firsts[v] {
+                    None => firsts[v] = Some(n.normalized()),
+                    Some(first) => if first.dot(&n.normalized()) < 0.999f32 { shared[v] = true; }
+                }
+            }
+        }
 
-        assert!(self.vertices_count < u16::MAX.into());
+        let new_normals = firsts.iter().zip(sums.iter()).zip(shared.iter()).zip(self.normals_1())
+            .map(|(((first, sum), is_shared), old)| match (first, mode) {
+                // vertex is not used by any face
+                (None, _)                  => old.clone(),
+                (Some(n), NormalsMode::Flat) if !is_shared => n.clone(),
+                _                          => sum.normalized(),
+            })
+            .collect::<Vec<_>>();
+
+        let new_tangents = new_normals.iter()
+            .zip(self.normals_1().iter().zip(self.normals_2()).zip(self.normals_3()))
+            .map(|(n, ((n_old, t_old), b_old))| tangent_frame(n, n_old, t_old, b_old))
+            .collect::<Vec<_>>();
+
+        let shared_count = shared.iter().filter(|x| **x).count();
+
+        self.normals_1_mut().clone_from_slice(&new_normals[..]);
+        for (dst, (t, _)) in self.normals_2_mut().iter_mut().zip(new_tangents.iter()) {
+            *dst = t.clone();
+        }
+        for (dst, (_, b)) in self.normals_3_mut().iter_mut().zip(new_tangents.iter()) {
+            *dst = b.clone();
+        }
+
+        match mode {
+            NormalsMode::Flat => shared_count,
+            NormalsMode::Smooth => 0,
+        }
+    }
+
+
+    pub fn optimize_indices(&mut self) -> Result<(), ObjectError> {
+
+        if self.vertices_count >= u16::MAX.into() {
+            return Err(ObjectError::TooManyVertices(self.vertices_count));
+        }
 
         let vx_count = self.vertices_count as u16;
 
@@ -430,6 +794,8 @@ impl ObjectFull {
 
             }
         }
+
+        Ok(())
     }
 }
 
@@ -459,6 +825,42 @@ impl RawVertex {
     fn mirror_z(&mut self) {
         self.z = 0f32 - self.z;
     }
+
+    #[inline]
+    fn to(&self, other: &RawVertex) -> RawVertex {
+        RawVertex { x: other.x - self.x, y: other.y - self.y, z: other.z - self.z }
+    }
+
+    #[inline]
+    fn plus(&self, other: &RawVertex) -> RawVertex {
+        RawVertex { x: self.x + other.x, y: self.y + other.y, z: self.z + other.z }
+    }
+
+    #[inline]
+    fn scaled(&self, k: f32) -> RawVertex {
+        RawVertex { x: self.x * k, y: self.y * k, z: self.z * k }
+    }
+
+    #[inline]
+    fn dot(&self, other: &RawVertex) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    #[inline]
+    fn cross(&self, other: &RawVertex) -> RawVertex {
+        RawVertex { 
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x
+        }
+    }
+
+    // Zero vector stays zero
+    #[inline]
+    fn normalized(&self) -> RawVertex {
+        let len = self.dot(self).sqrt();
+        if len > 0f32 { self.scaled(1f32 / len) } else { self.clone() }
+    }
 }
 
 impl RawBBox {
@@ -486,6 +888,22 @@ impl RawBBox {
 
 
 
+// Tangent and bitangent for normal n: the old tangent made perpendicular to n,
+// with the handedness of the old (n_old, t_old, b_old) frame
+fn tangent_frame(n: &RawVertex, n_old: &RawVertex, t_old: &RawVertex, b_old: &RawVertex) -> (RawVertex, RawVertex) {
+    let handedness = if n_old.cross(t_old).dot(b_old) < 0f32 { -1f32 } else { 1f32 };
+    let mut t = t_old.plus(&n.scaled(-n.dot(t_old)));
+    if t.dot(&t) < 1e-12 {
+        // old tangent is parallel to the new normal (or zero), take any perpendicular vector
+        let axis = if n.x.abs() < 0.9f32 { RawVertex { x: 1f32, y: 0f32, z: 0f32 } } else { RawVertex { x: 0f32, y: 1f32, z: 0f32 } };
+        t = axis.plus(&n.scaled(-n.dot(&axis)));
+    }
+    let t = t.normalized();
+    let b = n.cross(&t).scaled(handedness);
+    (t, b)
+}
+
+
 #[inline]
 fn get_faces_count(indices: usize) -> Result<usize, ObjectError> {
     let (c, rm) = num::integer::div_rem(indices, 3);
@@ -517,3 +935,263 @@ impl Hash for RawPoint {
         h.write(&self.y.to_le_bytes()[..]);
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nmf::{Error, NmfBufFull, NmfInfo, NmfType};
+    use crate::test_util::{self, ScratchDir};
+
+    // Objects are written back exactly as they were read, including door_1 with its
+    // padding after the indices (in the buffer, but not in the file)
+    #[test]
+    fn read_write_is_byte_identical() {
+        let bytes = test_util::nmf_bytes(&test_util::sample_nmf());
+
+        let nmf = NmfBufFull::from_bytes(&bytes).unwrap();
+        assert_eq!(test_util::nmf_bytes(&nmf), bytes);
+
+        let dir = ScratchDir::new("nmf-rw");
+        let path = dir.write("model.nmf", &bytes);
+        let nmf = NmfBufFull::from_path(&path).unwrap();
+        assert_eq!(test_util::nmf_bytes(&nmf), bytes);
+    }
+
+    #[test]
+    fn padding_after_indices_is_zeroed() {
+        let bytes = test_util::nmf_bytes(&test_util::sample_nmf());
+        let nmf = NmfBufFull::from_bytes(&bytes).unwrap();
+        let door = &nmf.objects[1];
+        let indices_bytes = door.indices_count * 2;
+        assert_eq!(door.vertices_start - indices_bytes, 2);
+        assert!(door.get_slice::<u8>(indices_bytes, door.vertices_start - indices_bytes).iter().all(|b| *b == 0));
+    }
+
+    // The typed slices need the data 4-aligned; a short read fails instead of leaving bytes unread
+    #[test]
+    fn object_data_is_aligned_and_short_reads_fail() {
+        let obj = test_util::object("tri", &[(0., 0., 0.), (1., 0., 0.), (0., 1., 0.)], &[(0, 1, 2)], &[(0, 1)]);
+        assert_eq!(obj.buf_ptr.align_offset(4), 0);
+
+        let mut bytes = Vec::new();
+        obj.write_bytes(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        match <ObjectFull as ObjectReader<_>>::from_reader(&mut Cursor::new(bytes), 0, u64::MAX) {
+            Err(ObjectError::FileIO(e)) => assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof),
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("truncated object was read"),
+        }
+    }
+
+    fn tri_parts<'a>(name: &'a str, vertices: &'a [RawVertex], normals: &'a [RawVertex], uv_map: &'a [RawPoint],
+                      faces: &'a [RawFace], submaterials: &'a [(u32, usize)]) -> ObjectParts<'a> {
+        ObjectParts { name, vertices, normals, uv_map, faces, submaterials }
+    }
+
+    // Two built objects survive write_to -> from_bytes -> write_to unchanged
+    #[test]
+    fn built_objects_round_trip() {
+        let nmf = test_util::sample_nmf();
+        let bytes = test_util::nmf_bytes(&nmf);
+
+        let read = NmfBufFull::from_bytes(&bytes).unwrap();
+        assert_eq!(read.objects.len(), 2);
+        assert_eq!(read.objects[0].name(), "main");
+        assert_eq!(read.objects[1].name(), "door_1");
+        assert_eq!(test_util::nmf_bytes(&read), bytes);
+    }
+
+    #[test]
+    fn build_rejects_bad_parts() {
+        let vertices = [RawVertex { x: 0., y: 0., z: 0. }, RawVertex { x: 1., y: 0., z: 0. }, RawVertex { x: 0., y: 0., z: 1. }];
+        let normals = vec![RawVertex { x: 0., y: 1., z: 0. }; 3];
+        let uv_map = vec![RawPoint { x: 0., y: 0. }; 3];
+        let faces = [RawFace { v1: 0, v2: 2, v3: 1 }];
+
+        let ok = tri_parts("tri", &vertices, &normals, &uv_map, &faces, &[(0, 1)]);
+        assert!(ObjectFull::build(&ok).is_ok());
+
+        let r = ObjectFull::build(&tri_parts("tri", &vertices, &normals[.. 2], &uv_map, &faces, &[(0, 1)]));
+        assert!(matches!(r, Err(ObjectError::CountMismatch("normals", 2, 3))));
+
+        let r = ObjectFull::build(&tri_parts("tri", &vertices, &normals, &uv_map[.. 1], &faces, &[(0, 1)]));
+        assert!(matches!(r, Err(ObjectError::CountMismatch("UV points", 1, 3))));
+
+        let r = ObjectFull::build(&tri_parts("tri", &vertices, &normals, &uv_map, &faces, &[(0, 2)]));
+        assert!(matches!(r, Err(ObjectError::CountMismatch("faces in submaterial runs", 2, 1))));
+
+        let long_name = "x".repeat(NameBuf::BUF_LENGTH + 1);
+        let r = ObjectFull::build(&tri_parts(&long_name, &vertices, &normals, &uv_map, &faces, &[(0, 1)]));
+        assert!(matches!(r, Err(ObjectError::NameTooLong(l)) if l == NameBuf::BUF_LENGTH + 1));
+
+        let r = ObjectFull::build(&tri_parts("", &vertices, &normals, &uv_map, &faces, &[(0, 1)]));
+        assert!(matches!(r, Err(ObjectError::EmptyName)));
+
+        // submaterial indices are checked against the names when the NMF is assembled
+        let obj = ObjectFull::build(&tri_parts("tri", &vertices, &normals, &uv_map, &faces, &[(2, 1)])).unwrap();
+        let r = NmfBufFull::new(NmfType::B3dmh10, &["mat_a", "mat_b"], vec![obj]);
+        assert!(matches!(r, Err(Error::Object(0, ObjectError::SubmaterialIdxTooBig(2)))));
+    }
+
+    // u16 face indices cannot address the last vertex of an over-limit object: reported, not a panic
+    #[test]
+    fn over_limit_object_is_rejected() {
+        let n = u16::MAX as usize;
+        let vertices = (0 .. n).map(|i| RawVertex { x: i as f32, y: 0., z: 0. }).collect::<Vec<_>>();
+        let normals = vec![RawVertex { x: 0., y: 1., z: 0. }; n];
+        let uv_map = vec![RawPoint { x: 0., y: 0. }; n];
+        let faces = [RawFace { v1: 0, v2: 1, v3: 2 }];
+
+        let r = ObjectFull::build(&tri_parts("big", &vertices, &normals, &uv_map, &faces, &[(0, 1)]));
+        match r {
+            Err(e @ ObjectError::TooManyVertices(65535)) => assert!(e.to_string().contains("Split the object"), "{}", e),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("over-limit object was built"),
+        }
+    }
+
+    #[test]
+    fn scale_detects_non_finite_vertices() {
+        let mut nmf = test_util::sample_nmf();
+        let main = &mut nmf.objects[0];
+        main.scale(2.0).unwrap();
+
+        // vertex 0 is at the origin and stays finite, vertex 1 (x = 2) overflows
+        assert!(matches!(main.scale(1e300), Err(ObjectError::NonFiniteVertex(1))));
+    }
+
+    // Unit cube centered at the origin, 4 own vertices per side (so that flat normals need no averaging),
+    // wound counter-clockwise when seen from outside
+    fn cube() -> ObjectFull {
+        let mut verts = Vec::with_capacity(24);
+        let mut faces = Vec::with_capacity(12);
+        for axis in 0 .. 3 {
+            for &sign in [1f32, -1f32].iter() {
+                // (u, v, n) is right-handed for the positive side; the negative side swaps u and v
+                let (mut u, mut v) = ((axis + 1) % 3, (axis + 2) % 3);
+                if sign < 0f32 {
+                    std::mem::swap(&mut u, &mut v);
+                }
+
+                let base = verts.len() as u16;
+                for &(du, dv) in [(-0.5f32, -0.5f32), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)].iter() {
+                    let mut p = [0f32; 3];
+                    p[axis] = sign * 0.5;
+                    p[u] = du;
+                    p[v] = dv;
+                    verts.push((p[0], p[1], p[2]));
+                }
+                faces.push((base, base + 1, base + 2));
+                faces.push((base, base + 2, base + 3));
+            }
+        }
+
+        test_util::object("cube", &verts, &faces, &[(0, faces.len())])
+    }
+
+    #[test]
+    fn flat_normals_of_cube_point_outward() {
+        let mut cube = cube();
+        assert_eq!(cube.recalc_normals(&NormalsMode::Flat), 0);
+
+        // vertices 4 * side .. 4 * side + 4 are on the side (axis, sign) = (side / 2, +1 or -1)
+        for (i, n) in cube.normals_1().iter().enumerate() {
+            let (axis, sign) = (i / 8, if i % 8 < 4 { 1f32 } else { -1f32 });
+            let mut outward = [0f32; 3];
+            outward[axis] = sign;
+            assert_eq!([n.x, n.y, n.z], outward, "vertex {}", i);
+        }
+    }
+
+    #[test]
+    fn set_name_changes_only_the_name_field() {
+        let bytes = |o: &ObjectFull| { let mut b = Vec::new(); o.write_bytes(&mut b).unwrap(); b };
+        let mut nmf = test_util::sample_nmf();
+        let door = &mut nmf.objects[1];
+        let before = bytes(door);
+
+        assert!(door.set_name("door_renamed"));
+        assert_eq!(door.name(), "door_renamed");
+        let after = bytes(door);
+        assert_eq!(after.len(), before.len());
+        assert_eq!(&after[.. 8], &before[.. 8]);
+        assert_eq!(&after[8 .. 20], b"door_renamed");
+        assert!(after[20 .. 8 + NameBuf::BUF_LENGTH].iter().all(|&b| b == 0));
+        assert_eq!(&after[8 + NameBuf::BUF_LENGTH ..], &before[8 + NameBuf::BUF_LENGTH ..]);
+
+        for bad in ["", "with\0nul", &"x".repeat(NameBuf::BUF_LENGTH + 1)] {
+            assert!(!door.set_name(bad));
+            assert_eq!(door.name(), "door_renamed");
+        }
+    }
+
+    #[test]
+    fn transform_uv_keeps_geometry() {
+        let mut nmf = test_util::sample_nmf();
+        let main = &mut nmf.objects[0];
+        let verts = main.vertices().iter().map(|v| (v.x, v.y, v.z)).collect::<Vec<_>>();
+        let normals = main.normals_1().iter().map(|n| (n.x, n.y, n.z)).collect::<Vec<_>>();
+
+        // UVs of 'main' are (x, z): (0 0) (1 0) (1 1) (0 1)
+        main.transform_uv(2., 0.5, 0.25, -1.);
+        let uvs = main.uv_map().iter().map(|p| (p.x, p.y)).collect::<Vec<_>>();
+        assert_eq!(uvs, vec![(0.25, -1.), (2.25, -1.), (2.25, -0.5), (0.25, -0.5)]);
+
+        assert_eq!(main.vertices().iter().map(|v| (v.x, v.y, v.z)).collect::<Vec<_>>(), verts);
+        assert_eq!(main.normals_1().iter().map(|n| (n.x, n.y, n.z)).collect::<Vec<_>>(), normals);
+    }
+
+    #[test]
+    fn tampered_size_field_is_rejected() {
+        let bytes = test_util::nmf_bytes(&test_util::sample_nmf());
+        let start = NmfInfo::from_bytes(&bytes).unwrap().objects[0].byte_range().start as usize;
+
+        // 'main' has 4 vertices: 4 * (4 * 12 + 8) bytes of vertex data
+        for field in [4, 232] {
+            let mut tampered = bytes.clone();
+            tampered[start + field .. start + field + 4].copy_from_slice(&10u32.to_le_bytes());
+            match NmfBufFull::from_bytes(&tampered) {
+                Err(Error::Object(0, ObjectError::SizeMismatch(10, 224))) => (),
+                Err(e) => panic!("field {}: unexpected error {}", field, e),
+                Ok(_) => panic!("field {}: tampered object was read", field),
+            }
+        }
+    }
+
+    // Right triangle with legs 1 and sqrt(2), then a degenerate face on a repeated vertex
+    #[test]
+    fn face_areas_match_hand_computed_values() {
+        let tri = test_util::object("tri", &[(0., 0., 0.), (1., 0., 0.), (0., 0., 2f32.sqrt())], &[(0, 2, 1), (0, 1, 1)], &[(0, 2)]);
+        let areas = tri.face_areas();
+        assert!((areas[0] - 2f32.sqrt() / 2.).abs() < 1e-6, "{:?}", areas);
+        assert_eq!(areas[1], 0.);
+
+        let nmf = test_util::sample_nmf();
+        assert_eq!(nmf.objects[0].face_areas(), vec![0.5, 0.5]);
+
+        let total: f32 = areas.iter().chain(nmf.objects[0].face_areas().iter()).sum();
+        assert!((total - 1.7071).abs() < 1e-4, "{}", total);
+
+        let cube_area: f32 = cube().face_areas().iter().sum();
+        assert!((cube_area - 6.).abs() < 1e-5, "{}", cube_area);
+    }
+
+    // Header integers and the object bbox are little-endian and may sit at any byte offset
+    #[test]
+    fn little_endian_fields_decode_from_unaligned_bytes() {
+        let bytes = [0xaa, 0x78, 0x56, 0x34, 0x12, 0xbb];
+        assert_eq!(read_u32(&bytes[1 ..]).unwrap(), 0x1234_5678);
+        assert!(read_u32(&bytes[3 ..]).is_err());
+
+        let mut door = test_util::sample_nmf().objects.remove(1);
+        let bbox = door.bbox();
+        assert!(bbox == RawBBox { v_min: RawVertex { x: 0., y: 0., z: 0. }, v_max: RawVertex { x: 1., y: 1., z: 0. } });
+
+        // 1.0 is 0x3f80_0000 and -2.5 is 0xc020_0000
+        door.set_bbox(&RawBBox { v_min: RawVertex { x: 1., y: 0., z: -2.5 }, v_max: RawVertex { x: 1., y: 1., z: 0. } });
+        assert_eq!(&door.head_buf[204 .. 216], &[0, 0, 0x80, 0x3f, 0, 0, 0, 0, 0, 0, 0x20, 0xc0]);
+        door.head_buf[208 .. 212].copy_from_slice(&[0, 0, 0x20, 0xc0]);
+        assert_eq!(door.bbox().v_min.y, -2.5);
+    }
+}
@@ -0,0 +1,109 @@
+// Fixtures shared by the unit tests, also compiled into the command line tests (tests/common),
+// so only the public API is used and ini/nmf come from the parent module
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use normpath::BasePathBuf;
+
+use super::ini::{self, GameRoots};
+use super::nmf::{NmfBufFull, NmfType, ObjectFull, ObjectParts};
+use super::nmf::object_full::{RawVertex, RawPoint, RawFace};
+
+
+// Directory under the system temp dir, removed with everything in it on drop
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    pub fn new(tag: &str) -> ScratchDir {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("wrsr-mt-{}-{}-{}", tag, std::process::id(), n));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        ScratchDir(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    // Writes a file at a path relative to the dir, creating parent dirs
+    pub fn write<C: AsRef<[u8]>>(&self, rel_path: &str, content: C) -> PathBuf {
+        let path = self.0.join(rel_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    pub fn read(&self, rel_path: &str) -> Vec<u8> {
+        fs::read(self.0.join(rel_path)).unwrap_or_else(|e| panic!("Cannot read {}: {}", rel_path, e))
+    }
+
+    pub fn read_string(&self, rel_path: &str) -> String {
+        String::from_utf8(self.read(rel_path)).unwrap()
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+
+// Object with +Y normals and UVs taken from x and z. sms: (submaterial index, faces count) runs
+pub fn object(name: &str, verts: &[(f32, f32, f32)], faces: &[(u16, u16, u16)], sms: &[(u32, usize)]) -> ObjectFull {
+    let vertices = verts.iter().map(|&(x, y, z)| RawVertex { x, y, z }).collect::<Vec<_>>();
+    let normals = vec![RawVertex { x: 0f32, y: 1f32, z: 0f32 }; verts.len()];
+    let uv_map = verts.iter().map(|&(x, _, z)| RawPoint { x, y: z }).collect::<Vec<_>>();
+    let faces = faces.iter().map(|&(v1, v2, v3)| RawFace { v1, v2, v3 }).collect::<Vec<_>>();
+
+    ObjectFull::build(&ObjectParts { name, vertices: &vertices, normals: &normals, uv_map: &uv_map, faces: &faces, submaterials: sms })
+        .unwrap()
+}
+
+// Submaterials mat_a, mat_b. Objects:
+//   main:   a 1x1 quad at y = 0, one face per submaterial
+//   door_1: a single triangle on mat_b (odd index count, so its buffer has padding after the indices)
+pub fn sample_nmf() -> NmfBufFull {
+    let main = object("main", &[(0., 0., 0.), (1., 0., 0.), (1., 0., 1.), (0., 0., 1.)], &[(0, 2, 1), (0, 3, 2)], &[(0, 1), (1, 1)]);
+    let door = object("door_1", &[(0., 0., 0.), (1., 0., 0.), (0., 1., 0.)], &[(0, 1, 2)], &[(1, 1)]);
+    NmfBufFull::new(NmfType::B3dmh10, &["mat_a", "mat_b"], vec![main, door]).unwrap()
+}
+
+pub fn nmf_bytes(nmf: &NmfBufFull) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    nmf.write_to(Cursor::new(&mut bytes)).unwrap();
+    bytes
+}
+
+
+// Empty stock and workshop dirs inside dir
+pub fn game_roots(dir: &ScratchDir) -> GameRoots {
+    let root = |name: &str| {
+        let path = dir.path().join(name);
+        fs::create_dir_all(&path).unwrap();
+        BasePathBuf::new(path).unwrap()
+    };
+
+    GameRoots { stock: root("stock"), workshop: root("workshop") }
+}
+
+pub const MTL: &str = "$SUBMATERIAL mat_a\r\n$TEXTURE_MTL 0 tex.dds\r\n$SUBMATERIAL mat_b\r\n$TEXTURE_MTL 0 tex.dds\r\n";
+
+// Valid building source at dir/rel_path: building.ini stub named name, renderconfig.source,
+// sample_nmf as the model and an mtl with both of its submaterials and one DDS texture
+pub fn write_building(dir: &ScratchDir, rel_path: &str, name: &str) -> PathBuf {
+    let mut bld_ini = Vec::new();
+    ini::building::write_stub(&mut bld_ini, name).unwrap();
+    dir.write(&format!("{}/building.ini", rel_path), bld_ini);
+    dir.write(&format!("{}/renderconfig.source", rel_path), "MODEL model.nmf\r\nMATERIAL model.mtl\r\n");
+    dir.write(&format!("{}/model.nmf", rel_path), nmf_bytes(&sample_nmf()));
+    dir.write(&format!("{}/model.mtl", rel_path), MTL);
+    dir.write(&format!("{}/tex.dds", rel_path), format!("DDS texture of {}", name));
+    dir.path().join(rel_path)
+}
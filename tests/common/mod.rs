@@ -0,0 +1,48 @@
+// Fixtures shared by the command line tests
+#![allow(dead_code, unused_imports)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use wrsr_mt::{ini, nmf};
+
+#[path = "../../src/test_util.rs"]
+mod test_util;
+
+pub use test_util::{ScratchDir, object, sample_nmf, nmf_bytes, write_building, MTL};
+
+
+// Runs wrsr-mt in dir (relative arguments resolve against it), with empty 'stock' and 'workshop' dirs in it as game roots
+pub fn run(dir: &ScratchDir, args: &[&str]) -> Output {
+    test_util::game_roots(dir);
+    Command::new(env!("CARGO_BIN_EXE_wrsr-mt"))
+        .current_dir(dir.path())
+        .args(["--stock", "stock", "--workshop", "workshop"])
+        .args(args)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap()
+}
+
+pub fn stdout(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+pub fn stderr(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stderr).into_owned()
+}
+
+// Panics with the output of a failed run
+pub fn assert_success(out: &Output) {
+    assert!(out.status.success(), "exit status {}\nstdout:\n{}\nstderr:\n{}", out.status, stdout(out), stderr(out));
+}
+
+
+// Installed building (with renderconfig.ini), as used by the mod-building commands.
+// write_building makes a modpack source (with renderconfig.source).
+pub fn write_mod_building(dir: &ScratchDir, rel_path: &str, name: &str) -> PathBuf {
+    let path = test_util::write_building(dir, rel_path, name);
+    fs::rename(path.join("renderconfig.source"), path.join("renderconfig.ini")).unwrap();
+    path
+}
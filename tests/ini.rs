@@ -0,0 +1,133 @@
+// ini commands
+mod common;
+
+use common::ScratchDir;
+
+
+#[test]
+fn scale_rejects_zero_factor() {
+    let dir = ScratchDir::new("scale-zero");
+    dir.write("a.ini", "$HELIPORT_AREA 5\r\nend\r\n");
+
+    for factor in ["0", "-1"] {
+        let out = common::run(&dir, &["ini", "scale", "building", "--", "a.ini", factor, "b.ini"]);
+        assert!(!out.status.success());
+        assert!(common::stderr(&out).contains("Scale factor must be greater than zero"), "{}", common::stderr(&out));
+        assert!(!dir.path().join("b.ini").exists());
+    }
+}
+
+#[test]
+fn scale_warns_about_extreme_factor() {
+    let dir = ScratchDir::new("scale-extreme");
+    dir.write("a.ini", "$HELIPORT_AREA 5\r\nend\r\n");
+
+    let out = common::run(&dir, &["ini", "scale", "building", "a.ini", "1000", "b.ini"]);
+    common::assert_success(&out);
+    assert!(common::stderr(&out).contains("WARNING: extreme scale factor 1000"), "{}", common::stderr(&out));
+}
+
+#[test]
+fn list_types_prints_known_keywords() {
+    let dir = ScratchDir::new("list-types");
+    let out = common::run(&dir, &["ini", "list-types"]);
+    common::assert_success(&out);
+
+    let words = common::stdout(&out).lines().map(str::trim).map(String::from).collect::<Vec<_>>();
+    assert!(words.iter().any(|w| w == "CONSTRUCTION_OFFICE_RAIL"), "{:?}", words);
+    assert!(words.iter().any(|w| w == "nuclearfuel"), "{:?}", words);
+}
+
+#[test]
+fn parse_summary_counts_tokens_by_category() {
+    let dir = ScratchDir::new("parse-summary");
+    dir.write("building.ini", "$NAME_STR \"b1\"\r\n\
+                               $WORKERS_NEEDED 10\r\n\
+                               $STORAGE RESOURCE_TRANSPORT_CONCRETE 100\r\n\
+                               $STORAGE RESOURCE_TRANSPORT_GRAVEL 100\r\n\
+                               $CONNECTION_ROAD_DEAD 0 0 0\r\n\
+                               $COST_WORK SOVIET_CONSTRUCTION_GROUNDWORKS 10\r\n\
+                               $WORKERS_NEEDED ten\r\n\
+                               end\r\n");
+
+    let out = common::run(&dir, &["ini", "parse", "building", "building.ini", "--summary"]);
+    common::assert_success(&out);
+    let lines = common::stdout(&out).lines().map(|l| l.split_whitespace().collect::<Vec<_>>().join(" ")).collect::<Vec<_>>();
+    assert_eq!(lines, ["Tokens: 7, errors: 1", "connection 1", "cost 1", "general 1", "storage 2", "workers 1"]);
+}
+
+#[test]
+fn parse_filter_prints_only_category_tokens() {
+    let dir = ScratchDir::new("parse-filter");
+    dir.write("building.ini", "$WORKERS_NEEDED 10\r\n\
+                               $STORAGE RESOURCE_TRANSPORT_CONCRETE 100\r\n\
+                               $CONNECTION_ROAD_DEAD 0 0 0\r\n\
+                               $STORAGE_IMPORT RESOURCE_TRANSPORT_GRAVEL 50\r\n\
+                               end\r\n");
+
+    let out = common::run(&dir, &["ini", "parse", "building", "building.ini", "--filter", "storage"]);
+    common::assert_success(&out);
+    let stdout = common::stdout(&out);
+    assert!(stdout.contains("STORAGE RESOURCE_TRANSPORT_CONCRETE 100"), "{}", stdout);
+    assert!(stdout.contains("STORAGE_IMPORT RESOURCE_TRANSPORT_GRAVEL 50"), "{}", stdout);
+    assert!(!stdout.contains("WORKERS_NEEDED"), "{}", stdout);
+    assert!(!stdout.contains("CONNECTION_ROAD_DEAD"), "{}", stdout);
+}
+
+#[test]
+fn scale_processes_several_pairs_in_one_call() {
+    let dir = ScratchDir::new("scale-pairs");
+    for i in 1 ..= 3 {
+        dir.write(&format!("in{}.ini", i), format!("$HELIPORT_AREA {}\r\nend\r\n", i));
+    }
+
+    let out = common::run(&dir, &["ini", "scale", "building", "in1.ini", "2", "out1.ini", "in2.ini", "out2.ini", "in3.ini", "out3.ini"]);
+    common::assert_success(&out);
+    for i in 1 ..= 3 {
+        assert_eq!(dir.read_string(&format!("out{}.ini", i)), format!("$HELIPORT_AREA {}\r\nend\r\n", i * 2));
+    }
+
+    let out = common::run(&dir, &["ini", "scale", "building", "in1.ini", "2", "odd1.ini", "in2.ini"]);
+    assert!(!out.status.success());
+    assert!(!dir.path().join("odd1.ini").exists());
+}
+
+#[test]
+fn get_models_prints_resolved_paths() {
+    let dir = ScratchDir::new("get-models");
+    common::write_mod_building(&dir, "in", "b1");
+    dir.write("in/renderconfig.ini", "MODEL model.nmf\r\nMODEL_LOD lods/lod.nmf 100\r\nMATERIAL model.mtl\r\n");
+
+    let out = common::run(&dir, &["ini", "get-models", "in/renderconfig.ini"]);
+    common::assert_success(&out);
+    let expected = format!("model {}\nmodel_lod {}\nmaterial {}\n",
+                           dir.path().join("in/model.nmf").display(),
+                           dir.path().join("in/lods/lod.nmf").display(),
+                           dir.path().join("in/model.mtl").display());
+    assert_eq!(common::stdout(&out), expected);
+}
+
+#[test]
+fn parse_mtl_resolve_prints_texture_paths() {
+    let dir = ScratchDir::new("mtl-resolve");
+    dir.write("stock/textures/stock.dds", "DDS stock");
+    dir.write("workshop/1234567890/ws.dds", "DDS workshop");
+    dir.write("m/local.dds", "DDS local");
+    dir.write("m/model.mtl", "$SUBMATERIAL a\r\n\
+                              $TEXTURE_MTL 0 ~textures/stock.dds\r\n\
+                              $TEXTURE_MTL 1 #1234567890/ws.dds\r\n\
+                              $TEXTURE_MTL 2 local.dds\r\n\
+                              $TEXTURE_MTL 3 missing.dds\r\n\
+                              $TEXTURE 4 textures/stock.dds\r\n");
+
+    let out = common::run(&dir, &["ini", "parse", "mtl", "m/model.mtl", "--resolve"]);
+    common::assert_success(&out);
+    let stdout = common::stdout(&out);
+    let textures = stdout.lines().skip_while(|l| *l != "Textures:").skip(1).collect::<Vec<_>>();
+    let expected = [("OK", "stock/textures/stock.dds"), ("OK", "workshop/1234567890/ws.dds"), ("OK", "m/local.dds"), ("MISSING", "m/missing.dds"),
+                    ("OK", "stock/textures/stock.dds")]
+        .iter()
+        .map(|(status, rel)| format!("{:<8} {}", status, dir.path().join(rel).display()))
+        .collect::<Vec<_>>();
+    assert_eq!(textures, expected, "{}", stdout);
+}
@@ -0,0 +1,94 @@
+// modpack commands
+mod common;
+
+use common::ScratchDir;
+
+
+#[test]
+fn install_prints_timings() {
+    let dir = ScratchDir::new("install-timings");
+    common::write_building(&dir, "src/b1", "b1");
+    std::fs::create_dir_all(dir.path().join("dst")).unwrap();
+
+    let out = common::run(&dir, &["modpack", "install", "src", "dst", "--timings"]);
+    common::assert_success(&out);
+    let stdout = common::stdout(&out);
+
+    let timings = stdout.lines().skip_while(|l| *l != "Timings:").skip(1).collect::<Vec<_>>();
+    let phases = timings.iter().map(|l| l.split_whitespace().next().unwrap()).collect::<Vec<_>>();
+    assert_eq!(phases, ["validation", "models", "textures", "ini", "total"], "{}", stdout);
+    for l in timings {
+        let secs = l.trim_end_matches('s').rsplit(' ').next().unwrap().parse::<f64>().unwrap();
+        assert!(secs >= 0., "{}", l);
+    }
+}
+
+// ScratchDir::run passes '--stock stock --workshop workshop', relative to the run dir
+#[test]
+fn relative_game_dirs_resolve_against_run_dir() {
+    let dir = ScratchDir::new("relative-dirs");
+    common::write_building(&dir, "src/b1", "b1");
+
+    let out = common::run(&dir, &["modpack", "validate", "src"]);
+    common::assert_success(&out);
+    let stdout = common::stdout(&out);
+    assert!(stdout.contains(&format!("Stock game files:   {}\n", dir.path().join("stock").display())), "{}", stdout);
+    assert!(stdout.contains(&format!("Workshop directory: {}\n", dir.path().join("workshop").display())), "{}", stdout);
+}
+
+#[test]
+fn validate_report_has_an_entry_per_building() {
+    let dir = ScratchDir::new("validate-report");
+    common::write_building(&dir, "src/good", "good");
+    common::write_building(&dir, "src/bad", "bad");
+    dir.write("src/bad/renderconfig.source", "MODEL missing.nmf\r\nMATERIAL model.mtl\r\n");
+
+    common::assert_success(&common::run(&dir, &["modpack", "validate", "src", "--report", "report.json"]));
+    let report = dir.read_string("report.json");
+    let entry = |path: &str| {
+        let lines = report.lines().filter(|l| l.contains(&format!("{{ \"path\": \"{}\",", path))).collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1, "{}", report);
+        lines[0].to_string()
+    };
+
+    assert!(report.contains("\"errors\": 1,"), "{}", report);
+    assert!(entry("good").contains("\"status\": \"ok\", \"errors\": [],"), "{}", report);
+    let bad = entry("bad");
+    assert!(bad.contains("\"status\": \"error\"") && bad.contains("No such file"), "{}", report);
+    assert_eq!(report.lines().filter(|l| l.contains("\"status\":")).count(), 2);
+}
+
+#[test]
+fn validate_accepts_a_lone_building_dir() {
+    let dir = ScratchDir::new("validate-lone");
+    common::write_building(&dir, "b1", "b1");
+
+    let out = common::run(&dir, &["modpack", "validate", "b1", "--report", "report.json"]);
+    common::assert_success(&out);
+    let stdout = common::stdout(&out);
+    assert!(stdout.lines().any(|l| l == "OK: b1"), "{}", stdout);
+    assert!(stdout.contains("OK: found 1 buildings"), "{}", stdout);
+    assert!(dir.read_string("report.json").contains("{ \"path\": \"b1\", \"status\": \"ok\""));
+}
+
+#[test]
+fn validate_summary_only_prints_totals_and_errors() {
+    let dir = ScratchDir::new("validate-summary");
+    // the same building name twice gives a warning on each
+    common::write_building(&dir, "src/a", "same");
+    common::write_building(&dir, "src/b", "same");
+    common::write_building(&dir, "src/bad", "bad");
+    dir.write("src/bad/renderconfig.source", "MODEL missing.nmf\r\nMATERIAL model.mtl\r\n");
+
+    let out = common::run(&dir, &["modpack", "validate", "src"]);
+    let full = common::stdout(&out) + &common::stderr(&out);
+    assert!(full.contains("OK: a\n") && full.contains("WARNING"), "{}", full);
+
+    let out = common::run(&dir, &["modpack", "validate", "src", "--summary-only"]);
+    common::assert_success(&out);
+    let (stdout, stderr) = (common::stdout(&out), common::stderr(&out));
+    assert!(!stdout.contains("OK: a") && !stdout.contains("OK: b"), "{}", stdout);
+    assert!(!stdout.contains("WARNING") && !stderr.contains("WARNING"), "{}\n{}", stdout, stderr);
+    assert!(stderr.contains("ERROR: bad: ") && stderr.contains("FAILED: encountered 1 errors"), "{}", stderr);
+    assert!(stdout.contains("2 warnings (not shown, see --report)"), "{}", stdout);
+}
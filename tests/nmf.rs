@@ -0,0 +1,258 @@
+// nmf commands
+mod common;
+
+use common::ScratchDir;
+use wrsr_mt::ini;
+use wrsr_mt::nmf::{NmfBufFull, NmfInfo, NmfType};
+
+
+#[test]
+fn show_warns_about_empty_object() {
+    let dir = ScratchDir::new("nmf-show-empty");
+    let mut nmf = common::sample_nmf();
+    nmf.objects.push(common::object("empty", &[], &[], &[(0, 0)]));
+    dir.write("model.nmf", common::nmf_bytes(&nmf));
+
+    let out = common::run(&dir, &["nmf", "show", "model.nmf"]);
+    common::assert_success(&out);
+    let stdout = common::stdout(&out);
+    assert!(stdout.contains("WARNING: object 'empty' has no geometry"), "{}", stdout);
+    assert!(!stdout.contains("'main' has no geometry"));
+}
+
+#[test]
+fn extract_object_writes_only_that_object() {
+    let dir = ScratchDir::new("nmf-extract");
+    dir.write("model.nmf", common::nmf_bytes(&common::sample_nmf()));
+
+    common::assert_success(&common::run(&dir, &["nmf", "extract-object", "model.nmf", "door_1", "door.obj"]));
+    let obj = dir.read_string("door.obj");
+    assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 3);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 1);
+    assert!(!obj.contains("main"));
+
+    let out = common::run(&dir, &["nmf", "extract-object", "model.nmf", "missing", "missing.obj"]);
+    assert!(!out.status.success());
+    assert!(common::stderr(&out).contains("    main"));
+}
+
+// Largest x coordinate over all vertices of the nmf
+fn max_x(bytes: &[u8]) -> f32 {
+    let nmf = wrsr_mt::nmf::NmfBufFull::from_bytes(bytes).unwrap();
+    nmf.objects.iter().flat_map(|o| o.vertices().iter().map(|v| v.x)).fold(f32::MIN, f32::max)
+}
+
+#[test]
+fn transform_building_scales_every_model() {
+    let dir = ScratchDir::new("nmf-transform-building");
+    common::write_mod_building(&dir, "in", "b1");
+    dir.write("in/lod.nmf", common::nmf_bytes(&common::sample_nmf()));
+    dir.write("in/renderconfig.ini", "MODEL model.nmf\r\nMODEL_LOD lod.nmf 100\r\nMATERIAL model.mtl\r\n");
+
+    common::assert_success(&common::run(&dir, &["nmf", "transform-building", "in/renderconfig.ini", "2", "out"]));
+    for model in ["model.nmf", "lod.nmf"] {
+        assert_eq!(max_x(&dir.read(&format!("in/{}", model))), 1.);
+        assert_eq!(max_x(&dir.read(&format!("out/{}", model))), 2.);
+    }
+    assert!(!dir.path().join("out/building.ini").exists());
+}
+
+#[test]
+fn export_stl_writes_every_triangle() {
+    let dir = ScratchDir::new("nmf-export-stl");
+    dir.write("model.nmf", common::nmf_bytes(&common::sample_nmf()));
+
+    common::assert_success(&common::run(&dir, &["nmf", "export", "model.nmf", "model.stl", "--format", "stl"]));
+    let stl = dir.read("model.stl");
+    let triangles = u32::from_le_bytes([stl[80], stl[81], stl[82], stl[83]]);
+    assert_eq!(triangles, 3);
+    assert_eq!(stl.len(), 84 + 50 * 3);
+
+    // first triangle of 'main': (0 0 0) (1 0 1) (1 0 0), facing +Y
+    let f32_at = |i: usize| f32::from_le_bytes([stl[i], stl[i + 1], stl[i + 2], stl[i + 3]]);
+    assert_eq!((0 .. 3).map(|i| f32_at(84 + i * 4)).collect::<Vec<_>>(), vec![0., 1., 0.]);
+}
+
+#[test]
+fn classify_ranks_higher_vertex_count_as_main_model() {
+    let dir = ScratchDir::new("nmf-classify");
+    let mut lod = common::sample_nmf();
+    lod.objects.remove(0);
+    dir.write("lod.nmf", common::nmf_bytes(&lod));
+    dir.write("model.nmf", common::nmf_bytes(&common::sample_nmf()));
+
+    let out = common::run(&dir, &["nmf", "classify", "lod.nmf", "model.nmf"]);
+    common::assert_success(&out);
+    let stdout = common::stdout(&out);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert!(lines[0].contains("MODEL ") && lines[0].contains(" 7 vertices") && lines[0].ends_with("model.nmf"), "{}", stdout);
+    assert!(lines[1].contains("MODEL_LOD ") && lines[1].contains(" 3 vertices") && lines[1].ends_with("lod.nmf"), "{}", stdout);
+}
+
+#[test]
+fn dump_object_copies_its_byte_range() {
+    let dir = ScratchDir::new("nmf-dump");
+    let bytes = common::nmf_bytes(&common::sample_nmf());
+    dir.write("model.nmf", &bytes);
+
+    let info = wrsr_mt::nmf::NmfInfo::from_bytes(&bytes).unwrap();
+    let range = info.objects[1].byte_range();
+
+    let out = common::run(&dir, &["nmf", "dump-object", "model.nmf", "door_1", "door.bin"]);
+    common::assert_success(&out);
+    let dumped = dir.read("door.bin");
+    assert_eq!(dumped.len() as u64, range.end - range.start);
+    assert_eq!(dumped, &bytes[range.start as usize .. range.end as usize]);
+    assert!(common::stdout(&out).contains(&format!("Done: {} bytes", dumped.len())));
+}
+
+#[test]
+fn to_obj_groups_faces_by_submaterial() {
+    let dir = ScratchDir::new("nmf-obj-groups");
+    dir.write("model.nmf", common::nmf_bytes(&common::sample_nmf()));
+
+    common::assert_success(&common::run(&dir, &["nmf", "to-obj", "model.nmf", "grouped.obj", "--group-by-submaterial"]));
+    let obj = dir.read_string("grouped.obj");
+    let usemtl = obj.lines().filter(|l| l.starts_with("usemtl ")).collect::<Vec<_>>();
+    assert_eq!(usemtl, ["usemtl mat_a", "usemtl mat_b", "usemtl mat_b"]);
+    assert!(obj.contains("g main_mat_a\n") && obj.contains("g main_mat_b\n") && obj.contains("g door_1_mat_b\n"), "{}", obj);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 3);
+
+    common::assert_success(&common::run(&dir, &["nmf", "to-obj", "model.nmf", "plain.obj"]));
+    assert!(!dir.read_string("plain.obj").contains("usemtl"));
+}
+
+#[test]
+fn gen_mtl_writes_a_submaterial_per_nmf_submaterial() {
+    let dir = ScratchDir::new("nmf-gen-mtl");
+    let quad = common::object("main", &[(0., 0., 0.), (1., 0., 0.), (1., 0., 1.), (0., 0., 1.)], &[(0, 2, 1), (0, 3, 2)], &[(0, 1), (2, 1)]);
+    let nmf = NmfBufFull::new(NmfType::B3dmh10, &["glass", "unused", "wall"], vec![quad]).unwrap();
+    dir.write("model.nmf", common::nmf_bytes(&nmf));
+
+    common::assert_success(&common::run(&dir, &["nmf", "gen-mtl", "model.nmf", "all.mtl"]));
+    let all = dir.read_string("all.mtl");
+    assert_eq!(ini::parse_mtl(&all).unwrap().submaterial_names(), vec!["glass", "unused", "wall"]);
+
+    common::assert_success(&common::run(&dir, &["nmf", "gen-mtl", "model.nmf", "used.mtl", "--used-only"]));
+    let used = dir.read_string("used.mtl");
+    assert_eq!(ini::parse_mtl(&used).unwrap().submaterial_names(), vec!["glass", "wall"]);
+}
+
+#[test]
+fn mirror_validate_only_reports_zero_drift() {
+    let dir = ScratchDir::new("nmf-mirror-check");
+    dir.write("model.nmf", common::nmf_bytes(&common::sample_nmf()));
+
+    let out = common::run(&dir, &["nmf", "mirror", "model.nmf", "--validate-only"]);
+    common::assert_success(&out);
+    assert!(common::stdout(&out).contains("Max coordinate drift: 0 (exactly reversible)"), "{}", common::stdout(&out));
+
+    let out = common::run(&dir, &["nmf", "mirror", "model.nmf", "out.nmf", "--validate-only"]);
+    assert!(!out.status.success());
+    assert!(!dir.path().join("out.nmf").exists());
+}
+
+// No legacy layout to retry with: show reports the bytes the parser left over
+#[test]
+fn show_reports_leftover_bytes() {
+    let dir = ScratchDir::new("nmf-show-leftover");
+    let mut model = common::nmf_bytes(&common::sample_nmf());
+    model.extend_from_slice(b"extra");
+    let len = model.len() as u32;
+    model[16 .. 20].copy_from_slice(&len.to_le_bytes());
+    dir.write("model.nmf", model);
+
+    let out = common::run(&dir, &["nmf", "show", "model.nmf"]);
+    common::assert_success(&out);
+    let stdout = common::stdout(&out);
+    assert!(stdout.contains("WARNING: Nmf parsed with leftover bytes (5)"), "{}", stdout);
+    assert!(stdout.contains("door_1"), "{}", stdout);
+}
+
+#[test]
+fn show_matrix_decodes_header_floats() {
+    let dir = ScratchDir::new("nmf-show-matrix");
+    let mut bytes = common::nmf_bytes(&common::sample_nmf());
+    // identity with a translation in the last row, then a marker word
+    let door = NmfInfo::from_bytes(&bytes).unwrap().objects[1].byte_range().start as usize + 72;
+    let matrix = [1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 2.5, -1., 3., 1f32];
+    for (i, x) in matrix.iter().enumerate() {
+        bytes[door + i * 4 .. door + i * 4 + 4].copy_from_slice(&x.to_le_bytes());
+    }
+    bytes[door + 64 .. door + 68].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+    dir.write("model.nmf", bytes);
+
+    let out = common::run(&dir, &["nmf", "show", "model.nmf", "--matrix"]);
+    common::assert_success(&out);
+    let stdout = common::stdout(&out);
+    let rows = stdout.lines().skip_while(|l| *l != "door_1:").skip(1).take(5).collect::<Vec<_>>();
+    let floats = rows[.. 4].iter()
+        .flat_map(|r| r.split_whitespace().map(|x| x.parse::<f32>().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(floats, matrix);
+    assert!(rows[4].trim_start().starts_with("deadbeef 00000000"), "{}", stdout);
+
+    let main = stdout.lines().skip_while(|l| *l != "main:").nth(1).unwrap();
+    assert!(main.split_whitespace().all(|x| x.parse::<f32>().unwrap() == 0.), "{}", main);
+}
+
+// Face lines with every v/vt/vn index replaced by the line it points to
+fn resolved_faces(obj: &str) -> Vec<String> {
+    let mut lists = [Vec::new(), Vec::new(), Vec::new()];
+    let mut faces = Vec::new();
+    for l in obj.lines() {
+        let mut words = l.split_whitespace();
+        match words.next() {
+            Some("v")  => lists[0].push(l),
+            Some("vt") => lists[1].push(l),
+            Some("vn") => lists[2].push(l),
+            Some("f")  => faces.push(words.map(|corner| {
+                corner.split('/').zip(lists.iter()).map(|(i, list)| {
+                    let i = i.parse::<isize>().unwrap();
+                    let idx = if i < 0 { list.len() as isize + i } else { i - 1 };
+                    list[idx as usize]
+                }).collect::<Vec<_>>().join(" | ")
+            }).collect::<Vec<_>>().join(" || ")),
+            _ => (),
+        }
+    }
+    faces
+}
+
+#[test]
+fn to_obj_relative_to_object_resolves_to_same_faces() {
+    let dir = ScratchDir::new("nmf-obj-relative");
+    dir.write("model.nmf", common::nmf_bytes(&common::sample_nmf()));
+
+    common::assert_success(&common::run(&dir, &["nmf", "to-obj", "model.nmf", "file.obj"]));
+    common::assert_success(&common::run(&dir, &["nmf", "to-obj", "model.nmf", "object.obj", "--relative-to", "object"]));
+    let (file, object) = (dir.read_string("file.obj"), dir.read_string("object.obj"));
+
+    let face_lines = |obj: &str| obj.lines().filter(|l| l.starts_with("f ")).map(String::from).collect::<Vec<_>>();
+    assert_eq!(face_lines(&file), ["f 1/1/1  3/3/1  2/2/1", "f 1/1/1  4/4/1  3/3/1", "f 5/5/2  6/6/2  7/5/2"]);
+    assert_eq!(face_lines(&object), ["f -4/-4/-1  -2/-2/-1  -3/-3/-1", "f -4/-4/-1  -1/-1/-1  -2/-2/-1", "f -3/-2/-1  -2/-1/-1  -1/-2/-1"]);
+    assert_eq!(resolved_faces(&object), resolved_faces(&file));
+}
+
+#[test]
+fn verify_length_reports_the_delta() {
+    let dir = ScratchDir::new("nmf-verify-length");
+    let bytes = common::nmf_bytes(&common::sample_nmf());
+    dir.write("ok.nmf", &bytes);
+    let mut longer = bytes.clone();
+    longer.extend_from_slice(&[0; 6]);
+    dir.write("longer.nmf", longer);
+    dir.write("shorter.nmf", &bytes[.. bytes.len() - 10]);
+
+    let out = common::run(&dir, &["nmf", "verify-length", "ok.nmf"]);
+    common::assert_success(&out);
+    assert!(common::stdout(&out).ends_with("OK\n"), "{}", common::stdout(&out));
+
+    for (file, line) in [("longer.nmf", "MISMATCH: file is longer by 6 bytes"), ("shorter.nmf", "MISMATCH: file is shorter by 10 bytes")] {
+        let out = common::run(&dir, &["nmf", "verify-length", file]);
+        assert_eq!(out.status.code(), Some(1));
+        let stdout = common::stdout(&out);
+        assert!(stdout.contains(&format!("Header length: {}\n", bytes.len())) && stdout.contains(line), "{}", stdout);
+    }
+}